@@ -0,0 +1,534 @@
+//! the conversions between the content of a file in the working tree and its blob:
+//! the `clean` filter and CRLF to LF on the way in, LF to CRLF and the `smudge` filter
+//! on the way out
+//!
+//! which line endings are converted follows git: the `text`(or the older `crlf`) and
+//! `eol` attributes first, then `core.autocrlf`, then `core.eol`
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+use crate::{
+    config::{parse_bool, Config},
+    GitError,
+};
+
+use super::{Attributes, State};
+
+/// git only looks this far for a NUL to call content binary
+const FIRST_FEW_BYTES: usize = 8000;
+
+/// the content looks binary, like `diff` and `merge` decide it
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(FIRST_FEW_BYTES)].contains(&0)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Stats {
+    nul: usize,
+    lone_cr: usize,
+    lone_lf: usize,
+    crlf: usize,
+    printable: usize,
+    nonprintable: usize,
+}
+
+impl Stats {
+    fn gather(content: &[u8]) -> Self {
+        let mut stats = Self::default();
+        let mut i = 0;
+        while i < content.len() {
+            match content[i] {
+                b'\r' if content.get(i + 1) == Some(&b'\n') => {
+                    stats.crlf += 1;
+                    i += 1;
+                }
+                b'\r' => stats.lone_cr += 1,
+                b'\n' => stats.lone_lf += 1,
+                127 => stats.nonprintable += 1,
+                // BS, HT, ESC and FF
+                b'\x08' | b'\t' | b'\x1b' | b'\x0c' => stats.printable += 1,
+                0 => {
+                    stats.nul += 1;
+                    stats.nonprintable += 1;
+                }
+                c if c < 32 => stats.nonprintable += 1,
+                _ => stats.printable += 1,
+            }
+            i += 1;
+        }
+        // a DOS end of file isn't held against it
+        if content.last() == Some(&b'\x1a') {
+            stats.nonprintable -= 1;
+        }
+        stats
+    }
+
+    /// not converted by `text=auto` or `core.autocrlf`
+    fn is_binary(&self) -> bool {
+        self.lone_cr > 0 || self.nul > 0 || (self.printable >> 7) < self.nonprintable
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoCrlf {
+    False,
+    True,
+    Input,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eol {
+    Unset,
+    Lf,
+    Crlf,
+}
+
+/// `crlf_action` of git, what is done to the line endings of a path
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrlfAction {
+    /// nothing says anything
+    Undefined,
+    /// never converted
+    Binary,
+    /// text, with the line ending of `core.eol`
+    Text,
+    /// text, LF in the working tree
+    TextInput,
+    /// text, CRLF in the working tree
+    TextCrlf,
+    /// text unless it looks binary, with the line ending of `core.eol`
+    Auto,
+    AutoInput,
+    AutoCrlf,
+}
+
+impl CrlfAction {
+    fn is_auto(self) -> bool {
+        matches!(self, Self::Auto | Self::AutoInput | Self::AutoCrlf)
+    }
+}
+
+/// `filter.<name>.*`
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub name: String,
+    pub clean: Option<String>,
+    pub smudge: Option<String>,
+    /// a failure of the filter is an error, not a warning
+    pub required: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Convert {
+    attributes: Attributes,
+    auto_crlf: AutoCrlf,
+    eol: Eol,
+    filters: HashMap<String, Filter>,
+    /// filters run in the root of the working tree
+    root: PathBuf,
+}
+
+impl Convert {
+    /// for the working tree at `root`, whose repository is `git_dir`
+    pub fn open(root: &Path, git_dir: &Path) -> Result<Self, GitError> {
+        let attributes = Attributes::open(root, git_dir)?;
+        let config = Config::load(git_dir.join("config"))?;
+        Ok(Self::new(root, attributes, &config))
+    }
+
+    pub fn new(root: &Path, attributes: Attributes, config: &Config) -> Self {
+        let auto_crlf = match config.get("core.autocrlf") {
+            Some(value) if value.eq_ignore_ascii_case("input") => AutoCrlf::Input,
+            _ if config.get_bool("core.autocrlf") == Some(true) => AutoCrlf::True,
+            _ => AutoCrlf::False,
+        };
+        let eol = match config
+            .get("core.eol")
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("lf") => Eol::Lf,
+            Some("crlf") => Eol::Crlf,
+            // `native`, which is LF where we run
+            _ if cfg!(windows) => Eol::Crlf,
+            _ => Eol::Unset,
+        };
+        Self {
+            attributes,
+            auto_crlf,
+            eol,
+            filters: filters(config),
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// the content of the blob for the file at `path`
+    pub fn to_git(&mut self, path: &[u8], content: Vec<u8>) -> Result<Vec<u8>, GitError> {
+        let (action, filter) = self.actions(path)?;
+        let content = match filter {
+            Some(filter) => {
+                run_filter(&filter, filter.clean.as_deref(), path, content, &self.root)?
+            }
+            None => content,
+        };
+        Ok(crlf_to_git(action, content))
+    }
+
+    /// the content of the file at `path` for the blob
+    pub fn to_worktree(&mut self, path: &[u8], content: Vec<u8>) -> Result<Vec<u8>, GitError> {
+        let (action, filter) = self.actions(path)?;
+        let content = crlf_to_worktree(self.output_eol(action), action, content);
+        match filter {
+            Some(filter) => {
+                run_filter(&filter, filter.smudge.as_deref(), path, content, &self.root)
+            }
+            None => Ok(content),
+        }
+    }
+
+    /// whether `to_git` or `to_worktree` may change the content of `path`. when they
+    /// don't, the content can be streamed instead
+    pub fn converts(&mut self, path: &[u8]) -> Result<bool, GitError> {
+        let (action, filter) = self.actions(path)?;
+        Ok(action != CrlfAction::Binary || filter.is_some())
+    }
+
+    /// what is done to the line endings of `path`
+    pub fn crlf_action(&mut self, path: &[u8]) -> Result<CrlfAction, GitError> {
+        Ok(self.actions(path)?.0)
+    }
+
+    fn actions(&mut self, path: &[u8]) -> Result<(CrlfAction, Option<Filter>), GitError> {
+        let found = self.attributes.of(path)?;
+        let get = |name: &str| {
+            found
+                .iter()
+                .find(|(n, _)| n == name)
+                .map_or(State::Unspecified, |(_, state)| state.clone())
+        };
+        let mut action = match crlf_attribute(get("text")) {
+            CrlfAction::Undefined => crlf_attribute(get("crlf")),
+            action => action,
+        };
+        if action != CrlfAction::Binary {
+            let eol = match get("eol") {
+                State::Value(value) if value == "lf" => Eol::Lf,
+                State::Value(value) if value == "crlf" => Eol::Crlf,
+                _ => Eol::Unset,
+            };
+            action = match (action, eol) {
+                (CrlfAction::Auto, Eol::Lf) => CrlfAction::AutoInput,
+                (CrlfAction::Auto, Eol::Crlf) => CrlfAction::AutoCrlf,
+                (_, Eol::Lf) => CrlfAction::TextInput,
+                (_, Eol::Crlf) => CrlfAction::TextCrlf,
+                (action, Eol::Unset) => action,
+            };
+        }
+        let action = match (action, self.auto_crlf) {
+            (CrlfAction::Text, _) if self.text_eol_is_crlf() => CrlfAction::TextCrlf,
+            (CrlfAction::Text, _) => CrlfAction::TextInput,
+            (CrlfAction::Undefined, AutoCrlf::False) => CrlfAction::Binary,
+            (CrlfAction::Undefined, AutoCrlf::True) => CrlfAction::AutoCrlf,
+            (CrlfAction::Undefined, AutoCrlf::Input) => CrlfAction::AutoInput,
+            (action, _) => action,
+        };
+
+        let filter = match get("filter") {
+            State::Value(name) => Some(self.filters.get(&name).cloned().unwrap_or(Filter {
+                name,
+                ..Filter::default()
+            })),
+            _ => None,
+        };
+        Ok((action, filter))
+    }
+
+    fn text_eol_is_crlf(&self) -> bool {
+        match self.auto_crlf {
+            AutoCrlf::True => true,
+            AutoCrlf::Input => false,
+            AutoCrlf::False => self.eol == Eol::Crlf,
+        }
+    }
+
+    fn output_eol(&self, action: CrlfAction) -> Eol {
+        match action {
+            CrlfAction::Binary => Eol::Unset,
+            CrlfAction::TextCrlf | CrlfAction::AutoCrlf | CrlfAction::Undefined => Eol::Crlf,
+            CrlfAction::TextInput | CrlfAction::AutoInput => Eol::Lf,
+            CrlfAction::Text | CrlfAction::Auto if self.text_eol_is_crlf() => Eol::Crlf,
+            CrlfAction::Text | CrlfAction::Auto => Eol::Lf,
+        }
+    }
+}
+
+fn crlf_attribute(state: State) -> CrlfAction {
+    match state {
+        State::Set => CrlfAction::Text,
+        State::Unset => CrlfAction::Binary,
+        State::Value(value) if value == "input" => CrlfAction::TextInput,
+        State::Value(value) if value == "auto" => CrlfAction::Auto,
+        _ => CrlfAction::Undefined,
+    }
+}
+
+/// every `filter.<name>.{clean,smudge,required}` of the config
+fn filters(config: &Config) -> HashMap<String, Filter> {
+    let mut filters: HashMap<String, Filter> = HashMap::new();
+    for (key, value) in config.entries() {
+        let rest = match key.strip_prefix("filter.") {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let (name, field) = match rest.rsplit_once('.') {
+            Some(split) => split,
+            None => continue,
+        };
+        let filter = filters.entry(name.to_string()).or_insert_with(|| Filter {
+            name: name.to_string(),
+            ..Filter::default()
+        });
+        let value = value.unwrap_or("true");
+        match field {
+            "clean" => filter.clean = Some(value.to_string()),
+            "smudge" => filter.smudge = Some(value.to_string()),
+            "required" => filter.required = parse_bool(value).unwrap_or(false),
+            _ => {}
+        }
+    }
+    filters
+}
+
+/// CRLF to LF, but a lone CR stays
+fn crlf_to_git(action: CrlfAction, content: Vec<u8>) -> Vec<u8> {
+    if action == CrlfAction::Binary || content.is_empty() {
+        return content;
+    }
+    let stats = Stats::gather(&content);
+    // content already in the index with CRs is not converted either, which needs the
+    // index, see `git add --renormalize`
+    if stats.crlf == 0 || (action.is_auto() && stats.is_binary()) {
+        return content;
+    }
+    let mut converted = Vec::with_capacity(content.len() - stats.crlf);
+    for (i, &c) in content.iter().enumerate() {
+        if c != b'\r' || content.get(i + 1) != Some(&b'\n') {
+            converted.push(c);
+        }
+    }
+    converted
+}
+
+/// a lone LF to CRLF, if the line endings of the working tree are CRLF
+fn crlf_to_worktree(eol: Eol, action: CrlfAction, content: Vec<u8>) -> Vec<u8> {
+    if eol != Eol::Crlf {
+        return content;
+    }
+    let stats = Stats::gather(&content);
+    if stats.lone_lf == 0 {
+        return content;
+    }
+    // mixed line endings are left alone
+    if action.is_auto() && (stats.lone_cr > 0 || stats.crlf > 0 || stats.is_binary()) {
+        return content;
+    }
+    let mut converted = Vec::with_capacity(content.len() + stats.lone_lf);
+    for (i, &c) in content.iter().enumerate() {
+        if c == b'\n' && (i == 0 || content[i - 1] != b'\r') {
+            converted.push(b'\r');
+        }
+        converted.push(c);
+    }
+    converted
+}
+
+/// pipe the content through `command` by the shell, `%f` is the quoted path
+fn run_filter(
+    filter: &Filter,
+    command: Option<&str>,
+    path: &[u8],
+    content: Vec<u8>,
+    root: &Path,
+) -> Result<Vec<u8>, GitError> {
+    let command = match command {
+        Some(command) => command,
+        None if filter.required => {
+            return Err(filter_error(filter, path, "no command for it".to_string()))
+        }
+        None => return Ok(content),
+    };
+    let quoted = format!("'{}'", String::from_utf8_lossy(path).replace('\'', "'\\''"));
+    let command = command.replace("%f", &quoted);
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .current_dir(root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            let mut stdin = child.stdin.take().expect("stdin is piped");
+            // written while the output is read, or a large file would block both
+            let input = content.clone();
+            let writer = thread::spawn(move || stdin.write_all(&input));
+            let output = child.wait_with_output()?;
+            // a filter may not read everything, which is its own business
+            let _ = writer.join();
+            Ok(output)
+        });
+    let reason = match output {
+        Ok(output) if output.status.success() => return Ok(output.stdout),
+        Ok(output) => format!("`{}` failed with {}", command, output.status),
+        Err(e) => format!("`{}` can't run: {}", command, e),
+    };
+    if filter.required {
+        return Err(filter_error(filter, path, reason));
+    }
+    // like git, a filter which isn't required may fail
+    warn!(
+        "{}, {} is used unfiltered",
+        reason,
+        String::from_utf8_lossy(path)
+    );
+    Ok(content)
+}
+
+fn filter_error(filter: &Filter, path: &[u8], reason: String) -> GitError {
+    GitError::Filter {
+        name: filter.name.clone(),
+        path: String::from_utf8_lossy(path).into_owned(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::super::{AttributeList, ATTRIBUTES_FILE};
+    use super::*;
+
+    fn converter(root: &Path, attributes: &str, config: &str) -> Convert {
+        fs::write(root.join(ATTRIBUTES_FILE), attributes).unwrap();
+        let attributes = Attributes::new(root.to_path_buf(), AttributeList::default(), Vec::new());
+        Convert::new(root, attributes, &Config::parse(config).unwrap())
+    }
+
+    #[test]
+    fn test_eol() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let attributes = "*.txt text\n*.bat eol=crlf\n*.sh eol=lf\n*.bin binary\n*.a text=auto\n";
+        let mut convert = converter(root, attributes, "");
+        let to_git = |convert: &mut Convert, path: &str, content: &[u8]| {
+            convert.to_git(path.as_bytes(), content.to_vec()).unwrap()
+        };
+        let to_worktree = |convert: &mut Convert, path: &str, content: &[u8]| {
+            convert
+                .to_worktree(path.as_bytes(), content.to_vec())
+                .unwrap()
+        };
+
+        assert_eq!(to_git(&mut convert, "a.txt", b"a\r\nb\rc\n"), b"a\nb\rc\n");
+        assert_eq!(to_worktree(&mut convert, "a.txt", b"a\nb\n"), b"a\nb\n");
+        assert_eq!(to_git(&mut convert, "run.bat", b"a\r\nb\n"), b"a\nb\n");
+        assert_eq!(
+            to_worktree(&mut convert, "run.bat", b"a\nb\r\n"),
+            b"a\r\nb\r\n"
+        );
+        assert_eq!(to_git(&mut convert, "run.sh", b"a\r\n"), b"a\n");
+        assert_eq!(to_git(&mut convert, "a.bin", b"a\r\n"), b"a\r\n");
+        // no attribute and no `core.autocrlf`, as read
+        assert_eq!(to_git(&mut convert, "a.c", b"a\r\n"), b"a\r\n");
+        assert_eq!(convert.crlf_action(b"a.c").unwrap(), CrlfAction::Binary);
+        assert!(!convert.converts(b"a.c").unwrap());
+        assert!(!convert.converts(b"a.bin").unwrap());
+        assert!(convert.converts(b"a.txt").unwrap());
+        // auto leaves what looks binary alone
+        assert_eq!(to_git(&mut convert, "x.a", b"a\r\n"), b"a\n");
+        assert_eq!(to_git(&mut convert, "x.a", b"\0a\r\n"), b"\0a\r\n");
+
+        let mut convert = converter(
+            root,
+            "*.txt text\n*.sh -text\n",
+            "[core]\n\tautocrlf = true\n",
+        );
+        assert_eq!(to_worktree(&mut convert, "a.txt", b"a\nb\n"), b"a\r\nb\r\n");
+        assert_eq!(to_worktree(&mut convert, "a.c", b"a\nb\n"), b"a\r\nb\r\n");
+        // mixed line endings stay, unless it's text for sure
+        assert_eq!(to_worktree(&mut convert, "a.c", b"a\r\nb\n"), b"a\r\nb\n");
+        assert_eq!(
+            to_worktree(&mut convert, "a.txt", b"a\r\nb\n"),
+            b"a\r\nb\r\n"
+        );
+        assert_eq!(to_worktree(&mut convert, "a.sh", b"a\n"), b"a\n");
+        assert_eq!(to_git(&mut convert, "a.c", b"a\r\n"), b"a\n");
+
+        let mut convert = converter(root, "", "[core]\n\tautocrlf = input\n");
+        assert_eq!(to_git(&mut convert, "a.c", b"a\r\n"), b"a\n");
+        assert_eq!(to_worktree(&mut convert, "a.c", b"a\n"), b"a\n");
+        let mut convert = converter(root, "*.txt text\n", "[core]\n\teol = crlf\n");
+        assert_eq!(to_worktree(&mut convert, "a.txt", b"a\n"), b"a\r\n");
+        assert_eq!(to_worktree(&mut convert, "a.c", b"a\n"), b"a\n");
+    }
+
+    #[test]
+    fn test_binary() {
+        assert!(is_binary(b"a\0b"));
+        assert!(!is_binary(b"a\r\nb"));
+        let mut late_nul = vec![b'a'; FIRST_FEW_BYTES];
+        late_nul.push(0);
+        assert!(!is_binary(&late_nul));
+
+        assert!(Stats::gather(b"a\rb").is_binary());
+        assert!(!Stats::gather(b"plain text\r\n\x1a").is_binary());
+        assert!(Stats::gather(b"\x01\x02\x03").is_binary());
+        let stats = Stats::gather(b"a\r\nb\nc\r");
+        assert_eq!((stats.crlf, stats.lone_lf, stats.lone_cr), (1, 1, 1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let config = "[filter \"upper\"]\n\tclean = tr a-z A-Z\n\tsmudge = tr A-Z a-z\n\
+                      [filter \"name\"]\n\tclean = echo %f\n\
+                      [filter \"broken\"]\n\tclean = false\n\
+                      [filter \"strict\"]\n\tclean = false\n\trequired\n";
+        let attributes = "*.up filter=upper\n*.name filter=name\n*.broken filter=broken\n\
+                          *.strict filter=strict\n*.missing filter=missing\n";
+        let mut convert = converter(root, attributes, config);
+
+        assert!(convert.converts(b"a.up").unwrap());
+        assert_eq!(
+            convert.to_git(b"a.up", b"abc\n".to_vec()).unwrap(),
+            b"ABC\n"
+        );
+        assert_eq!(
+            convert.to_worktree(b"a.up", b"ABC\n".to_vec()).unwrap(),
+            b"abc\n"
+        );
+        assert_eq!(
+            convert.to_git(b"it's.name", vec![]).unwrap(),
+            b"it's.name\n"
+        );
+        // a filter which fails, or has no command, passes the content through
+        assert_eq!(convert.to_git(b"a.broken", b"x".to_vec()).unwrap(), b"x");
+        assert_eq!(convert.to_git(b"a.missing", b"x".to_vec()).unwrap(), b"x");
+        assert!(matches!(
+            convert.to_git(b"a.strict", b"x".to_vec()),
+            Err(GitError::Filter { .. })
+        ));
+        // a large file doesn't block on the pipes
+        let large = vec![b'a'; 1 << 20];
+        assert_eq!(convert.to_git(b"a.up", large).unwrap(), vec![b'A'; 1 << 20]);
+    }
+}
@@ -0,0 +1,444 @@
+//! the attributes of paths, from `.gitattributes` of every directory,
+//! `info/attributes` and the file of `core.attributesFile`
+//!
+//! pattern attr1 -attr2 !attr3 attr4=value
+//!
+//! sets, unsets, makes unspecified and gives a value to the attributes of the paths
+//! matching the pattern. `[attr]name attr1 -attr2` defines a macro, setting `name`
+//! sets the attributes it stands for. `binary` is built in, as `-diff -merge -text`.
+//!
+//! `info/attributes` wins over the `.gitattributes` closest to the path, which wins
+//! over the ones above it and `core.attributesFile`. in each of them the last line
+//! deciding an attribute wins
+
+pub mod convert;
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{config::Config, ignore::Pattern, utils::path::bytes_to_path, GitError};
+
+pub const ATTRIBUTES_FILE: &str = ".gitattributes";
+const MACRO_PREFIX: &[u8] = b"[attr]";
+const BUILTIN: &[u8] = b"[attr]binary -diff -merge -text";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Set,
+    Unset,
+    Value(String),
+    /// `!attr`, as if no line above had said anything about it
+    Unspecified,
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            State::Set => write!(f, "set"),
+            State::Unset => write!(f, "unset"),
+            State::Value(value) => write!(f, "{}", value),
+            State::Unspecified => write!(f, "unspecified"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Target {
+    Pattern(Pattern),
+    Macro(String),
+}
+
+#[derive(Clone, Debug)]
+struct Line {
+    target: Target,
+    states: Vec<(String, State)>,
+}
+
+/// the lines of one file
+#[derive(Clone, Debug, Default)]
+pub struct AttributeList {
+    /// the directory the patterns are relative to, empty for the root
+    base: Vec<u8>,
+    lines: Vec<Line>,
+}
+
+impl AttributeList {
+    /// macros are only defined in the top level files, `macros` tells whether this is one
+    pub fn parse(base: Vec<u8>, content: &[u8], macros: bool) -> Self {
+        let content = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+        let lines = content
+            .split(|&c| c == b'\n')
+            .enumerate()
+            .filter_map(|(i, line)| parse_line(line, i + 1, macros))
+            .collect();
+        Self { base, lines }
+    }
+
+    /// a missing file has no lines
+    pub fn load(base: Vec<u8>, path: &Path, macros: bool) -> Result<Self, GitError> {
+        match fs::read(path) {
+            Ok(content) => Ok(Self::parse(base, &content, macros)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self {
+                base,
+                lines: Vec::new(),
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// the names of the macros and attributes, in the order they are written
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().flat_map(|line| {
+            let name = match &line.target {
+                Target::Macro(name) => Some(name.as_str()),
+                Target::Pattern(_) => None,
+            };
+            name.into_iter()
+                .chain(line.states.iter().map(|(name, _)| name.as_str()))
+        })
+    }
+
+    fn macro_states(&self, name: &str) -> Option<&[(String, State)]> {
+        self.lines.iter().rev().find_map(|line| match &line.target {
+            Target::Macro(macro_name) if macro_name == name => Some(&line.states[..]),
+            _ => None,
+        })
+    }
+}
+
+fn parse_line(line: &[u8], number: usize, macros: bool) -> Option<Line> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let line = trim_start(line);
+    if line.is_empty() || line[0] == b'#' {
+        return None;
+    }
+    let (pattern, rest) = if line[0] == b'"' {
+        unquote(line)?
+    } else {
+        let end = line.iter().position(|c| is_blank(*c)).unwrap_or(line.len());
+        (line[..end].to_vec(), &line[end..])
+    };
+
+    let target = match pattern.strip_prefix(MACRO_PREFIX) {
+        Some(name) if macros && is_attribute_name(name) => {
+            Target::Macro(String::from_utf8_lossy(name).into_owned())
+        }
+        Some(_) => {
+            warn!("line {}: macros are only allowed at the top level", number);
+            return None;
+        }
+        None => match Pattern::parse(&pattern, number) {
+            Some(pattern) if !pattern.negative => Target::Pattern(pattern),
+            _ => {
+                warn!(
+                    "line {}: negative patterns are ignored in attributes",
+                    number
+                );
+                return None;
+            }
+        },
+    };
+
+    let mut states = Vec::new();
+    for word in rest.split(|c| is_blank(*c)).filter(|w| !w.is_empty()) {
+        let (name, state) = match word {
+            [b'-', name @ ..] => (name, State::Unset),
+            [b'!', name @ ..] => (name, State::Unspecified),
+            _ => match word.iter().position(|&c| c == b'=') {
+                Some(eq) => {
+                    let value = String::from_utf8_lossy(&word[eq + 1..]).into_owned();
+                    (&word[..eq], State::Value(value))
+                }
+                None => (word, State::Set),
+            },
+        };
+        if !is_attribute_name(name) {
+            warn!(
+                "line {}: `{}` is not a valid attribute name",
+                number,
+                String::from_utf8_lossy(name)
+            );
+            return None;
+        }
+        states.push((String::from_utf8_lossy(name).into_owned(), state));
+    }
+    Some(Line { target, states })
+}
+
+fn is_blank(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\r' | b'\n')
+}
+
+fn trim_start(line: &[u8]) -> &[u8] {
+    let start = line
+        .iter()
+        .position(|c| !is_blank(*c))
+        .unwrap_or(line.len());
+    &line[start..]
+}
+
+/// letters, digits, `-`, `.` and `_`, not starting with `-`
+fn is_attribute_name(name: &[u8]) -> bool {
+    !name.is_empty()
+        && name[0] != b'-'
+        && name
+            .iter()
+            .all(|&c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'.' | b'_'))
+}
+
+/// a pattern quoted like a C string, and what follows it
+fn unquote(line: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let mut pattern = Vec::new();
+    let mut i = 1;
+    loop {
+        match *line.get(i)? {
+            b'"' => return Some((pattern, &line[i + 1..])),
+            b'\\' => {
+                i += 1;
+                let c = *line.get(i)?;
+                match c {
+                    b'0'..=b'7' => {
+                        let digits = line.get(i..i + 3)?;
+                        let octal = std::str::from_utf8(digits).ok()?;
+                        pattern.push(u8::from_str_radix(octal, 8).ok()?);
+                        i += 2;
+                    }
+                    b'a' => pattern.push(0x07),
+                    b'b' => pattern.push(0x08),
+                    b'f' => pattern.push(0x0c),
+                    b'n' => pattern.push(b'\n'),
+                    b'r' => pattern.push(b'\r'),
+                    b't' => pattern.push(b'\t'),
+                    b'v' => pattern.push(0x0b),
+                    b'"' | b'\\' => pattern.push(c),
+                    _ => return None,
+                }
+            }
+            c => pattern.push(c),
+        }
+        i += 1;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Attributes {
+    root: PathBuf,
+    /// the `.gitattributes` of the directories looked at so far, by their path
+    dirs: HashMap<Vec<u8>, AttributeList>,
+    /// `info/attributes`, above every `.gitattributes`
+    info: AttributeList,
+    /// `core.attributesFile`, then the built in macros
+    global: Vec<AttributeList>,
+    /// every name in the order git would meet them reading the files, which is the
+    /// order `check-attr -a` shows them in
+    names: Vec<String>,
+}
+
+impl Attributes {
+    /// for the working tree at `root`, whose repository is `git_dir`
+    pub fn open(root: &Path, git_dir: &Path) -> Result<Self, GitError> {
+        let config = Config::load(git_dir.join("config"))?;
+        let info = AttributeList::load(Vec::new(), &git_dir.join("info").join("attributes"), true)?;
+        let mut global = Vec::new();
+        if let Some(path) = attributes_file(&config) {
+            global.push(AttributeList::load(Vec::new(), &path, true)?);
+        }
+        Ok(Self::new(root.to_path_buf(), info, global))
+    }
+
+    pub fn new(root: PathBuf, info: AttributeList, mut global: Vec<AttributeList>) -> Self {
+        global.push(AttributeList::parse(Vec::new(), BUILTIN, true));
+        let mut attributes = Self {
+            root,
+            dirs: HashMap::new(),
+            info,
+            global,
+            names: Vec::new(),
+        };
+        for i in (0..attributes.global.len()).rev() {
+            let names: Vec<String> = attributes.global[i].names().map(String::from).collect();
+            attributes.register(names);
+        }
+        attributes
+    }
+
+    /// every attribute decided for `path`(relative to the root, separated by `/`), in
+    /// the order they are found, from the highest precedence
+    pub fn of(&mut self, path: &[u8]) -> Result<Vec<(String, State)>, GitError> {
+        self.load_dir(b"")?;
+        for (i, _) in path.iter().enumerate().filter(|(_, &c)| c == b'/') {
+            self.load_dir(&path[..i])?;
+        }
+
+        let mut found: Vec<(String, State)> = Vec::new();
+        for list in self.lists(path) {
+            for line in list.lines.iter().rev() {
+                match &line.target {
+                    Target::Pattern(pattern) if pattern.matches(&list.base, path, false) => {
+                        self.fill(&line.states, &mut found)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// the attributes of `path` which are not unspecified, like `check-attr -a` shows
+    /// them
+    pub fn all(&mut self, path: &[u8]) -> Result<Vec<(String, State)>, GitError> {
+        let mut found = self.of(path)?;
+        found.retain(|(_, state)| *state != State::Unspecified);
+        found.sort_by_key(|(name, _)| self.names.iter().position(|n| n == name));
+        Ok(found)
+    }
+
+    /// the state of the attribute `name` for `path`
+    pub fn get(&mut self, path: &[u8], name: &str) -> Result<State, GitError> {
+        let found = self.of(path)?;
+        Ok(found
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map_or(State::Unspecified, |(_, state)| state))
+    }
+
+    /// from the highest precedence to the lowest
+    fn lists<'a>(&'a self, path: &'a [u8]) -> impl Iterator<Item = &'a AttributeList> {
+        let parents = path
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, &c)| c == b'/')
+            .map(|(i, _)| &path[..i])
+            .chain([&b""[..]]);
+        [&self.info]
+            .into_iter()
+            .chain(parents.filter_map(|dir| self.dirs.get(dir)))
+            .chain(self.global.iter())
+    }
+
+    /// the states not decided yet, the last of a line first. a macro which is set sets
+    /// its attributes too
+    fn fill(&self, states: &[(String, State)], found: &mut Vec<(String, State)>) {
+        for (name, state) in states.iter().rev() {
+            if found.iter().any(|(n, _)| n == name) {
+                continue;
+            }
+            found.push((name.clone(), state.clone()));
+            if *state == State::Set {
+                if let Some(states) = self.macro_states(name) {
+                    self.fill(states, found);
+                }
+            }
+        }
+    }
+
+    /// macros come from the top level files only
+    fn macro_states(&self, name: &str) -> Option<&[(String, State)]> {
+        [&self.info]
+            .into_iter()
+            .chain(self.dirs.get(&b""[..]))
+            .chain(self.global.iter())
+            .find_map(|list| list.macro_states(name))
+    }
+
+    fn load_dir(&mut self, dir: &[u8]) -> Result<(), GitError> {
+        if self.dirs.contains_key(dir) {
+            return Ok(());
+        }
+        let file = self.root.join(bytes_to_path(dir)).join(ATTRIBUTES_FILE);
+        let list = AttributeList::load(dir.to_vec(), &file, dir.is_empty())?;
+        let mut names: Vec<String> = list.names().map(String::from).collect();
+        if dir.is_empty() {
+            // git reads `info/attributes` right after the root file
+            names.extend(self.info.names().map(String::from));
+        }
+        self.register(names);
+        self.dirs.insert(dir.to_vec(), list);
+        Ok(())
+    }
+
+    fn register(&mut self, names: Vec<String>) {
+        for name in names {
+            if !self.names.contains(&name) {
+                self.names.push(name);
+            }
+        }
+    }
+}
+
+/// `core.attributesFile`, by default `$XDG_CONFIG_HOME/git/attributes` or
+/// `~/.config/git/attributes`
+fn attributes_file(config: &Config) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    match config.get("core.attributesFile") {
+        Some(path) => match (path.strip_prefix("~/"), home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(path)),
+        },
+        None => match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(xdg) if !xdg.is_empty() => Some(PathBuf::from(xdg).join("git").join("attributes")),
+            _ => home.map(|home| home.join(".config").join("git").join("attributes")),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(s: &str) -> State {
+        State::Value(s.to_string())
+    }
+
+    #[test]
+    fn test_attributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::write(
+            root.join(ATTRIBUTES_FILE),
+            "# comment\n*.txt text eol=crlf\n*.png binary\n[attr]generated -diff linguist\n\
+             \"with space.txt\" -text\n!*.rs text\nsrc/** myattr=top\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("src").join(ATTRIBUTES_FILE),
+            "*.txt eol=lf\ngen/* generated\n[attr]ignored text\n*.c myattr=low !eol\n",
+        )
+        .unwrap();
+        let info = AttributeList::parse(Vec::new(), b"*.c myattr=info\n", true);
+        let mut attributes = Attributes::new(root.to_path_buf(), info, Vec::new());
+
+        let mut get = |path: &str, name: &str| attributes.get(path.as_bytes(), name).unwrap();
+        assert_eq!(get("a.txt", "text"), State::Set);
+        assert_eq!(get("a.txt", "eol"), value("crlf"));
+        // the closer file wins
+        assert_eq!(get("src/a.txt", "eol"), value("lf"));
+        assert_eq!(get("src/a.txt", "text"), State::Set);
+        assert_eq!(get("img/a.png", "text"), State::Unset);
+        assert_eq!(get("img/a.png", "diff"), State::Unset);
+        assert_eq!(get("img/a.png", "binary"), State::Set);
+        assert_eq!(get("src/gen/x.c", "diff"), State::Unset);
+        assert_eq!(get("src/gen/x.c", "linguist"), State::Set);
+        assert_eq!(get("src/gen/x.c", "myattr"), value("info"));
+        assert_eq!(get("src/x.c", "eol"), State::Unspecified);
+        assert_eq!(get("with space.txt", "text"), State::Unset);
+        assert_eq!(get("a.rs", "text"), State::Unspecified);
+        assert_eq!(get("src/x.h", "myattr"), value("top"));
+
+        let found = attributes.of(b"src/gen/x.c").unwrap();
+        let names: Vec<&str> = found.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["myattr", "eol", "generated", "linguist", "diff"]);
+        // as git shows them
+        let all = attributes.all(b"src/gen/x.c").unwrap();
+        let names: Vec<&str> = all.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["diff", "generated", "linguist", "myattr"]);
+        let all = attributes.all(b"a.png").unwrap();
+        let names: Vec<&str> = all.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["binary", "diff", "merge", "text"]);
+    }
+}
@@ -0,0 +1,618 @@
+//! materialize a tree into the working tree and the index together
+//!
+//! like `git read-tree -m -u <head> <target>`, each path is looked at in HEAD, the
+//! index and the target. a path the target has as HEAD has it keeps whatever the
+//! index and the working tree have, so local changes are carried over. a path which
+//! changes must be clean(its entry is the one of HEAD, its file the one of the
+//! entry), and must not replace an untracked file, or nothing is touched at all.
+//! forcing throws the local changes away instead
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    attributes::{convert::Convert, ATTRIBUTES_FILE},
+    ignore::Ignore,
+    index::{extension::Extension, refresh::is_missing, stage::STAGE_MERGED, Index, IndexEntry},
+    object::{
+        tree::{verify_path, ObjectType},
+        ObjectDatabase, ObjectId, ObjectMode,
+    },
+    tree::flatten,
+    utils::path::{bytes_to_path, has_symlink_leading_path, path_to_bytes},
+    GitError,
+};
+
+pub struct Checkout<'a> {
+    database: &'a ObjectDatabase,
+    root: PathBuf,
+    git_dir: PathBuf,
+    force: bool,
+}
+
+/// the paths a checkout changed in the working tree
+#[derive(Debug, Default)]
+pub struct Outcome {
+    /// written from the target
+    pub updated: Vec<Vec<u8>>,
+    /// gone from the target
+    pub removed: Vec<Vec<u8>>,
+}
+
+/// a path to write from the target, or to remove when it's `None`
+type Change = (Vec<u8>, Option<(ObjectMode, ObjectId)>);
+
+impl<'a> Checkout<'a> {
+    /// for the working tree at `root`, whose repository is `git_dir`
+    pub fn new(database: &'a ObjectDatabase, root: &Path, git_dir: &Path) -> Self {
+        Self {
+            database,
+            root: root.to_path_buf(),
+            git_dir: git_dir.to_path_buf(),
+            force: false,
+        }
+    }
+
+    /// overwrite local changes and untracked files instead of refusing to
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// move the index and the working tree from the tree `head`(`None` for an unborn
+    /// branch) to the tree `target`. the index isn't written here
+    pub fn run(
+        &self,
+        index: &mut Index,
+        head: Option<&ObjectId>,
+        target: &ObjectId,
+    ) -> Result<Outcome, GitError> {
+        let head = match head {
+            Some(id) => flatten(self.database, id)?,
+            None => BTreeMap::new(),
+        };
+        let target = flatten(self.database, target)?;
+        let mut convert = Convert::open(&self.root, &self.git_dir)?;
+        let refresh = index.refresh(&self.root, self.database.format(), &mut convert)?;
+        let dirty: HashSet<Vec<u8>> = refresh.modified.into_iter().collect();
+        let missing: HashSet<Vec<u8>> = refresh.deleted.into_iter().collect();
+        let changes = self.changes(index, &head, &target, &dirty, &missing)?;
+
+        let mut outcome = Outcome::default();
+        // removed first, a file may make way for a directory
+        for (path, _) in changes.iter().filter(|(_, side)| side.is_none()) {
+            self.remove(path)?;
+            index.set_path(path, None)?;
+            outcome.removed.push(path.clone());
+        }
+        // the attributes are written before the files they convert
+        let (attributes, files): (Vec<_>, Vec<_>) = changes
+            .iter()
+            .filter_map(|(path, side)| side.map(|side| (path, side)))
+            .partition(|(path, _)| is_attributes_file(path));
+        for (path, (mode, id)) in attributes {
+            let entry = self.write(path, mode, id, &mut convert)?;
+            index.set_path(path, Some(entry))?;
+            outcome.updated.push(path.clone());
+        }
+        let mut convert = Convert::open(&self.root, &self.git_dir)?;
+        for (path, (mode, id)) in files {
+            let entry = self.write(path, mode, id, &mut convert)?;
+            index.set_path(path, Some(entry))?;
+            outcome.updated.push(path.clone());
+        }
+        // like git, a checkout forgets the resolved conflicts, and the untracked cache
+        // no longer fits the working tree
+        index
+            .extensions
+            .retain(|e| !matches!(e, Extension::ResolveUndo(_) | Extension::UntrackedCache(_)));
+        Ok(outcome)
+    }
+
+    /// what to do to each path, checked before anything is touched. like git, a file
+    /// which is `missing` may be written over, but is only brought back when forced
+    fn changes(
+        &self,
+        index: &Index,
+        head: &BTreeMap<Vec<u8>, (ObjectMode, ObjectId)>,
+        target: &BTreeMap<Vec<u8>, (ObjectMode, ObjectId)>,
+        dirty: &HashSet<Vec<u8>>,
+        missing: &HashSet<Vec<u8>>,
+    ) -> Result<Vec<Change>, GitError> {
+        for (path, &(mode, _)) in target.iter() {
+            let invalid = |reason| GitError::InvalidPath {
+                path: path.clone(),
+                reason,
+            };
+            verify_path(path, mode).map_err(invalid)?;
+            // trees with the same name twice, a file and a directory
+            if parents(path).any(|dir| target.contains_key(dir)) {
+                return Err(invalid("a directory above it is a file"));
+            }
+        }
+
+        let mut ignore = Ignore::open(&self.root, &self.git_dir)?;
+        let mut paths: BTreeSet<&[u8]> = target.keys().map(Vec::as_slice).collect();
+        paths.extend(index.entrys.iter().map(|e| &e.filepath[..]));
+
+        let (mut changes, mut local, mut untracked) = (Vec::new(), Vec::new(), Vec::new());
+        for path in paths {
+            let entrys = index.entries_of(path);
+            let conflicted = entrys.iter().any(|e| e.stage() != STAGE_MERGED);
+            let ours = entrys.first().filter(|e| e.stage() == STAGE_MERGED);
+            if ours.is_some_and(|e| e.skip_worktree()) {
+                continue;
+            }
+            let ours = ours.map(|e| (e.mode, e.sha1));
+            let side = target.get(path).copied();
+            let theirs = side.map(|(mode, id)| (mode.bits(), id));
+            let base = head.get(path).map(|&(mode, id)| (mode.bits(), id));
+
+            if self.force {
+                if conflicted || ours != theirs || dirty.contains(path) || missing.contains(path) {
+                    changes.push((path.to_vec(), side));
+                }
+                continue;
+            }
+            if conflicted {
+                local.push(path.to_vec());
+            } else if base == theirs || ours == theirs {
+                continue;
+            } else if ours != base || dirty.contains(path) {
+                local.push(path.to_vec());
+            } else if (ours.is_none() || replaces_dir(side) && self.is_dir(path))
+                && self.in_the_way(path, index, &mut ignore)?
+            {
+                // an untracked file, or one in a directory which took the place of
+                // the tracked file. a submodule keeps its directory
+                untracked.push(path.to_vec());
+            } else {
+                changes.push((path.to_vec(), side));
+            }
+        }
+
+        if !local.is_empty() {
+            return Err(GitError::LocalChanges(local));
+        }
+        if !untracked.is_empty() {
+            return Err(GitError::UntrackedOverwritten(untracked));
+        }
+        Ok(changes)
+    }
+
+    /// whether a file which is neither tracked nor ignored is at `path`, or where a
+    /// directory above it must be made
+    fn in_the_way(
+        &self,
+        path: &[u8],
+        index: &Index,
+        ignore: &mut Ignore,
+    ) -> Result<bool, GitError> {
+        for dir in parents(path) {
+            match fs::symlink_metadata(self.full_path(dir)) {
+                Ok(meta) if meta.is_dir() => continue,
+                Ok(_) => {
+                    return Ok(index.entries_of(dir).is_empty() && !ignore.is_ignored(dir, false)?)
+                }
+                Err(_) => return Ok(false),
+            }
+        }
+        match fs::symlink_metadata(self.full_path(path)) {
+            Ok(meta) if meta.is_dir() => self.untracked_under(path, index, ignore),
+            Ok(_) => Ok(!ignore.is_ignored(path, false)?),
+            Err(_) => Ok(false),
+        }
+    }
+
+    fn untracked_under(
+        &self,
+        dir: &[u8],
+        index: &Index,
+        ignore: &mut Ignore,
+    ) -> Result<bool, GitError> {
+        if ignore.is_ignored(dir, true)? {
+            return Ok(false);
+        }
+        for entry in fs::read_dir(self.full_path(dir))? {
+            let entry = entry?;
+            let mut path = dir.to_vec();
+            path.push(b'/');
+            path.extend_from_slice(&path_to_bytes(Path::new(&entry.file_name())));
+            let found = if entry.file_type()?.is_dir() {
+                self.untracked_under(&path, index, ignore)?
+            } else {
+                index.entries_of(&path).is_empty() && !ignore.is_ignored(&path, false)?
+            };
+            if found {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// remove the file of `path`, and the directories it leaves empty. a file behind a
+    /// symlink is left alone, it may be anywhere
+    fn remove(&self, path: &[u8]) -> Result<(), GitError> {
+        if has_symlink_leading_path(&self.root, path) {
+            return Ok(());
+        }
+        let full_path = self.full_path(path);
+        match fs::symlink_metadata(&full_path) {
+            // a submodule, which is left alone unless it's empty
+            Ok(meta) if meta.is_dir() => {
+                let _ = fs::remove_dir(&full_path);
+            }
+            Ok(_) => fs::remove_file(&full_path)?,
+            Err(e) if is_missing(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        for dir in parents(path).rev() {
+            if fs::remove_dir(self.full_path(dir)).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// write the file of `path` from the blob `id`, returns its new entry
+    fn write(
+        &self,
+        path: &[u8],
+        mode: ObjectMode,
+        id: ObjectId,
+        convert: &mut Convert,
+    ) -> Result<IndexEntry, GitError> {
+        let full_path = self.full_path(path);
+        self.make_parents(path)?;
+        let mut entry = IndexEntry::with_path(path.to_vec(), mode.bits(), id);
+        if mode == ObjectMode::Gitlink {
+            // the commit is in the submodule, which isn't cloned: only its directory
+            // is made, and the entry has no stat data
+            match fs::symlink_metadata(&full_path) {
+                Ok(meta) if meta.is_dir() => {}
+                Ok(_) => {
+                    fs::remove_file(&full_path)?;
+                    fs::create_dir(&full_path)?;
+                }
+                Err(_) => fs::create_dir(&full_path)?,
+            }
+            return Ok(entry);
+        }
+
+        let mut reader = self.database.open_stream(&id)?;
+        if reader.kind() != ObjectType::Blob {
+            return Err(GitError::UnexpectedType {
+                id,
+                expected: ObjectType::Blob,
+                actual: reader.kind(),
+            });
+        }
+        match fs::symlink_metadata(&full_path) {
+            // `changes` found only ignored files in it, or it's forced
+            Ok(meta) if meta.is_dir() => fs::remove_dir_all(&full_path)?,
+            Ok(_) => fs::remove_file(&full_path)?,
+            Err(e) if is_missing(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let executable = mode == ObjectMode::Executable;
+        if mode == ObjectMode::SymbolLink {
+            let mut target = Vec::new();
+            reader.read_to_end(&mut target)?;
+            symlink(&target, &full_path)?;
+        } else if convert.converts(path)? {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            let content = convert.to_worktree(path, content)?;
+            write_file(&full_path, &content[..], executable)?;
+        } else {
+            // nothing to convert, so the blob goes to the file as it's inflated
+            write_file(&full_path, reader, executable)?;
+        }
+        entry.update_stat(&fs::symlink_metadata(&full_path)?);
+        Ok(entry)
+    }
+
+    /// the directories above `path`, a file in the way was found tracked(and removed
+    /// by now) or ignored. a symlink in the way is replaced too, never followed
+    fn make_parents(&self, path: &[u8]) -> Result<(), GitError> {
+        for dir in parents(path) {
+            let dir = self.full_path(dir);
+            match fs::symlink_metadata(&dir) {
+                Ok(meta) if meta.is_dir() => continue,
+                Ok(_) => fs::remove_file(&dir)?,
+                Err(e) if is_missing(&e) => {}
+                Err(e) => return Err(e.into()),
+            }
+            fs::create_dir(&dir)?;
+        }
+        Ok(())
+    }
+
+    fn is_dir(&self, path: &[u8]) -> bool {
+        fs::symlink_metadata(self.full_path(path)).is_ok_and(|meta| meta.is_dir())
+    }
+
+    fn full_path(&self, path: &[u8]) -> PathBuf {
+        self.root.join(bytes_to_path(path))
+    }
+}
+
+/// `a`, `a/b` for `a/b/c`
+fn parents(path: &[u8]) -> impl DoubleEndedIterator<Item = &[u8]> {
+    path.iter()
+        .enumerate()
+        .filter(|(_, &c)| c == b'/')
+        .map(move |(i, _)| &path[..i])
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// whether writing `side` removes a directory in its place
+fn replaces_dir(side: Option<(ObjectMode, ObjectId)>) -> bool {
+    side.is_some_and(|(mode, _)| mode != ObjectMode::Gitlink)
+}
+
+fn is_attributes_file(path: &[u8]) -> bool {
+    path.rsplit(|&c| c == b'/').next() == Some(ATTRIBUTES_FILE.as_bytes())
+}
+
+/// a new file, the umask decides the permissions like it does for git
+fn write_file(path: &Path, mut content: impl Read, executable: bool) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(if executable { 0o777 } else { 0o666 });
+    }
+    #[cfg(not(unix))]
+    let _ = executable;
+    io::copy(&mut content, &mut options.open(path)?)?;
+    Ok(())
+}
+
+/// where symlinks aren't supported, the file holds the target instead
+fn symlink(target: &[u8], path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(bytes_to_path(target), path);
+    #[cfg(not(unix))]
+    return fs::write(path, target);
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::{
+        index::INDEX_SIGNATURE,
+        object::{tree::TreeBuilder, Blob, Object, Tree, TreeEntry},
+        utils::hash::ObjectFormat,
+        REPO_NAME,
+    };
+
+    use super::*;
+
+    /// the tree of `files`, whose paths may have directories
+    fn write_tree(database: &ObjectDatabase, files: &[(&str, ObjectMode, &str)]) -> ObjectId {
+        let mut builder = TreeBuilder::new();
+        let mut dirs: BTreeMap<&str, Vec<(&str, ObjectMode, &str)>> = BTreeMap::new();
+        for &(path, mode, content) in files {
+            match path.split_once('/') {
+                Some((dir, rest)) => dirs.entry(dir).or_default().push((rest, mode, content)),
+                None => {
+                    let blob = Object::Blob(Blob::new(content.as_bytes().to_vec()));
+                    let id = database.write(&blob).unwrap();
+                    builder
+                        .insert(TreeEntry::new(mode, id, path.as_bytes().to_vec()))
+                        .unwrap();
+                }
+            }
+        }
+        for (dir, files) in dirs {
+            let id = write_tree(database, &files);
+            builder
+                .insert(TreeEntry::new(ObjectMode::Dir, id, dir.as_bytes().to_vec()))
+                .unwrap();
+        }
+        database.write(&Object::Tree(builder.build())).unwrap()
+    }
+
+    #[test]
+    fn test_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let git_dir = root.join(REPO_NAME);
+        fs::create_dir_all(git_dir.join("objects")).unwrap();
+        fs::write(git_dir.join("config"), "[core]\n\texcludesFile = none\n").unwrap();
+        let database = ObjectDatabase::new(git_dir.join("objects"), ObjectFormat::Sha1);
+        let first = write_tree(
+            &database,
+            &[
+                ("a.txt", ObjectMode::Normal, "a\n"),
+                ("bin/run", ObjectMode::Executable, "#!/bin/sh\n"),
+                ("dir/b.txt", ObjectMode::Normal, "b\n"),
+                ("link", ObjectMode::SymbolLink, "a.txt"),
+            ],
+        );
+        let second = write_tree(
+            &database,
+            &[
+                ("a.txt", ObjectMode::Normal, "a2\n"),
+                ("bin/run", ObjectMode::Executable, "#!/bin/sh\n"),
+                ("c.txt", ObjectMode::Normal, "c\n"),
+                ("dir", ObjectMode::Normal, "now a file\n"),
+                ("link", ObjectMode::SymbolLink, "a.txt"),
+            ],
+        );
+        let checkout = Checkout::new(&database, root, &git_dir);
+        let mut index = Index::new(INDEX_SIGNATURE, 2, 0, vec![], vec![], None);
+
+        let outcome = checkout.run(&mut index, None, &first).unwrap();
+        assert_eq!(outcome.updated.len(), 4);
+        assert_eq!(fs::read(root.join("dir/b.txt")).unwrap(), b"b\n");
+        #[cfg(unix)]
+        {
+            let mode = fs::metadata(root.join("bin/run"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_ne!(mode & 0o100, 0);
+            assert_eq!(
+                fs::read_link(root.join("link")).unwrap(),
+                Path::new("a.txt")
+            );
+        }
+        let paths: Vec<&[u8]> = index.entrys.iter().map(|e| &e.filepath[..]).collect();
+        assert_eq!(paths, [&b"a.txt"[..], b"bin/run", b"dir/b.txt", b"link"]);
+        assert_eq!(index.entry(b"link", 0).unwrap().mode, 0o120000);
+
+        // a local change in the way, then an untracked file: nothing is touched
+        fs::write(root.join("a.txt"), "mine\n").unwrap();
+        assert!(matches!(
+            checkout.run(&mut index, Some(&first), &second),
+            Err(GitError::LocalChanges(paths)) if paths == [b"a.txt".to_vec()]
+        ));
+        assert!(root.join("dir/b.txt").exists());
+        fs::write(root.join("a.txt"), "a\n").unwrap();
+        fs::write(root.join("c.txt"), "untracked\n").unwrap();
+        assert!(matches!(
+            checkout.run(&mut index, Some(&first), &second),
+            Err(GitError::UntrackedOverwritten(paths)) if paths == [b"c.txt".to_vec()]
+        ));
+        fs::remove_file(root.join("c.txt")).unwrap();
+
+        // a change to a path both trees share is carried over
+        fs::write(root.join("bin/run"), "#!/bin/bash\n").unwrap();
+        let outcome = checkout.run(&mut index, Some(&first), &second).unwrap();
+        assert_eq!(outcome.removed, [b"dir/b.txt".to_vec()]);
+        assert_eq!(fs::read(root.join("dir")).unwrap(), b"now a file\n");
+        assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a2\n");
+        assert_eq!(fs::read(root.join("bin/run")).unwrap(), b"#!/bin/bash\n");
+        assert_eq!(index.entrys.len(), 5);
+
+        // unless it's forced
+        let outcome = checkout
+            .force(true)
+            .run(&mut index, Some(&second), &first)
+            .unwrap();
+        assert!(outcome.updated.contains(&b"bin/run".to_vec()));
+        assert_eq!(fs::read(root.join("bin/run")).unwrap(), b"#!/bin/sh\n");
+        assert_eq!(fs::read(root.join("dir/b.txt")).unwrap(), b"b\n");
+        assert!(!root.join("c.txt").exists());
+    }
+
+    #[test]
+    fn test_deleted_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let git_dir = root.join(REPO_NAME);
+        fs::create_dir_all(git_dir.join("objects")).unwrap();
+        fs::write(git_dir.join("config"), "[core]\n\texcludesFile = none\n").unwrap();
+        let database = ObjectDatabase::new(git_dir.join("objects"), ObjectFormat::Sha1);
+        let first = write_tree(
+            &database,
+            &[
+                ("a.txt", ObjectMode::Normal, "a\n"),
+                ("b.txt", ObjectMode::Normal, "b\n"),
+            ],
+        );
+        let second = write_tree(
+            &database,
+            &[
+                ("a.txt", ObjectMode::Normal, "a\n"),
+                ("b.txt", ObjectMode::Normal, "b2\n"),
+            ],
+        );
+        let checkout = Checkout::new(&database, root, &git_dir);
+        let mut index = Index::new(INDEX_SIGNATURE, 2, 0, vec![], vec![], None);
+        checkout.run(&mut index, None, &first).unwrap();
+
+        // a deletion is a local change, which is carried over
+        fs::remove_file(root.join("a.txt")).unwrap();
+        checkout.run(&mut index, Some(&first), &second).unwrap();
+        assert!(!root.join("a.txt").exists());
+        assert_eq!(fs::read(root.join("b.txt")).unwrap(), b"b2\n");
+
+        // unless it's forced, even if the index and the target agree
+        let checkout = checkout.force(true);
+        let outcome = checkout.run(&mut index, Some(&second), &second).unwrap();
+        assert_eq!(outcome.updated, [b"a.txt".to_vec()]);
+        assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"a\n");
+
+        // a directory of untracked files took the place of a tracked file, which is a
+        // deletion too: it isn't thrown away unless it's forced
+        fs::remove_file(root.join("b.txt")).unwrap();
+        fs::create_dir(root.join("b.txt")).unwrap();
+        fs::write(root.join("b.txt/keep"), "mine\n").unwrap();
+        let checkout = checkout.force(false);
+        assert!(matches!(
+            checkout.run(&mut index, Some(&second), &first),
+            Err(GitError::UntrackedOverwritten(paths)) if paths == [b"b.txt".to_vec()]
+        ));
+        assert!(root.join("b.txt/keep").exists());
+        checkout
+            .force(true)
+            .run(&mut index, Some(&second), &first)
+            .unwrap();
+        assert_eq!(fs::read(root.join("b.txt")).unwrap(), b"b\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_path_safety() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let git_dir = root.join(REPO_NAME);
+        fs::create_dir_all(git_dir.join("objects")).unwrap();
+        fs::write(git_dir.join("config"), "[core]\n\texcludesFile = none\n").unwrap();
+        let database = ObjectDatabase::new(git_dir.join("objects"), ObjectFormat::Sha1);
+        let first = write_tree(&database, &[("dir/a.txt", ObjectMode::Normal, "a\n")]);
+        let second = write_tree(&database, &[("b.txt", ObjectMode::Normal, "b\n")]);
+        let checkout = Checkout::new(&database, root, &git_dir);
+        let mut index = Index::new(INDEX_SIGNATURE, 2, 0, vec![], vec![], None);
+        checkout.run(&mut index, None, &first).unwrap();
+
+        // the directory is swapped for a symlink, the file behind it isn't ours
+        fs::write(outside.path().join("a.txt"), "a\n").unwrap();
+        fs::remove_dir_all(root.join("dir")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.join("dir")).unwrap();
+        checkout.run(&mut index, Some(&first), &second).unwrap();
+        assert!(outside.path().join("a.txt").exists());
+        assert!(index.entry(b"dir/a.txt", 0).is_none());
+
+        // and a file is never written through one
+        let third = write_tree(&database, &[("dir/c.txt", ObjectMode::Normal, "c\n")]);
+        assert!(matches!(
+            checkout.run(&mut index, Some(&second), &third),
+            Err(GitError::UntrackedOverwritten(_))
+        ));
+        let checkout = checkout.force(true);
+        checkout.run(&mut index, Some(&second), &third).unwrap();
+        assert!(!outside.path().join("c.txt").exists());
+        assert!(fs::symlink_metadata(root.join("dir")).unwrap().is_dir());
+
+        // a tree with a file and a directory of the same name, which `write` refuses,
+        // is refused even when forced
+        let blob = database
+            .write(&Object::Blob(Blob::new(b"x".to_vec())))
+            .unwrap();
+        let sub = write_tree(&database, &[("x", ObjectMode::Normal, "x")]);
+        let tree = Tree::new(vec![
+            TreeEntry::new(ObjectMode::SymbolLink, blob, b"a".to_vec()),
+            TreeEntry::new(ObjectMode::Dir, sub, b"a".to_vec()),
+        ]);
+        let raw: Vec<u8> = (&Object::Tree(tree)).into();
+        let content = &raw[raw.iter().position(|&c| c == 0).unwrap() + 1..];
+        let bad = database
+            .write_stream(ObjectType::Tree, content.len() as u64, content)
+            .unwrap();
+        assert!(matches!(
+            checkout.run(&mut index, Some(&third), &bad),
+            Err(GitError::InvalidPath { path, .. }) if path == b"a/x"
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::{self, Write};
+
+use crate::{
+    object::{tree::ObjectType, Object, ObjectDatabase, ObjectMode},
+    utils::path::repo_dir,
+    GitError,
+};
+
+/**
+ * like `git cat-file`, every object can be given by a unique prefix of its name
+ *
+ * 1. `type`: the type of the object
+ * 2. `size`: the size of the content
+ * 3. `pretty`: the content, with trees listed line by line
+ * 4. `blob`/`tree`/`commit`/`tag`: the raw content, if the type matches
+ */
+pub fn execute(mode: &str, objects: &[String]) -> Result<(), GitError> {
+    let git_dir = repo_dir()?;
+    let database = ObjectDatabase::open(&git_dir)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for name in objects {
+        cat(&database, mode, name, &mut out)?;
+    }
+    Ok(())
+}
+
+pub fn cat<W: Write>(
+    database: &ObjectDatabase,
+    mode: &str,
+    name: &str,
+    out: &mut W,
+) -> Result<(), GitError> {
+    let id = database.resolve_prefix(name)?;
+    // blobs may be huge, so never read them as a whole
+    let mut reader = database.open_stream(&id)?;
+    let kind = reader.kind();
+
+    match mode {
+        "type" => writeln!(out, "{}", kind)?,
+        "size" => writeln!(out, "{}", reader.size())?,
+        "pretty" if kind == ObjectType::Tree => {
+            let tree = match database.read(&id)? {
+                Object::Tree(tree) => tree,
+                _ => return Err(GitError::CorruptRepo(format!("object {}", id))),
+            };
+            for entry in tree.entrys.iter() {
+                let kind = match entry.mode {
+                    ObjectMode::Dir => ObjectType::Tree,
+                    ObjectMode::Gitlink => ObjectType::Commit,
+                    _ => ObjectType::Blob,
+                };
+                write!(
+                    out,
+                    "{:0>6} {} {}\t",
+                    entry.mode.to_string(),
+                    kind,
+                    entry.sha1
+                )?;
+                // names are raw bytes, which may not be utf-8
+                out.write_all(&entry.filename)?;
+                writeln!(out)?;
+            }
+        }
+        "pretty" => {
+            io::copy(&mut reader, out)?;
+        }
+        expected => {
+            let expected = ObjectType::try_from(expected.as_bytes())
+                .map_err(|_| GitError::ObjectNotFound(name.to_string()))?;
+            if expected != kind {
+                return Err(GitError::UnexpectedType {
+                    id,
+                    expected,
+                    actual: kind,
+                });
+            }
+            io::copy(&mut reader, out)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::hash::ObjectFormat;
+
+    use super::*;
+
+    fn cat_to_string(mode: &str, name: &str) -> Result<String, GitError> {
+        let database = ObjectDatabase::new("data/objects".into(), ObjectFormat::Sha1);
+        let mut out = Vec::new();
+        cat(&database, mode, name, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_cat() {
+        assert_eq!(cat_to_string("type", "3b18e5").unwrap(), "blob\n");
+        assert_eq!(cat_to_string("size", "3b18e5").unwrap(), "12\n");
+        assert_eq!(cat_to_string("pretty", "3b18e5").unwrap(), "hello world\n");
+        assert_eq!(cat_to_string("blob", "3b18e5").unwrap(), "hello world\n");
+        assert!(matches!(
+            cat_to_string("tree", "3b18e5"),
+            Err(GitError::UnexpectedType { .. })
+        ));
+
+        let tree = cat_to_string("pretty", "385277dd").unwrap();
+        assert_eq!(tree.lines().count(), 2);
+        assert!(tree.starts_with("100644 blob "));
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    env,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    attributes::{Attributes, State},
+    utils::path::{path_to_bytes, relative_to_root, repo_dir},
+    GitError,
+};
+
+/**
+ * like `git check-attr`, paths are relative to the current directory
+ *
+ * 1. `check-attr attr... -- path...`: the state of each attribute for each path, as
+ *    `<path>: <attribute>: <set|unset|unspecified|value>`
+ * 2. `check-attr -a path...`: every attribute which is not unspecified
+ *
+ * without `--` the first argument is the attribute and the others are paths
+ */
+pub fn execute(all: bool, attributes: &[String], paths: &[PathBuf]) -> Result<(), GitError> {
+    let (names, paths): (&[String], Vec<PathBuf>) = match (all, paths.is_empty()) {
+        (true, true) => (&[], attributes.iter().map(PathBuf::from).collect()),
+        (false, true) if !attributes.is_empty() => (
+            &attributes[..1],
+            attributes[1..].iter().map(PathBuf::from).collect(),
+        ),
+        _ => (attributes, paths.to_vec()),
+    };
+
+    let git_dir = repo_dir()?;
+    let root = git_dir.parent().ok_or(GitError::NotRepository)?;
+    let cwd = env::current_dir()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut checker = Attributes::open(root, &git_dir)?;
+    for path in paths.iter() {
+        let relative = relative_to_root(root, &cwd.join(path))
+            .ok_or_else(|| GitError::OutsideRepository(path.clone()))?;
+        let shown = path.to_string_lossy();
+        check_attr(
+            &mut checker,
+            &path_to_bytes(&relative),
+            &shown,
+            names,
+            all,
+            &mut out,
+        )?;
+    }
+    Ok(())
+}
+
+pub fn check_attr<W: Write>(
+    attributes: &mut Attributes,
+    path: &[u8],
+    shown: &str,
+    names: &[String],
+    all: bool,
+    out: &mut W,
+) -> Result<(), GitError> {
+    let states = if all {
+        attributes.all(path)?
+    } else {
+        let found = attributes.of(path)?;
+        names
+            .iter()
+            .map(|name| {
+                let state = found.iter().find(|(n, _)| n == name);
+                let state = state.map_or(State::Unspecified, |(_, state)| state.clone());
+                (name.clone(), state)
+            })
+            .collect()
+    };
+    for (name, state) in states {
+        writeln!(out, "{}: {}: {}", shown, name, state)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::attributes::{AttributeList, ATTRIBUTES_FILE};
+
+    use super::*;
+
+    #[test]
+    fn test_check_attr() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(
+            root.join(ATTRIBUTES_FILE),
+            "*.txt text eol=crlf\n*.png binary\n",
+        )
+        .unwrap();
+        let mut attributes =
+            Attributes::new(root.to_path_buf(), AttributeList::default(), Vec::new());
+        let mut check = |path: &str, names: &[&str], all| {
+            let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+            let mut out = Vec::new();
+            check_attr(
+                &mut attributes,
+                path.as_bytes(),
+                path,
+                &names,
+                all,
+                &mut out,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // written by git
+        assert_eq!(
+            check("a.png", &["text", "eol", "binary"], false),
+            "a.png: text: unset\na.png: eol: unspecified\na.png: binary: set\n"
+        );
+        assert_eq!(
+            check("a.txt", &[], true),
+            "a.txt: text: set\na.txt: eol: crlf\n"
+        );
+        assert_eq!(
+            check("a.png", &[], true),
+            "a.png: binary: set\na.png: diff: unset\na.png: merge: unset\na.png: text: unset\n"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    env,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    ignore::Ignore,
+    utils::path::{path_to_bytes, relative_to_root, repo_dir},
+    GitError,
+};
+
+/**
+ * like `git check-ignore`, paths are relative to the current directory
+ *
+ * 1. without `verbose`: print the paths which are ignored
+ * 2. with `verbose`: print every path matching a pattern, even a negated one, as
+ *    `<source>:<line>:<pattern>\t<path>`
+ * 3. fail with `NothingIgnored` when no path was printed, which exits with 1
+ */
+pub fn execute(verbose: bool, paths: &[PathBuf]) -> Result<(), GitError> {
+    let git_dir = repo_dir()?;
+    let root = git_dir.parent().ok_or(GitError::NotRepository)?;
+    let cwd = env::current_dir()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut ignore = Ignore::open(root, &git_dir)?;
+    let mut found = false;
+    for path in paths {
+        let relative = relative_to_root(root, &cwd.join(path))
+            .ok_or_else(|| GitError::OutsideRepository(path.clone()))?;
+        let is_dir = root.join(&relative).is_dir();
+        found |= check_ignore(
+            &mut ignore,
+            &path_to_bytes(&relative),
+            is_dir,
+            &path.to_string_lossy(),
+            verbose,
+            &mut out,
+        )?;
+    }
+    if !found {
+        return Err(GitError::NothingIgnored);
+    }
+    Ok(())
+}
+
+/// returns whether the path is printed: it's ignored, or with `verbose` it matches a
+/// pattern, negated or not. like git, that's what the exit code counts
+pub fn check_ignore<W: Write>(
+    ignore: &mut Ignore,
+    path: &[u8],
+    is_dir: bool,
+    shown: &str,
+    verbose: bool,
+    out: &mut W,
+) -> Result<bool, GitError> {
+    let found = match ignore.matching(path, is_dir)? {
+        Some(found) => found,
+        None => return Ok(false),
+    };
+    if verbose {
+        write!(out, "{}:{}:", found.source, found.pattern.line)?;
+        out.write_all(&found.pattern.original)?;
+        writeln!(out, "\t{}", shown)?;
+        Ok(true)
+    } else if found.is_ignored() {
+        writeln!(out, "{}", shown)?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::REPO_NAME;
+
+    use super::*;
+
+    #[test]
+    fn test_check_ignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let git_dir = root.join(REPO_NAME);
+        fs::create_dir_all(git_dir.join("info")).unwrap();
+        fs::write(git_dir.join("info/exclude"), "*.tmp\n").unwrap();
+        // no global excludes file in the way
+        fs::write(git_dir.join("config"), "[core]\n\texcludesFile = none\n").unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n!keep.log\n").unwrap();
+
+        let mut ignore = Ignore::open(root, &git_dir).unwrap();
+        let mut check = |path: &str, is_dir, verbose| {
+            let mut out = Vec::new();
+            let ignored = check_ignore(
+                &mut ignore,
+                path.as_bytes(),
+                is_dir,
+                path,
+                verbose,
+                &mut out,
+            );
+            (ignored.unwrap(), String::from_utf8(out).unwrap())
+        };
+        assert_eq!(
+            check("target/debug/app", false, true),
+            (true, ".gitignore:1:target/\ttarget/debug/app\n".to_string())
+        );
+        assert_eq!(
+            check("a.tmp", false, true),
+            (true, format!("{}/info/exclude:1:*.tmp\ta.tmp\n", REPO_NAME))
+        );
+        assert_eq!(
+            check("keep.log", false, true),
+            (true, ".gitignore:3:!keep.log\tkeep.log\n".to_string())
+        );
+        assert_eq!(check("keep.log", false, false), (false, String::new()));
+        assert_eq!(check("a.log", false, false), (true, "a.log\n".to_string()));
+        assert_eq!(check("a.rs", false, true), (false, String::new()));
+    }
+}
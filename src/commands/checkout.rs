@@ -0,0 +1,272 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    checkout::Checkout,
+    index::{Index, INDEX_SIGNATURE},
+    object::{ObjectDatabase, ObjectId},
+    refs::{head_ref, read_ref, resolve_head, write_ref, write_symbolic_ref},
+    tree::commit_tree,
+    utils::path::repo_dir,
+    GitError,
+};
+
+/// where HEAD is moved to
+#[derive(Clone, Debug)]
+pub enum Target {
+    /// the full name of a branch, such as `refs/heads/master`
+    Branch(String),
+    Detached(ObjectId),
+}
+
+/**
+ * like `git checkout <name>`, the index and the working tree follow HEAD
+ *
+ * 1. a branch under `refs/heads` is checked out, and HEAD points to it
+ * 2. anything else is a commit(or a tag of one), whose name may be abbreviated, and
+ *    HEAD is detached at it
+ * 3. local changes in the way are refused, unless `force` throws them away
+ */
+pub fn execute(force: bool, name: &str) -> Result<(), GitError> {
+    let git_dir = repo_dir()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    checkout(&git_dir, name, force, &mut out)
+}
+
+/**
+ * like `git switch <branch>`, which only takes branches. `create` makes the branch at
+ * HEAD first, it must not exist yet
+ */
+pub fn execute_switch(create: bool, force: bool, branch: &str) -> Result<(), GitError> {
+    let git_dir = repo_dir()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    switch(&git_dir, branch, create, force, &mut out)
+}
+
+pub fn checkout<W: Write>(
+    git_dir: &Path,
+    name: &str,
+    force: bool,
+    out: &mut W,
+) -> Result<(), GitError> {
+    let branch = format!("refs/heads/{}", name);
+    let target = match read_ref(git_dir, &branch)? {
+        Some(_) => Target::Branch(branch),
+        None => Target::Detached(ObjectDatabase::open(git_dir)?.resolve_prefix(name)?),
+    };
+    move_head(git_dir, &target, force, out)
+}
+
+pub fn switch<W: Write>(
+    git_dir: &Path,
+    branch: &str,
+    create: bool,
+    force: bool,
+    out: &mut W,
+) -> Result<(), GitError> {
+    let name = format!("refs/heads/{}", branch);
+    let exists = read_ref(git_dir, &name)?.is_some();
+    if create {
+        if exists {
+            return Err(GitError::BranchExists(branch.to_string()));
+        }
+        match resolve_head(git_dir)? {
+            Some(head) => write_ref(git_dir, &name, &head)?,
+            // nothing to check out, the branch is born with the first commit
+            None => {
+                write_symbolic_ref(git_dir, "HEAD", &name)?;
+                writeln!(out, "Switched to a new branch '{}'", branch)?;
+                return Ok(());
+            }
+        }
+    } else if !exists {
+        return Err(GitError::InvalidRef(branch.to_string()));
+    }
+    move_head(git_dir, &Target::Branch(name), force, out)
+}
+
+/// check out the commit of `target` from the one of HEAD, then point HEAD to it
+pub fn move_head<W: Write>(
+    git_dir: &Path,
+    target: &Target,
+    force: bool,
+    out: &mut W,
+) -> Result<(), GitError> {
+    let root = git_dir.parent().ok_or(GitError::NotRepository)?;
+    let database = ObjectDatabase::open(git_dir)?;
+    let format = database.format();
+    let index_path = git_dir.join("index");
+    let mut index = match Index::open(&index_path, format) {
+        Ok(index) => index,
+        Err(GitError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+            Index::new(INDEX_SIGNATURE, 2, 0, vec![], vec![], None)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let id = match target {
+        Target::Branch(name) => {
+            read_ref(git_dir, name)?.ok_or_else(|| GitError::InvalidRef(name.clone()))?
+        }
+        Target::Detached(id) => *id,
+    };
+    let head = match resolve_head(git_dir)? {
+        Some(head) => Some(commit_tree(&database, &head)?),
+        None => None,
+    };
+    let tree = commit_tree(&database, &id)?;
+    Checkout::new(&database, root, git_dir)
+        .force(force)
+        .run(&mut index, head.as_ref(), &tree)?;
+    index.write(&index_path, format)?;
+
+    match target {
+        Target::Branch(name) => {
+            let short = name.strip_prefix("refs/heads/").unwrap_or(name);
+            if head_ref(git_dir)?.as_ref() == Some(name) {
+                writeln!(out, "Already on '{}'", short)?;
+            } else {
+                write_symbolic_ref(git_dir, "HEAD", name)?;
+                writeln!(out, "Switched to branch '{}'", short)?;
+            }
+        }
+        Target::Detached(id) => {
+            write_ref(git_dir, "HEAD", id)?;
+            writeln!(out, "HEAD is now at {}", id)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        object::{tree::TreeBuilder, Blob, Commit, Object, ObjectMode, Signature, TreeEntry},
+        utils::hash::ObjectFormat,
+        REPO_NAME,
+    };
+
+    use super::*;
+
+    /// a commit of a tree with the single file `file.txt`
+    fn write_commit(database: &ObjectDatabase, content: &str) -> ObjectId {
+        let blob = database
+            .write(&Object::Blob(Blob::new(content.as_bytes().to_vec())))
+            .unwrap();
+        let mut builder = TreeBuilder::new();
+        builder
+            .insert(TreeEntry::new(
+                ObjectMode::Normal,
+                blob,
+                b"file.txt".to_vec(),
+            ))
+            .unwrap();
+        let tree = database.write(&Object::Tree(builder.build())).unwrap();
+        let signature = Signature::new(b"a".to_vec(), b"a@b.c".to_vec(), 0, "+0000".into());
+        let commit = Commit::new(
+            tree,
+            None,
+            Some(signature.clone()),
+            Some(signature),
+            b"commit\n".to_vec(),
+        );
+        database.write(&Object::Commit(commit)).unwrap()
+    }
+
+    /// a repository with the branches `master` and `topic`, which aren't checked out:
+    /// HEAD is on the unborn branch `unborn`
+    fn repository(root: &Path) -> (ObjectId, ObjectId) {
+        let git_dir = root.join(REPO_NAME);
+        fs::create_dir_all(git_dir.join("objects")).unwrap();
+        fs::write(git_dir.join("config"), "[core]\n\texcludesFile = none\n").unwrap();
+        write_symbolic_ref(&git_dir, "HEAD", "refs/heads/unborn").unwrap();
+        let database = ObjectDatabase::open(&git_dir).unwrap();
+        let master = write_commit(&database, "master\n");
+        let topic = write_commit(&database, "topic\n");
+        write_ref(&git_dir, "refs/heads/master", &master).unwrap();
+        write_ref(&git_dir, "refs/heads/topic", &topic).unwrap();
+        (master, topic)
+    }
+
+    #[test]
+    fn test_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let git_dir = root.join(REPO_NAME);
+        let (master, topic) = repository(root);
+        let run = |name: &str, force| {
+            let mut out = Vec::new();
+            checkout(&git_dir, name, force, &mut out).map(|_| String::from_utf8(out).unwrap())
+        };
+
+        assert_eq!(
+            run("master", false).unwrap(),
+            "Switched to branch 'master'\n"
+        );
+        assert_eq!(fs::read(root.join("file.txt")).unwrap(), b"master\n");
+        assert_eq!(run("master", false).unwrap(), "Already on 'master'\n");
+
+        let short = &topic.to_string()[..7];
+        assert_eq!(
+            run(short, false).unwrap(),
+            format!("HEAD is now at {}\n", topic)
+        );
+        assert_eq!(fs::read(root.join("file.txt")).unwrap(), b"topic\n");
+        assert_eq!(head_ref(&git_dir).unwrap(), None);
+        assert_eq!(resolve_head(&git_dir).unwrap(), Some(topic));
+
+        fs::write(root.join("file.txt"), "mine\n").unwrap();
+        assert!(matches!(
+            run("master", false),
+            Err(GitError::LocalChanges(_))
+        ));
+        run("master", true).unwrap();
+        assert_eq!(fs::read(root.join("file.txt")).unwrap(), b"master\n");
+        assert_eq!(resolve_head(&git_dir).unwrap(), Some(master));
+
+        let index = Index::open(&git_dir.join("index"), ObjectFormat::Sha1).unwrap();
+        assert_eq!(index.entrys.len(), 1);
+        assert!(matches!(
+            run("nothing", false),
+            Err(GitError::ObjectNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_switch() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let git_dir = root.join(REPO_NAME);
+        let (master, topic) = repository(root);
+        let run = |branch: &str, create| {
+            let mut out = Vec::new();
+            switch(&git_dir, branch, create, false, &mut out)
+                .map(|_| String::from_utf8(out).unwrap())
+        };
+
+        assert_eq!(run("topic", false).unwrap(), "Switched to branch 'topic'\n");
+        assert_eq!(fs::read(root.join("file.txt")).unwrap(), b"topic\n");
+        assert!(matches!(
+            run(&master.to_string(), false),
+            Err(GitError::InvalidRef(_))
+        ));
+        assert!(matches!(
+            run("master", true),
+            Err(GitError::BranchExists(_))
+        ));
+
+        assert!(run("new", false).is_err());
+        assert_eq!(run("new", true).unwrap(), "Switched to branch 'new'\n");
+        assert_eq!(read_ref(&git_dir, "refs/heads/new").unwrap(), Some(topic));
+        assert_eq!(
+            head_ref(&git_dir).unwrap().as_deref(),
+            Some("refs/heads/new")
+        );
+    }
+}
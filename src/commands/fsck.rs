@@ -0,0 +1,529 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    object::{
+        tree::{check_entry_name, compare_entry_names, ObjectType},
+        Object, ObjectDatabase, ObjectId, ObjectMode,
+    },
+    parser::decode::{decode_signature, parse_object},
+    refs,
+    utils::{
+        hash::{HashWriter, ObjectFormat},
+        path::repo_dir,
+    },
+    GitError,
+};
+
+/**
+ * 1. check every loose and packed object: its content must hash to its name, and it
+ *    must be well formed
+ * 2. walk from `HEAD` and every ref, objects which can't be found are missing.
+ *    objects borrowed from alternates are checked only if they are reachable
+ * 3. valid objects we never reach are unreachable, and the ones no other object
+ *    points to are dangling
+ */
+pub fn execute(unreachable: bool) -> Result<(), GitError> {
+    let git_dir = repo_dir()?;
+    let report = fsck(&git_dir)?;
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    report.print(&mut out, unreachable)?;
+
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(GitError::CorruptRepo(format!(
+            "{} broken and {} missing objects",
+            report.broken.len(),
+            report.missing.len()
+        )))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// number of objects checked, an object in several places counts several times
+    pub checked: usize,
+    /// objects which exist but are corrupt, and why
+    pub broken: Vec<(ObjectId, String)>,
+    /// objects which are referred to by reachable objects but don't exist
+    pub missing: Vec<(ObjectId, ObjectType)>,
+    /// unreachable objects which are not referred to by any other object
+    pub dangling: Vec<(ObjectId, ObjectType)>,
+    pub unreachable: Vec<(ObjectId, ObjectType)>,
+}
+
+impl FsckReport {
+    /// dangling and unreachable objects are harmless
+    pub fn is_ok(&self) -> bool {
+        self.broken.is_empty() && self.missing.is_empty()
+    }
+
+    /// like git, show either the dangling objects or all unreachable objects
+    pub fn print<W: Write>(&self, out: &mut W, unreachable: bool) -> io::Result<()> {
+        for (id, reason) in self.broken.iter() {
+            writeln!(out, "error in object {}: {}", id, reason)?;
+        }
+        for (id, kind) in self.missing.iter() {
+            writeln!(out, "missing {} {}", kind, id)?;
+        }
+        let (label, objects) = if unreachable {
+            ("unreachable", &self.unreachable)
+        } else {
+            ("dangling", &self.dangling)
+        };
+        for (id, kind) in objects.iter() {
+            writeln!(out, "{} {} {}", label, kind, id)?;
+        }
+        Ok(())
+    }
+}
+
+/// the type of a valid object, and the objects it refers to
+type Links = (ObjectType, Vec<(ObjectId, ObjectType)>);
+
+pub fn fsck(git_dir: &Path) -> Result<FsckReport, GitError> {
+    let database = ObjectDatabase::open(git_dir)?;
+    let format = database.format();
+    let mut report = FsckReport::default();
+    let mut objects: BTreeMap<ObjectId, Links> = BTreeMap::new();
+    let mut broken = HashSet::new();
+
+    let mut record = |report: &mut FsckReport, id: ObjectId, result: Result<Links, String>| {
+        report.checked += 1;
+        match result {
+            Ok(links) => {
+                objects.insert(id, links);
+            }
+            Err(reason) => {
+                broken.insert(id);
+                report.broken.push((id, reason));
+            }
+        }
+    };
+
+    let mut loose = database.loose_ids()?;
+    loose.sort();
+    for id in loose {
+        let result = check_loose(&database, &id);
+        record(&mut report, id, result);
+    }
+
+    for pack in database.packs() {
+        for raw in pack.index().ids() {
+            let id = ObjectId::from_bytes(raw)
+                .ok_or_else(|| GitError::CorruptRepo(pack.path().display().to_string()))?;
+            let result = match pack.read(raw, |base| match ObjectId::from_bytes(base) {
+                Some(base) => database.read_base(&base, 1),
+                None => Err(GitError::CorruptRepo(pack.path().display().to_string())),
+            }) {
+                Ok(Some((kind, content))) => check_object(&id, kind, &content, format),
+                Ok(None) => Err("listed in the index, but not in the pack".to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            record(&mut report, id, result);
+        }
+    }
+
+    // connectivity
+    let mut tips = Vec::new();
+    if let Some(head) = refs::resolve_head(git_dir)? {
+        tips.push((head, ObjectType::Commit));
+    }
+    for (_, id) in refs::list_refs(git_dir)? {
+        tips.push((id, ObjectType::Commit));
+    }
+
+    let mut reachable = HashSet::new();
+    let mut missing = BTreeMap::new();
+    let mut stack = tips;
+    while let Some((id, kind)) = stack.pop() {
+        if !reachable.insert(id) {
+            continue;
+        }
+        match objects.get(&id) {
+            Some((_, links)) => stack.extend(links.iter().cloned()),
+            // already reported
+            None if broken.contains(&id) => continue,
+            // borrowed from an alternate, which is checked when reached
+            None if database.exists(&id) => {
+                report.checked += 1;
+                let result = database
+                    .read_raw(&id)
+                    .map_err(|e| e.to_string())
+                    .and_then(|(kind, content)| check_object(&id, kind, &content, format));
+                match result {
+                    Ok((_, links)) => stack.extend(links),
+                    Err(reason) => report.broken.push((id, reason)),
+                }
+            }
+            None => {
+                missing.insert(id, kind);
+            }
+        }
+    }
+    report.missing = missing.into_iter().collect();
+
+    let referred: HashSet<ObjectId> = objects
+        .values()
+        .flat_map(|(_, links)| links.iter().map(|(id, _)| *id))
+        .collect();
+    for (id, (kind, _)) in objects.iter() {
+        if reachable.contains(id) {
+            continue;
+        }
+        report.unreachable.push((*id, *kind));
+        if !referred.contains(id) {
+            report.dangling.push((*id, *kind));
+        }
+    }
+
+    Ok(report)
+}
+
+/// blobs are hashed while being inflated, so that big ones are never in memory
+fn check_loose(database: &ObjectDatabase, id: &ObjectId) -> Result<Links, String> {
+    let mut reader = database.open_loose(id).map_err(|e| e.to_string())?;
+    let kind = reader.kind();
+    if kind != ObjectType::Blob {
+        let mut content = Vec::new();
+        reader
+            .read_to_end(&mut content)
+            .map_err(|e| e.to_string())?;
+        return check_object(id, kind, &content, database.format());
+    }
+
+    let mut writer = HashWriter::new(io::sink(), database.format());
+    write!(writer, "{} {}\0", kind, reader.size()).map_err(|e| e.to_string())?;
+    io::copy(&mut reader, &mut writer).map_err(|e| e.to_string())?;
+    let (_, digest) = writer.finish();
+    check_hash(id, &digest)?;
+    Ok((kind, Vec::new()))
+}
+
+fn check_hash(id: &ObjectId, digest: &[u8]) -> Result<(), String> {
+    if id.as_bytes() == digest {
+        Ok(())
+    } else {
+        Err(format!(
+            "hash mismatch, the content hashes to {}",
+            crate::utils::bytes::bytes_to_hex(digest)
+        ))
+    }
+}
+
+/// `content` is without the `<type> <size><NUL>` header
+pub fn check_object(
+    id: &ObjectId,
+    kind: ObjectType,
+    content: &[u8],
+    format: ObjectFormat,
+) -> Result<Links, String> {
+    let mut raw = format!("{} {}\0", kind, content.len()).into_bytes();
+    raw.extend_from_slice(content);
+    check_hash(id, &format.hash(&raw))?;
+
+    match kind {
+        ObjectType::Tree => check_tree(content, format)?,
+        ObjectType::Commit => check_commit(content, format)?,
+        _ => {}
+    }
+
+    let object = match parse_object(&raw, format, id) {
+        Ok(object) => object,
+        Err(GitError::Decode { offset, reason, .. }) => {
+            return Err(format!("bad {} at byte {}: {}", kind, offset, reason))
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let links = match object {
+        Object::Blob(_) => Vec::new(),
+        Object::Tree(tree) => tree
+            .entrys
+            .iter()
+            .filter_map(|entry| match entry.mode {
+                ObjectMode::Dir => Some((entry.sha1, ObjectType::Tree)),
+                // lives in another repository
+                ObjectMode::Gitlink => None,
+                _ => Some((entry.sha1, ObjectType::Blob)),
+            })
+            .collect(),
+        Object::Commit(commit) => {
+            let mut links = vec![(commit.root_sha1, ObjectType::Tree)];
+            if let Some(parents) = commit.parents_sha1 {
+                links.extend(parents.into_iter().map(|id| (id, ObjectType::Commit)));
+            }
+            links
+        }
+        Object::Tag(tag) => vec![(tag.object, tag.kind)],
+    };
+    Ok((kind, links))
+}
+
+/// `<mode> <name><NUL><raw id>`..., with valid modes, sorted and unique names
+fn check_tree(mut content: &[u8], format: ObjectFormat) -> Result<(), String> {
+    let mut names = HashSet::new();
+    let mut last: Option<(&[u8], bool)> = None;
+    while !content.is_empty() {
+        let space = content
+            .iter()
+            .position(|&c| c == b' ')
+            .ok_or("truncated tree entry")?;
+        let mode = ObjectMode::try_from(&content[..space])
+            .map_err(|_| format!("bad mode `{}`", String::from_utf8_lossy(&content[..space])))?;
+        content = &content[space + 1..];
+
+        let nul = content
+            .iter()
+            .position(|&c| c == b'\0')
+            .ok_or("truncated tree entry")?;
+        let name = &content[..nul];
+        if content.len() < nul + 1 + format.len() {
+            return Err("truncated tree entry".to_string());
+        }
+        content = &content[nul + 1 + format.len()..];
+
+        let shown = String::from_utf8_lossy(name);
+        check_entry_name(name).map_err(|reason| format!("{}: `{}`", reason, shown))?;
+        if !names.insert(name) {
+            return Err(format!("duplicate entry `{}`", shown));
+        }
+        let is_dir = mode == ObjectMode::Dir;
+        if let Some((last_name, last_is_dir)) = last {
+            if compare_entry_names(last_name, last_is_dir, name, is_dir).is_ge() {
+                return Err(format!("entries not sorted, `{}` is misplaced", shown));
+            }
+        }
+        last = Some((name, is_dir));
+    }
+    Ok(())
+}
+
+/// `tree`, `parent`s, `author` and `committer` in this order, then any other headers,
+/// and a blank line before the message
+fn check_commit(content: &[u8], format: ObjectFormat) -> Result<(), String> {
+    let end = content
+        .windows(2)
+        .position(|w| w == b"\n\n")
+        .ok_or("no blank line after the headers")?;
+    let mut lines = content[..end].split(|&c| c == b'\n').peekable();
+
+    let is_id = |value: &[u8]| ObjectId::from_hex(value).is_some_and(|id| id.format() == format);
+    let is_signature = |value: &[u8]| {
+        matches!(
+            decode_signature::<nom::error::Error<&[u8]>>(value),
+            Ok((rest, _)) if rest.is_empty()
+        )
+    };
+
+    match lines.next().and_then(|line| line.strip_prefix(b"tree ")) {
+        Some(tree) if is_id(tree) => {}
+        _ => return Err("bad tree line".to_string()),
+    }
+    while let Some(parent) = lines.peek().and_then(|line| line.strip_prefix(b"parent ")) {
+        if !is_id(parent) {
+            return Err("bad parent line".to_string());
+        }
+        lines.next();
+    }
+    match lines.next().and_then(|line| line.strip_prefix(b"author ")) {
+        Some(author) if is_signature(author) => {}
+        _ => return Err("bad author line".to_string()),
+    }
+    match lines
+        .next()
+        .and_then(|line| line.strip_prefix(b"committer "))
+    {
+        Some(committer) if is_signature(committer) => {}
+        _ => return Err("bad committer line".to_string()),
+    }
+    // continuation lines of multi-line headers start with a space
+    for line in lines {
+        if line.is_empty() || line[0] == b' ' {
+            continue;
+        }
+        match line.iter().position(|&c| c == b' ') {
+            Some(n) if n > 0 => {}
+            _ => return Err("bad extra header".to_string()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::object::{Blob, Commit, Signature, Tree, TreeEntry};
+
+    use super::*;
+
+    fn commit(tree: ObjectId, parent: Option<ObjectId>, message: &str) -> Object {
+        let signature = Signature::new(b"A U Thor".to_vec(), b"a@b".to_vec(), 0, "+0000".into());
+        Object::Commit(Commit::new(
+            tree,
+            parent.map(|p| vec![p]),
+            Some(signature.clone()),
+            Some(signature),
+            message.into(),
+        ))
+    }
+
+    fn repo() -> (tempfile::TempDir, ObjectDatabase) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("objects")).unwrap();
+        fs::write(dir.path().join("HEAD"), "ref: refs/heads/master\n").unwrap();
+        let database = ObjectDatabase::open(dir.path()).unwrap();
+        (dir, database)
+    }
+
+    #[test]
+    fn test_fsck_healthy() {
+        let (dir, database) = repo();
+        let blob = database
+            .write(&Object::Blob(Blob::new(b"hello world\n".to_vec())))
+            .unwrap();
+        let tree = database
+            .write(&Object::Tree(Tree::new(vec![TreeEntry::new(
+                ObjectMode::Normal,
+                blob,
+                "hello.txt".into(),
+            )])))
+            .unwrap();
+        let first = database.write(&commit(tree, None, "first\n")).unwrap();
+        let second = database
+            .write(&commit(tree, Some(first), "second\n"))
+            .unwrap();
+        refs::write_ref(dir.path(), "refs/heads/master", &second).unwrap();
+
+        // an abandoned commit, and a blob nothing points to
+        let orphan_tree = database
+            .write(&Object::Tree(Tree::new(vec![TreeEntry::new(
+                ObjectMode::Normal,
+                blob,
+                "orphan.txt".into(),
+            )])))
+            .unwrap();
+        let orphan = database
+            .write(&commit(orphan_tree, Some(first), "orphan\n"))
+            .unwrap();
+        let dangling_blob = database
+            .write(&Object::Blob(Blob::new(b"dangling\n".to_vec())))
+            .unwrap();
+
+        let report = fsck(dir.path()).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.checked, 7);
+
+        let mut dangling = vec![
+            (orphan, ObjectType::Commit),
+            (dangling_blob, ObjectType::Blob),
+        ];
+        dangling.sort_by_key(|(id, _)| *id);
+        assert_eq!(report.dangling, dangling);
+
+        let mut unreachable = vec![
+            (orphan, ObjectType::Commit),
+            (orphan_tree, ObjectType::Tree),
+            (dangling_blob, ObjectType::Blob),
+        ];
+        unreachable.sort_by_key(|(id, _)| *id);
+        assert_eq!(report.unreachable, unreachable);
+    }
+
+    #[test]
+    fn test_fsck_broken() {
+        let (dir, database) = repo();
+
+        // not sorted(which `write` refuses), and the blob doesn't exist
+        let missing_blob = database.hash_object(&Object::Blob(Blob::new(b"gone".to_vec())));
+        let unsorted = Object::Tree(Tree::new(vec![
+            TreeEntry::new(ObjectMode::Normal, missing_blob, "b.txt".into()),
+            TreeEntry::new(ObjectMode::Normal, missing_blob, "a.txt".into()),
+        ]));
+        assert!(database.write(&unsorted).is_err());
+        let raw: Vec<u8> = (&unsorted).into();
+        let body = &raw[raw.iter().position(|&c| c == b'\0').unwrap() + 1..];
+        let bad_tree = database
+            .write_stream(ObjectType::Tree, body.len() as u64, body)
+            .unwrap();
+        let good_tree = database
+            .write(&Object::Tree(Tree::new(vec![TreeEntry::new(
+                ObjectMode::Normal,
+                missing_blob,
+                "a.txt".into(),
+            )])))
+            .unwrap();
+        let head = database
+            .write(&commit(good_tree, None, "message\n"))
+            .unwrap();
+        refs::write_ref(dir.path(), "refs/heads/master", &head).unwrap();
+
+        // stored under the wrong name
+        let blob = Object::Blob(Blob::new(b"hello world\n".to_vec()));
+        let blob_id = database.write(&blob).unwrap();
+        let wrong_id = database.hash_object(&Object::Blob(Blob::new(b"other".to_vec())));
+        let wrong_path = database.path(&wrong_id);
+        fs::create_dir_all(wrong_path.parent().unwrap()).unwrap();
+        fs::copy(database.path(&blob_id), &wrong_path).unwrap();
+
+        // not even zlib
+        let garbage_id = database.hash_object(&Object::Blob(Blob::new(b"garbage".to_vec())));
+        let garbage_path = database.path(&garbage_id);
+        fs::create_dir_all(garbage_path.parent().unwrap()).unwrap();
+        fs::write(&garbage_path, b"garbage").unwrap();
+
+        let report = fsck(dir.path()).unwrap();
+        assert!(!report.is_ok());
+
+        let broken: BTreeMap<_, _> = report.broken.iter().cloned().collect();
+        assert_eq!(broken.len(), 3, "{:?}", broken);
+        assert!(broken[&bad_tree].contains("not sorted"));
+        assert!(broken[&wrong_id].contains("hash mismatch"));
+        assert!(broken.contains_key(&garbage_id));
+
+        assert_eq!(report.missing, vec![(missing_blob, ObjectType::Blob)]);
+
+        let mut out = Vec::new();
+        report.print(&mut out, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("missing blob {}", missing_blob)));
+    }
+
+    #[test]
+    fn test_check_commit() {
+        let format = ObjectFormat::Sha1;
+        let tree = "385277dd12e0bd9805036270d7822d1b1679ea51";
+        let good = format!(
+            "tree {}\nauthor A U Thor <a@b> 0 +0000\ncommitter C O Mitter <c@d> 0 +0000\n\
+             gpgsig -----BEGIN PGP SIGNATURE-----\n \n -----END PGP SIGNATURE-----\n\nmessage\n",
+            tree
+        );
+        assert_eq!(check_commit(good.as_bytes(), format), Ok(()));
+
+        let no_author = format!("tree {}\ncommitter C <c@d> 0 +0000\n\nmessage\n", tree);
+        assert_eq!(
+            check_commit(no_author.as_bytes(), format),
+            Err("bad author line".to_string())
+        );
+        let bad_tree = "tree 1234\nauthor A <a@b> 0 +0000\ncommitter C <c@d> 0 +0000\n\n";
+        assert_eq!(
+            check_commit(bad_tree.as_bytes(), format),
+            Err("bad tree line".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fsck_fixtures() {
+        // everything written by git itself must pass, although nothing is reachable
+        let report = fsck(Path::new("data")).unwrap();
+        assert!(report.is_ok(), "{:?}", report.broken);
+        assert!(report.checked > 10);
+    }
+}
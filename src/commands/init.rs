@@ -3,7 +3,9 @@ use std::{
     path::{Path, PathBuf},
 };
 
-const EXCLUDE_TEMPLATE: &'static str = r"
+use crate::utils::hash::ObjectFormat;
+
+const EXCLUDE_TEMPLATE: &str = r"
 # git ls-files --others --exclude-from=.git/info/exclude
 # Lines that start with '#' are comments.
 # For a project mostly in C, the following would be a good set of
@@ -11,16 +13,30 @@ const EXCLUDE_TEMPLATE: &'static str = r"
 # *.[oa]
 # *~";
 
-pub fn execute(path: &PathBuf) {
+pub fn execute(path: &PathBuf, format: ObjectFormat) {
     info!("git init {:?}", path);
-    init_dirs(&Path::new(&path).join(".git-rs"));
+    init_dirs(&Path::new(&path).join(".git-rs"), format);
+}
+
+/// sha256 repositories need `extensions.objectFormat`, which only counts in version 1
+fn config_content(format: ObjectFormat) -> String {
+    match format {
+        ObjectFormat::Sha1 => "[core]\n\trepositoryformatversion = 0\n".to_string(),
+        ObjectFormat::Sha256 => format!(
+            "[core]\n\trepositoryformatversion = 1\n[extensions]\n\tobjectformat = {}\n",
+            format
+        ),
+    }
 }
 
 /// ignore file already exists error
 /// TODO: handle other error, such as permission
 #[allow(unused_must_use)]
-fn init_dirs(base_dir: &Path) {
+fn init_dirs(base_dir: &Path, format: ObjectFormat) {
     fs::create_dir(base_dir);
+    if !base_dir.join("config").exists() {
+        fs::write(base_dir.join("config"), config_content(format));
+    }
 
     let info = base_dir.join("info");
     let objects = base_dir.join("objects");
@@ -29,9 +45,27 @@ fn init_dirs(base_dir: &Path) {
     fs::create_dir(&objects);
     fs::create_dir(&refs);
 
-    fs::File::create(&info.join("exclude"));
+    fs::File::create(info.join("exclude"));
     fs::write(info.join("exclude"), EXCLUDE_TEMPLATE);
-    fs::create_dir(&objects.join("info"));
-    fs::create_dir(&refs.join("heads"));
-    fs::create_dir(&refs.join("tags"));
+    fs::create_dir(objects.join("info"));
+    fs::create_dir(refs.join("heads"));
+    fs::create_dir(refs.join("tags"));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    use super::*;
+
+    #[test]
+    fn test_init_object_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let git_dir = dir.path().join(".git-rs");
+        init_dirs(&git_dir, ObjectFormat::Sha256);
+
+        let config = Config::load(git_dir.join("config")).unwrap();
+        assert_eq!(config.object_format().unwrap(), ObjectFormat::Sha256);
+        assert!(git_dir.join("objects").is_dir());
+    }
 }
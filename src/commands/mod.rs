@@ -1,17 +1,29 @@
 pub mod add;
 pub mod cat;
+pub mod check_attr;
+pub mod check_ignore;
+pub mod checkout;
 pub mod commit;
+pub mod fsck;
 pub mod init;
+pub mod repack;
+pub mod status;
+pub mod update_index;
 
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+use crate::utils::hash::ObjectFormat;
+
 #[derive(StructOpt)]
 #[structopt(about = "the stupid content tracker")]
 pub enum Git {
     Init {
         #[structopt(short, long, default_value = ".")]
         path: PathBuf,
+        /// the hash of the objects, `sha1` or `sha256`
+        #[structopt(long, default_value = "sha1")]
+        object_format: ObjectFormat,
     },
     Add {
         #[structopt(parse(from_os_str))]
@@ -30,8 +42,84 @@ pub enum Git {
         #[structopt(short, long)]
         all: bool,
     },
+    /// show the type, size or content of objects, whose names may be abbreviated
     Cat {
+        /// `type`, `size`, `pretty`, or the type the object must have
+        #[structopt(possible_values = &["type", "size", "pretty", "blob", "tree", "commit", "tag"])]
         mode: String,
-        files: Vec<PathBuf>,
+        objects: Vec<String>,
+    },
+    /// switch to a branch, or detach HEAD at a commit, updating the index and the
+    /// working tree
+    Checkout {
+        /// throw away local changes in the way
+        #[structopt(short, long)]
+        force: bool,
+        /// a branch, or the name of a commit which may be abbreviated
+        name: String,
+    },
+    /// show the gitattributes of the paths
+    CheckAttr {
+        /// every attribute which is not unspecified
+        #[structopt(short, long)]
+        all: bool,
+        /// the attributes, then the paths unless they follow `--`
+        attributes: Vec<String>,
+        #[structopt(parse(from_os_str), last = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// show whether the paths are ignored, by `.gitignore`, `info/exclude` or
+    /// `core.excludesFile`
+    CheckIgnore {
+        /// show the matching pattern and where it comes from, negated ones too
+        #[structopt(short, long)]
+        verbose: bool,
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// verify the connectivity and validity of the objects
+    Fsck {
+        /// show every unreachable object, not only the dangling ones
+        #[structopt(long)]
+        unreachable: bool,
+    },
+    /// pack every reachable object, and remove the loose copies
+    Repack {
+        /// number of objects before each one to try as its delta base
+        #[structopt(long, default_value = "10")]
+        window: usize,
+        /// the longest delta chain
+        #[structopt(long, default_value = "50")]
+        depth: usize,
+    },
+    /// show the paths changed between HEAD, the index and the working tree, and the
+    /// untracked ones
+    Status {
+        /// `XY path` for each path, the default
+        #[structopt(short, long)]
+        short: bool,
+        /// like `--short` with the paths relative to the root, or with the modes and
+        /// ids for `v2`
+        #[structopt(long, possible_values = &["v1", "v2"])]
+        porcelain: Option<Option<String>>,
+        /// show the ignored files too
+        #[structopt(long)]
+        ignored: bool,
+    },
+    /// switch to a branch, updating the index and the working tree
+    Switch {
+        /// create the branch at HEAD first
+        #[structopt(short, long)]
+        create: bool,
+        /// throw away local changes in the way
+        #[structopt(short, long)]
+        force: bool,
+        branch: String,
+    },
+    /// modify the index
+    UpdateIndex {
+        /// rewrite the index in this version, 4 compresses the paths
+        #[structopt(long, possible_values = &["2", "3", "4"])]
+        index_version: Option<u32>,
     },
 }
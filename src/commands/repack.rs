@@ -0,0 +1,245 @@
+use std::{fs, path::Path};
+
+use crate::{
+    index::{refresh::MODE_GITLINK, Index, TreeExtension},
+    object::{walk::reachable, ObjectDatabase, ObjectId},
+    refs,
+    utils::{
+        hash::ObjectFormat,
+        pack::{write_pack, PackEntry},
+        path::repo_dir,
+    },
+    GitError,
+};
+
+/**
+ * like `git repack -a -d`:
+ *
+ * 1. collect every object reachable from `HEAD`, `refs/` and the index: its entries
+ *    and cached trees
+ * 2. write them into a new pack, deltified against similar objects
+ * 3. remove the loose copies of objects which are in the new pack, and the packs it
+ *    supersedes, unless they have a `.keep` file. packed objects which aren't
+ *    reachable, the index counting as a reference, go with them
+ */
+pub fn execute(window: usize, depth: usize) -> Result<(), GitError> {
+    let git_dir = repo_dir()?;
+    let packed = repack(&git_dir, window, depth)?;
+    info!("packed {} objects", packed);
+    Ok(())
+}
+
+/// returns the number of objects in the new pack. the content of every object is held
+/// in memory until the pack is written, deltas are searched among all of them
+pub fn repack(git_dir: &Path, window: usize, depth: usize) -> Result<usize, GitError> {
+    let mut database = ObjectDatabase::open(git_dir)?;
+    let format = database.format();
+    let objects_dir = database.objects_dir().to_path_buf();
+
+    let mut tips = Vec::new();
+    if let Some(head) = refs::resolve_head(git_dir)? {
+        tips.push(head);
+    }
+    tips.extend(refs::list_refs(git_dir)?.into_iter().map(|(_, id)| id));
+    tips.extend(index_tips(git_dir, format)?);
+
+    let objects = reachable(&database, &tips)?;
+    if objects.is_empty() {
+        return Ok(0);
+    }
+
+    let mut entries = Vec::with_capacity(objects.len());
+    for (id, _) in objects.iter() {
+        let (kind, content) = database.read_raw(id)?;
+        entries.push(PackEntry {
+            id: id.as_bytes().to_vec(),
+            kind,
+            content,
+        });
+    }
+
+    let idx_path = write_pack(&objects_dir.join("pack"), &entries, window, depth, format)?;
+    database.reload_packs();
+
+    // only remove what we can find in the new pack
+    let pack_path = idx_path.with_extension("pack");
+    let pack = database
+        .packs()
+        .iter()
+        .find(|pack| pack.path() == pack_path)
+        .ok_or_else(|| GitError::CorruptRepo(idx_path.display().to_string()))?;
+    for ((id, _), entry) in objects.iter().zip(entries.iter()) {
+        let path = database.path(id);
+        if path.exists() && pack.contains(&entry.id) {
+            fs::remove_file(&path)?;
+            if let Some(dir) = path.parent() {
+                // fails if there are other objects in it, which is fine
+                let _ = fs::remove_dir(dir);
+            }
+        }
+    }
+
+    // the index first, so that a pack is never found half removed
+    for old in database.packs().iter().map(|pack| pack.path()) {
+        if old != pack_path && !old.with_extension("keep").exists() {
+            fs::remove_file(old.with_extension("idx"))?;
+            fs::remove_file(old)?;
+        }
+    }
+
+    Ok(entries.len())
+}
+
+/// what the index refers to, like `pack-objects --indexed-objects`: the blobs of its
+/// entries and the valid cached trees. submodule commits are in another repository
+fn index_tips(git_dir: &Path, format: ObjectFormat) -> Result<Vec<ObjectId>, GitError> {
+    let path = git_dir.join("index");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let index = Index::open(&path, format)?;
+    let mut tips: Vec<ObjectId> = index
+        .entrys
+        .iter()
+        .filter(|entry| entry.mode != MODE_GITLINK && !entry.intent_to_add())
+        .map(|entry| entry.sha1)
+        .collect();
+    let mut trees: Vec<&TreeExtension> = index.tree_extension.iter().collect();
+    while let Some(tree) = trees.pop() {
+        if let (true, Some(id)) = (tree.entry_num >= 0, tree.sha1) {
+            tips.push(id);
+        }
+        trees.extend(tree.children.iter());
+    }
+    Ok(tips)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::{IndexEntry, INDEX_SIGNATURE};
+    use crate::object::{Blob, Commit, Object, ObjectMode, Signature, Tree, TreeEntry};
+
+    use super::*;
+
+    #[test]
+    fn test_repack() {
+        let dir = tempfile::tempdir().unwrap();
+        let git_dir = dir.path();
+        fs::create_dir(git_dir.join("objects")).unwrap();
+        let database = ObjectDatabase::new(git_dir.join("objects"), ObjectFormat::Sha1);
+
+        let content: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let signature = Signature::new(b"a".to_vec(), b"a@b".to_vec(), 0, "+0000".into());
+        let mut parent = None;
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let blob = Object::Blob(Blob::new(format!("{}{}\n", content, i).into_bytes()));
+            let blob_id = database.write(&blob).unwrap();
+            let tree = Tree::new(vec![TreeEntry::new(
+                ObjectMode::Normal,
+                blob_id,
+                "file.txt".into(),
+            )]);
+            let tree_id = database.write(&Object::Tree(tree)).unwrap();
+            let commit = Commit::new(
+                tree_id,
+                parent.map(|p| vec![p]),
+                Some(signature.clone()),
+                Some(signature.clone()),
+                format!("commit {}\n", i).into_bytes(),
+            );
+            let commit_id = database.write(&Object::Commit(commit)).unwrap();
+            parent = Some(commit_id);
+            ids.extend([blob_id, tree_id, commit_id]);
+        }
+        // not reachable from any ref, so it stays loose
+        let dangling = database
+            .write(&Object::Blob(Blob::new(b"dangling".to_vec())))
+            .unwrap();
+
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/master\n").unwrap();
+        refs::write_ref(git_dir, "refs/heads/master", parent.as_ref().unwrap()).unwrap();
+
+        assert_eq!(repack(git_dir, 10, 50).unwrap(), 9);
+
+        let database = ObjectDatabase::new(git_dir.join("objects"), ObjectFormat::Sha1);
+        assert_eq!(database.packs().len(), 1);
+        for id in ids.iter() {
+            assert!(!database.path(id).exists());
+            let object = database.read(id).unwrap();
+            assert_eq!(&database.hash_object(&object), id);
+        }
+        assert!(database.path(&dangling).exists());
+
+        // another commit, the new pack replaces the old one
+        let commit = Commit::new(
+            ids[7],
+            parent.map(|p| vec![p]),
+            Some(signature.clone()),
+            Some(signature),
+            b"commit 3\n".to_vec(),
+        );
+        let commit_id = database.write(&Object::Commit(commit)).unwrap();
+        refs::write_ref(git_dir, "refs/heads/master", &commit_id).unwrap();
+        let old_pack = database.packs()[0].path().to_path_buf();
+        assert_eq!(repack(git_dir, 10, 50).unwrap(), 10);
+
+        let database = ObjectDatabase::new(git_dir.join("objects"), ObjectFormat::Sha1);
+        assert_eq!(database.packs().len(), 1);
+        assert!(!old_pack.exists());
+        assert!(!old_pack.with_extension("idx").exists());
+        for id in ids.iter().chain([&commit_id]) {
+            database.read(id).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_repack_keeps_indexed() {
+        let dir = tempfile::tempdir().unwrap();
+        let git_dir = dir.path();
+        fs::create_dir(git_dir.join("objects")).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/master\n").unwrap();
+        let database = ObjectDatabase::new(git_dir.join("objects"), ObjectFormat::Sha1);
+        let signature = Signature::new(b"a".to_vec(), b"a@b".to_vec(), 0, "+0000".into());
+        let commit = |content: &[u8]| {
+            let blob_id = database
+                .write(&Object::Blob(Blob::new(content.to_vec())))
+                .unwrap();
+            let entry = TreeEntry::new(ObjectMode::Normal, blob_id, "a.txt".into());
+            let tree_id = database
+                .write(&Object::Tree(Tree::new(vec![entry])))
+                .unwrap();
+            let commit = Commit::new(
+                tree_id,
+                None,
+                Some(signature.clone()),
+                Some(signature.clone()),
+                b"commit\n".to_vec(),
+            );
+            let commit_id = database.write(&Object::Commit(commit)).unwrap();
+            refs::write_ref(git_dir, "refs/heads/master", &commit_id).unwrap();
+            (blob_id, tree_id)
+        };
+
+        // packed while it was committed, then only staged
+        let (blob_id, tree_id) = commit(b"staged\n");
+        assert_eq!(repack(git_dir, 10, 50).unwrap(), 3);
+        let packed = ObjectDatabase::new(git_dir.join("objects"), ObjectFormat::Sha1);
+        let old_pack = packed.packs()[0].path().to_path_buf();
+        commit(b"committed\n");
+        fs::write(git_dir.join("a.txt"), "staged\n").unwrap();
+        let entry = IndexEntry::try_new(git_dir, b"a.txt", ObjectFormat::Sha1).unwrap();
+        assert_eq!(entry.sha1, blob_id);
+        let cached = TreeExtension::new(vec![], 1, 0, Some(tree_id), vec![]);
+        Index::new(INDEX_SIGNATURE, 2, 1, vec![entry], vec![], Some(cached))
+            .write(&git_dir.join("index"), ObjectFormat::Sha1)
+            .unwrap();
+
+        assert_eq!(repack(git_dir, 10, 50).unwrap(), 5);
+        let database = ObjectDatabase::new(git_dir.join("objects"), ObjectFormat::Sha1);
+        assert!(!old_pack.exists());
+        assert!(!database.path(&blob_id).exists());
+        database.read(&blob_id).unwrap();
+        database.read(&tree_id).unwrap();
+    }
+}
@@ -0,0 +1,501 @@
+use std::{
+    env,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    attributes::convert::Convert,
+    core::{
+        repository::Repository, stage::Stage, status, working_area::WorkingArea, Change, Side,
+        Status,
+    },
+    ignore::Ignore,
+    object::ObjectDatabase,
+    utils::{
+        hash::ObjectFormat,
+        path::{path_to_bytes, relative_to_root, repo_dir},
+    },
+    GitError,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// `XY path`, the paths relative to the current directory
+    Short,
+    /// like `Short`, but the paths are relative to the root, for scripts
+    PorcelainV1,
+    /// the modes and the ids of every side, see `git status --porcelain=v2`
+    PorcelainV2,
+}
+
+/**
+ * like `git status`, which compares HEAD with the index and the index with the working
+ * tree:
+ *
+ * 1. changed paths, staged(X) and unstaged(Y), or unmerged ones
+ * 2. untracked files, a directory with nothing tracked in it is shown once
+ * 3. with `ignored`, ignored files too
+ */
+pub fn execute(format: Format, ignored: bool) -> Result<(), GitError> {
+    let git_dir = repo_dir()?;
+    let root = git_dir.parent().ok_or(GitError::NotRepository)?;
+    let mut prefix =
+        path_to_bytes(&relative_to_root(root, &env::current_dir()?).unwrap_or_default())
+            .into_owned();
+    if !prefix.is_empty() {
+        prefix.push(b'/');
+    }
+    let database = ObjectDatabase::open(&git_dir)?;
+    let entries = collect(&git_dir, &database, ignored)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    print(&entries, format, &prefix, database.format(), &mut out)
+}
+
+/// the status of every path which isn't clean, see `core::status`
+pub fn collect(
+    git_dir: &Path,
+    database: &ObjectDatabase,
+    ignored: bool,
+) -> Result<Vec<(Vec<u8>, Status)>, GitError> {
+    let root = git_dir.parent().ok_or(GitError::NotRepository)?;
+    let repository = Repository::new(git_dir, database)?;
+    let mut stage = Stage::new(git_dir, database.format())?;
+    let working_area = WorkingArea::new(root.to_path_buf());
+    let mut convert = Convert::open(root, git_dir)?;
+    let mut ignore = Ignore::open(root, git_dir)?;
+    status(
+        &repository,
+        &mut stage,
+        &working_area,
+        &mut convert,
+        &mut ignore,
+        ignored,
+    )
+}
+
+/// `prefix` is the current directory relative to the root, ending with `/` unless it is
+/// the root. only the short format uses it
+pub fn print<W: Write>(
+    entries: &[(Vec<u8>, Status)],
+    format: Format,
+    prefix: &[u8],
+    object_format: ObjectFormat,
+    out: &mut W,
+) -> Result<(), GitError> {
+    if format == Format::PorcelainV2 {
+        // like git, ordinary changes first, then conflicts, untracked and ignored files
+        let mut entries: Vec<_> = entries.iter().collect();
+        entries.sort_by_key(|(_, status)| match status {
+            Status::Tracked { .. } => 0,
+            Status::Unmerged { .. } => 1,
+            Status::Untracked => 2,
+            Status::Ignored => 3,
+        });
+        for (path, status) in entries {
+            print_v2(path, status, object_format, out)?;
+        }
+        return Ok(());
+    }
+    for (path, status) in entries {
+        let code = match status {
+            Status::Tracked {
+                staged, unstaged, ..
+            } => {
+                let letter = |change: &Option<Change>| change.map_or(' ', Change::letter);
+                format!("{}{}", letter(staged), letter(unstaged))
+            }
+            Status::Unmerged { stages, .. } => unmerged_code(stages).to_string(),
+            Status::Untracked => "??".to_string(),
+            Status::Ignored => "!!".to_string(),
+        };
+        let path = match format {
+            Format::Short => relative(path, prefix),
+            _ => path.clone(),
+        };
+        write!(out, "{} ", code)?;
+        out.write_all(&quote_path(&path, true))?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn print_v2<W: Write>(
+    path: &[u8],
+    status: &Status,
+    object_format: ObjectFormat,
+    out: &mut W,
+) -> Result<(), GitError> {
+    let zero = "0".repeat(object_format.hex_len());
+    let mode = |side: &Side| format!("{:06o}", side.map_or(0, |(mode, _)| mode));
+    let id = |side: &Side| side.map_or(zero.clone(), |(_, id)| id.to_string());
+    match status {
+        Status::Tracked {
+            head,
+            index,
+            worktree,
+            staged,
+            unstaged,
+        } => {
+            let letter = |change: &Option<Change>| change.map_or('.', Change::letter);
+            write!(
+                out,
+                "1 {}{} {} {} {} {:06o} {} {} ",
+                letter(staged),
+                letter(unstaged),
+                submodule(&[*head, *index], *worktree, unstaged.is_some()),
+                mode(head),
+                mode(index),
+                worktree,
+                id(head),
+                id(index),
+            )?;
+        }
+        Status::Unmerged { stages, worktree } => {
+            write!(
+                out,
+                "u {} {} {} {} {} {:06o} {} {} {} ",
+                unmerged_code(stages),
+                submodule(stages, *worktree, false),
+                mode(&stages[0]),
+                mode(&stages[1]),
+                mode(&stages[2]),
+                worktree,
+                id(&stages[0]),
+                id(&stages[1]),
+                id(&stages[2]),
+            )?;
+        }
+        Status::Untracked => write!(out, "? ")?,
+        Status::Ignored => write!(out, "! ")?,
+    }
+    out.write_all(&quote_path(path, false))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// the two letters of a conflict, by which of the base, ours and theirs exist
+fn unmerged_code(stages: &[Side; 3]) -> &'static str {
+    match [
+        stages[0].is_some(),
+        stages[1].is_some(),
+        stages[2].is_some(),
+    ] {
+        [true, false, false] => "DD",
+        [false, true, false] => "AU",
+        [true, true, false] => "UD",
+        [false, false, true] => "UA",
+        [true, false, true] => "DU",
+        [false, true, true] => "AA",
+        _ => "UU",
+    }
+}
+
+/// `N...` unless a side is a submodule, whose commit may have changed. what changed
+/// inside the submodule isn't looked at
+fn submodule(sides: &[Side], worktree: u32, changed: bool) -> &'static str {
+    const MODE_GITLINK: u32 = 0o160000;
+    let is_gitlink = worktree == MODE_GITLINK
+        || sides
+            .iter()
+            .any(|side| side.is_some_and(|(mode, _)| mode == MODE_GITLINK));
+    match (is_gitlink, changed) {
+        (false, _) => "N...",
+        (true, false) => "S...",
+        (true, true) => "SC..",
+    }
+}
+
+/// `path` relative to the directory `prefix`, both relative to the root
+fn relative(path: &[u8], prefix: &[u8]) -> Vec<u8> {
+    let mut common = 0;
+    for (i, &c) in prefix.iter().enumerate() {
+        if path.get(i) != Some(&c) {
+            break;
+        }
+        if c == b'/' {
+            common = i + 1;
+        }
+    }
+    let ups = prefix[common..].iter().filter(|&&c| c == b'/').count();
+    let mut relative = b"../".repeat(ups);
+    relative.extend_from_slice(&path[common..]);
+    if relative.is_empty() {
+        relative.extend_from_slice(b"./");
+    }
+    relative
+}
+
+/// quote `path` like a C string if it has control characters, `"`, `\` or bytes past
+/// ASCII, as git does unless `core.quotePath` is off. spaces are quoted too with
+/// `quote_space`, which all the formats but v2 do
+fn quote_path(path: &[u8], quote_space: bool) -> Vec<u8> {
+    let needs_quote = |c: u8| !(0x20..0x7f).contains(&c) || c == b'"' || c == b'\\';
+    if !path
+        .iter()
+        .any(|&c| needs_quote(c) || (quote_space && c == b' '))
+    {
+        return path.to_vec();
+    }
+    let mut quoted = vec![b'"'];
+    for &c in path {
+        match c {
+            0x07 => quoted.extend_from_slice(b"\\a"),
+            0x08 => quoted.extend_from_slice(b"\\b"),
+            b'\t' => quoted.extend_from_slice(b"\\t"),
+            b'\n' => quoted.extend_from_slice(b"\\n"),
+            0x0b => quoted.extend_from_slice(b"\\v"),
+            0x0c => quoted.extend_from_slice(b"\\f"),
+            b'\r' => quoted.extend_from_slice(b"\\r"),
+            b'"' | b'\\' => quoted.extend_from_slice(&[b'\\', c]),
+            c if needs_quote(c) => quoted.extend_from_slice(format!("\\{:03o}", c).as_bytes()),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push(b'"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::{
+        index::{stage::STAGE_OURS, stage::STAGE_THEIRS, Index, IndexEntry, INDEX_SIGNATURE},
+        object::{
+            tree::TreeBuilder, Blob, Commit, Object, ObjectId, ObjectMode, Signature, TreeEntry,
+        },
+        refs::{write_ref, write_symbolic_ref},
+        REPO_NAME,
+    };
+
+    use super::*;
+
+    fn tree(database: &ObjectDatabase, entries: &[(ObjectMode, ObjectId, &str)]) -> ObjectId {
+        let mut builder = TreeBuilder::new();
+        for (mode, id, name) in entries {
+            let entry = TreeEntry::new(*mode, *id, name.as_bytes().to_vec());
+            builder.insert(entry).unwrap();
+        }
+        database.write(&Object::Tree(builder.build())).unwrap()
+    }
+
+    /// an empty repository at `root`
+    fn init(root: &Path) -> (PathBuf, ObjectDatabase) {
+        let git_dir = root.join(REPO_NAME);
+        fs::create_dir_all(git_dir.join("objects")).unwrap();
+        fs::write(git_dir.join("config"), "[core]\n\texcludesFile = none\n").unwrap();
+        let database = ObjectDatabase::open(&git_dir).unwrap();
+        (git_dir, database)
+    }
+
+    fn blob(database: &ObjectDatabase, content: &str) -> ObjectId {
+        let blob = Blob::new(content.as_bytes().to_vec());
+        database.write(&Object::Blob(blob)).unwrap()
+    }
+
+    /// commit `tree` on `master`, which HEAD points to
+    fn commit(git_dir: &Path, database: &ObjectDatabase, tree: ObjectId) {
+        let signature = Signature::new(b"a".to_vec(), b"a@b.c".to_vec(), 0, "+0000".into());
+        let commit = Commit::new(
+            tree,
+            None,
+            Some(signature.clone()),
+            Some(signature),
+            b"commit\n".to_vec(),
+        );
+        let commit = database.write(&Object::Commit(commit)).unwrap();
+        write_ref(git_dir, "refs/heads/master", &commit).unwrap();
+        write_symbolic_ref(git_dir, "HEAD", "refs/heads/master").unwrap();
+    }
+
+    fn write_index(git_dir: &Path, entrys: Vec<IndexEntry>) {
+        Index::new(
+            INDEX_SIGNATURE,
+            2,
+            entrys.len() as u32,
+            entrys,
+            vec![],
+            None,
+        )
+        .write(&git_dir.join("index"), ObjectFormat::Sha1)
+        .unwrap();
+    }
+
+    fn show(entries: &[(Vec<u8>, Status)], format: Format, prefix: &str) -> String {
+        let mut out = Vec::new();
+        print(
+            entries,
+            format,
+            prefix.as_bytes(),
+            ObjectFormat::Sha1,
+            &mut out,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let (git_dir, database) = init(root);
+        let blob = |content| blob(&database, content);
+        let (a, b, c, new) = (blob("a\n"), blob("b\n"), blob("c\n"), blob("new\n"));
+        let (staged, ours, theirs) = (blob("staged\n"), blob("ours\n"), blob("theirs\n"));
+
+        // HEAD has a.txt, c.txt and dir/b.txt
+        let normal = ObjectMode::Normal;
+        let sub = tree(&database, &[(normal, b, "b.txt")]);
+        let files = [
+            (normal, a, "a.txt"),
+            (normal, c, "c.txt"),
+            (ObjectMode::Dir, sub, "dir"),
+        ];
+        commit(&git_dir, &database, tree(&database, &files));
+
+        // dir/b.txt and new.txt are staged, conflict.txt was added on both sides
+        let entry = |path: &str, id| IndexEntry::with_path(path.into(), 0o100644, id);
+        let conflict = |id, stage| {
+            let mut entry = entry("conflict.txt", id);
+            entry.set_stage(stage);
+            entry
+        };
+        let entrys = vec![
+            entry("a.txt", a),
+            entry("c.txt", c),
+            conflict(ours, STAGE_OURS),
+            conflict(theirs, STAGE_THEIRS),
+            entry("dir/b.txt", staged),
+            entry("new.txt", new),
+        ];
+        write_index(&git_dir, entrys);
+
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::create_dir_all(root.join("new dir")).unwrap();
+        for (path, content) in [
+            ("c.txt", "changed\n"),
+            ("conflict.txt", "both\n"),
+            ("dir/b.txt", "b\n"),
+            ("new.txt", "new\n"),
+            ("new dir/x", "x\n"),
+            (".gitignore", "*.log\n"),
+            ("debug.log", "log\n"),
+            ("café.txt", "\n"),
+            ("tab\there", "\n"),
+        ] {
+            fs::write(root.join(path), content).unwrap();
+        }
+
+        let entries = collect(&git_dir, &database, true).unwrap();
+        let show = |format, prefix| show(&entries, format, prefix);
+        assert_eq!(
+            show(Format::PorcelainV1, "dir/"),
+            " D a.txt\n M c.txt\nAA conflict.txt\nMM dir/b.txt\nA  new.txt\n\
+             ?? .gitignore\n?? \"caf\\303\\251.txt\"\n?? \"new dir/\"\n?? \"tab\\there\"\n\
+             !! debug.log\n"
+        );
+        assert_eq!(
+            show(Format::Short, "dir/"),
+            " D ../a.txt\n M ../c.txt\nAA ../conflict.txt\nMM b.txt\nA  ../new.txt\n\
+             ?? ../.gitignore\n?? \"../caf\\303\\251.txt\"\n?? \"../new dir/\"\n\
+             ?? \"../tab\\there\"\n!! ../debug.log\n"
+        );
+        let zero = "0".repeat(40);
+        assert_eq!(
+            show(Format::PorcelainV2, ""),
+            format!(
+                "1 .D N... 100644 100644 000000 {a} {a} a.txt\n\
+                 1 .M N... 100644 100644 100644 {c} {c} c.txt\n\
+                 1 MM N... 100644 100644 100644 {b} {staged} dir/b.txt\n\
+                 1 A. N... 000000 100644 100644 {zero} {new} new.txt\n\
+                 u AA N... 000000 100644 100644 100644 {zero} {ours} {theirs} conflict.txt\n\
+                 ? .gitignore\n? \"caf\\303\\251.txt\"\n? new dir/\n? \"tab\\there\"\n\
+                 ! debug.log\n"
+            )
+        );
+
+        // the current directory itself, and quoting without spaces
+        assert_eq!(relative(b"dir/", b"dir/"), b"./");
+        assert_eq!(
+            quote_path("é\t\"".as_bytes(), false),
+            b"\"\\303\\251\\t\\\"\""
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_status_changes() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let (git_dir, database) = init(root);
+        let blob = |content| blob(&database, content);
+        let (ignore, old, gone) = (blob("*.log\ntarget/\n"), blob("old\n"), blob("gone\n"));
+        let (target, f) = (blob("target"), blob("f"));
+
+        let (normal, link) = (ObjectMode::Normal, ObjectMode::SymbolLink);
+        let files = [
+            (normal, ignore, ".gitignore"),
+            (normal, f, "file2link"),
+            (normal, gone, "gone.txt"),
+            (link, target, "link"),
+            (normal, old, "old.txt"),
+        ];
+        commit(&git_dir, &database, tree(&database, &files));
+
+        // old.txt was renamed to new.txt(shown as a deletion and an addition, renames
+        // aren't detected), gone.txt was removed and file2link is now a symlink, all of
+        // it staged. link was replaced by a file, which isn't
+        let entry = |path: &str, mode, id| IndexEntry::with_path(path.into(), mode, id);
+        write_index(
+            &git_dir,
+            vec![
+                entry(".gitignore", 0o100644, ignore),
+                entry("file2link", 0o120000, f),
+                entry("link", 0o120000, target),
+                entry("new.txt", 0o100644, old),
+            ],
+        );
+        fs::write(root.join(".gitignore"), "*.log\ntarget/\n").unwrap();
+        fs::write(root.join("new.txt"), "old\n").unwrap();
+        fs::write(root.join("link"), "target").unwrap();
+        symlink("f", root.join("file2link")).unwrap();
+
+        // docs/ has an untracked file, build/ and target/ only ignored ones
+        for dir in ["build", "docs", "target"] {
+            fs::create_dir(root.join(dir)).unwrap();
+        }
+        for path in ["build/out.log", "docs/a.md", "docs/x.log", "target/bin"] {
+            fs::write(root.join(path), "\n").unwrap();
+        }
+
+        let entries = collect(&git_dir, &database, false).unwrap();
+        assert_eq!(
+            show(&entries, Format::PorcelainV1, ""),
+            "T  file2link\nD  gone.txt\n T link\nA  new.txt\nD  old.txt\n?? docs/\n"
+        );
+        let zero = "0".repeat(40);
+        assert_eq!(
+            show(&entries, Format::PorcelainV2, ""),
+            format!(
+                "1 T. N... 100644 120000 120000 {f} {f} file2link\n\
+                 1 D. N... 100644 000000 000000 {gone} {zero} gone.txt\n\
+                 1 .T N... 120000 120000 100644 {target} {target} link\n\
+                 1 A. N... 000000 100644 100644 {zero} {old} new.txt\n\
+                 1 D. N... 100644 000000 000000 {old} {zero} old.txt\n\
+                 ? docs/\n"
+            )
+        );
+
+        let entries = collect(&git_dir, &database, true).unwrap();
+        let ignored: Vec<_> = entries
+            .iter()
+            .filter(|(_, status)| *status == Status::Ignored)
+            .map(|(path, _)| &path[..])
+            .collect();
+        assert_eq!(ignored, [&b"build/"[..], b"docs/x.log", b"target/"]);
+    }
+}
@@ -0,0 +1,115 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    config::Config,
+    index::{Index, INDEX_SIGNATURE, SUPPORTED_VERSIONS},
+    parser::decode::parse_index,
+    utils::path::repo_dir,
+    GitError,
+};
+
+/**
+ * 1. read the index, a missing one is empty
+ * 2. rewrite it in the requested version, paths are compressed from version 4 on
+ */
+pub fn execute(index_version: Option<u32>) -> Result<(), GitError> {
+    let git_dir = repo_dir()?;
+    if let Some(version) = index_version {
+        let written = set_index_version(&git_dir, version)?;
+        if written != version {
+            warn!(
+                "index uses extended flags, wrote version {} instead of {}",
+                written, version
+            );
+        }
+    }
+    Ok(())
+}
+
+/// returns the version actually written, which is higher than `version` when the
+/// entries need it
+pub fn set_index_version(git_dir: &Path, version: u32) -> Result<u32, GitError> {
+    if !SUPPORTED_VERSIONS.contains(&version) {
+        return Err(GitError::UnsupportedIndexVersion(version));
+    }
+    let format = Config::load(git_dir.join("config"))?.object_format()?;
+    let path = git_dir.join("index");
+    let mut index = match fs::read(&path) {
+        Ok(content) => parse_index(&content, format, &path)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Index::new(INDEX_SIGNATURE, version, 0, vec![], vec![], None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    index.version = version;
+    index.write(&path, format)?;
+    Ok(index.written_version())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::{IndexEntry, EXTENDED_FLAG_SKIP_WORKTREE};
+    use crate::utils::hash::ObjectFormat;
+
+    use super::*;
+
+    fn read_index(git_dir: &Path) -> (Vec<u8>, Index) {
+        let path = git_dir.join("index");
+        let content = fs::read(&path).unwrap();
+        let index = parse_index(&content, ObjectFormat::Sha1, &path).unwrap();
+        (content, index)
+    }
+
+    #[test]
+    fn test_set_index_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let git_dir = dir.path();
+        fs::copy("data/index2", git_dir.join("index")).unwrap();
+        let (v2, original) = read_index(git_dir);
+        assert!(original.entrys.len() > 1);
+
+        assert_eq!(set_index_version(git_dir, 4).unwrap(), 4);
+        let (v4, index) = read_index(git_dir);
+        assert_eq!(index.version, 4);
+        assert!(v4.len() < v2.len());
+        let paths = |index: &Index| -> Vec<Vec<u8>> {
+            index.entrys.iter().map(|e| e.filepath.clone()).collect()
+        };
+        assert_eq!(paths(&index), paths(&original));
+        assert_eq!(index.encode(ObjectFormat::Sha1), v4);
+
+        // back to where we started, byte for byte
+        assert_eq!(set_index_version(git_dir, 2).unwrap(), 2);
+        assert_eq!(read_index(git_dir).0, v2);
+
+        assert!(matches!(
+            set_index_version(git_dir, 5),
+            Err(GitError::UnsupportedIndexVersion(5))
+        ));
+    }
+
+    #[test]
+    fn test_extended_flags() {
+        let dir = tempfile::tempdir().unwrap();
+        let git_dir = dir.path();
+        fs::write(git_dir.join("a.txt"), b"hello world\n").unwrap();
+        let mut entry = IndexEntry::try_new(git_dir, b"a.txt", ObjectFormat::Sha1).unwrap();
+        entry.extended_flags = EXTENDED_FLAG_SKIP_WORKTREE;
+        let index = Index::new(INDEX_SIGNATURE, 2, 1, vec![entry], vec![], None);
+        index
+            .write(&git_dir.join("index"), ObjectFormat::Sha1)
+            .unwrap();
+
+        // version 2 can't hold them
+        let (_, index) = read_index(git_dir);
+        assert_eq!(index.version, 3);
+        assert!(index.entrys[0].skip_worktree());
+        assert!(!index.entrys[0].intent_to_add());
+        assert_eq!(set_index_version(git_dir, 2).unwrap(), 3);
+
+        assert_eq!(set_index_version(git_dir, 4).unwrap(), 4);
+        let (_, index) = read_index(git_dir);
+        assert_eq!(index.version, 4);
+        assert!(index.entrys[0].skip_worktree());
+    }
+}
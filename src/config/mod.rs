@@ -0,0 +1,240 @@
+//! a reader of git's config file(`.git-rs/config`)
+//!
+//! [section]
+//!     key = value
+//! [section "subsection"]
+//!     key = "quoted value" ; comment
+//!
+//! section and key names are case insensitive, subsections are not.
+//! a key without `=` is a boolean `true`.
+
+use std::{fs, path::Path};
+
+use crate::{utils::hash::ObjectFormat, GitError};
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// (`section[.subsection].key`, value) in the order of the file,
+    /// section and key are already lowercased
+    entries: Vec<(String, Option<String>)>,
+}
+
+impl Config {
+    /// a missing file is an empty config
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, GitError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|line| {
+            GitError::CorruptRepo(format!("{}: bad config line {}", path.display(), line))
+        })
+    }
+
+    /// the error is the line number(starts from 1) of the bad line
+    pub fn parse(content: &str) -> Result<Self, usize> {
+        let mut entries = Vec::new();
+        let mut section: Option<String> = None;
+
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let line = if let Some(header) = line.strip_prefix('[') {
+                let end = header.find(']').ok_or(n + 1)?;
+                section = Some(parse_section(&header[..end]).ok_or(n + 1)?);
+                header[end + 1..].trim()
+            } else {
+                line
+            };
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let section = section.as_ref().ok_or(n + 1)?;
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(parse_value(value).ok_or(n + 1)?)),
+                None => (line, None),
+            };
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(n + 1);
+            }
+            entries.push((format!("{}.{}", section, key.to_ascii_lowercase()), value));
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// the last value of `key`, such as `core.bare` or `remote.origin.url`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.as_deref().unwrap_or("true"))
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .filter(|(k, _)| *k == key)
+            .map(|(_, v)| v.as_deref().unwrap_or("true"))
+            .collect()
+    }
+
+    /// `true`/`yes`/`on`/`1` and `false`/`no`/`off`/`0`/empty
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        parse_bool(self.get(key)?)
+    }
+
+    /// every (`section[.subsection].key`, value) in the order of the file, section and
+    /// key lowercased. the value is `None` for a key without `=`
+    pub fn entries(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_deref()))
+    }
+
+    /// the hash function of the repository, `extensions.objectFormat` only counts
+    /// when `core.repositoryFormatVersion` is 1
+    pub fn object_format(&self) -> Result<ObjectFormat, GitError> {
+        let version = self.get("core.repositoryformatversion").unwrap_or("0");
+        match (version, self.get("extensions.objectformat")) {
+            ("1", Some(format)) => format.parse().map_err(GitError::CorruptRepo),
+            _ => Ok(ObjectFormat::Sha1),
+        }
+    }
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// `section` or `section "subsection"`, or the deprecated `section.subsection`
+fn parse_section(header: &str) -> Option<String> {
+    let header = header.trim();
+    match header.split_once(|c: char| c.is_whitespace()) {
+        Some((name, subsection)) => {
+            let subsection = subsection.trim().strip_prefix('"')?.strip_suffix('"')?;
+            let subsection = subsection.replace("\\\"", "\"").replace("\\\\", "\\");
+            Some(format!("{}.{}", name.to_ascii_lowercase(), subsection))
+        }
+        None => Some(header.to_ascii_lowercase()),
+    }
+}
+
+/// unquote and unescape the value, and cut off trailing comments
+fn parse_value(raw: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut pending_space = String::new();
+    let mut chars = raw.trim().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' | ';' if !quoted => break,
+            '\\' => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+                match chars.next()? {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'b' => {
+                        value.pop();
+                    }
+                    c @ ('"' | '\\') => value.push(c),
+                    _ => return None,
+                }
+            }
+            c if c.is_whitespace() && !quoted => pending_space.push(c),
+            c => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+                value.push(c);
+            }
+        }
+    }
+    if quoted {
+        return None;
+    }
+    Some(value)
+}
+
+/// lowercase the section and the key, but keep the subsection
+fn normalize_key(key: &str) -> String {
+    let (section, rest) = key.split_once('.').unwrap_or((key, ""));
+    let (subsection, name) = match rest.rsplit_once('.') {
+        Some((subsection, name)) => (Some(subsection), name),
+        None => (None, rest),
+    };
+    match subsection {
+        Some(subsection) => format!(
+            "{}.{}.{}",
+            section.to_ascii_lowercase(),
+            subsection,
+            name.to_ascii_lowercase()
+        ),
+        None => format!(
+            "{}.{}",
+            section.to_ascii_lowercase(),
+            name.to_ascii_lowercase()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = r#"
+# comment
+[core]
+	repositoryformatversion = 1
+	bare = false
+	excludesFile = "~/.gitignore global" ; comment
+	filemode
+[extensions]
+	objectformat = sha256
+[remote "Origin"]
+	url = https://example.com/repo.git
+	fetch = +refs/heads/*:refs/remotes/origin/*
+	fetch = +refs/tags/*:refs/tags/*
+"#;
+        let config = Config::parse(content).unwrap();
+        assert_eq!(config.get("core.repositoryFormatVersion"), Some("1"));
+        assert_eq!(config.get_bool("core.bare"), Some(false));
+        assert_eq!(config.get_bool("core.filemode"), Some(true));
+        assert_eq!(config.get("core.excludesfile"), Some("~/.gitignore global"));
+        assert_eq!(
+            config.get("remote.Origin.url"),
+            Some("https://example.com/repo.git")
+        );
+        assert_eq!(config.get("remote.origin.url"), None);
+        assert_eq!(config.get_all("remote.Origin.fetch").len(), 2);
+        assert_eq!(config.object_format().unwrap(), ObjectFormat::Sha256);
+
+        assert_eq!(Config::parse("[core\nbare").unwrap_err(), 1);
+        assert_eq!(Config::parse("bare = true").unwrap_err(), 1);
+    }
+
+    #[test]
+    fn test_object_format() {
+        let config = Config::parse("[extensions]\n\tobjectformat = sha256\n").unwrap();
+        // extensions are ignored in version 0
+        assert_eq!(config.object_format().unwrap(), ObjectFormat::Sha1);
+
+        let config =
+            Config::parse("[core]\nrepositoryformatversion = 1\n[extensions]\nobjectformat = md5")
+                .unwrap();
+        assert!(config.object_format().is_err());
+    }
+}
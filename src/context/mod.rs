@@ -1,12 +1,12 @@
 use std::{fs, path::PathBuf};
 
-use nom::IResult;
-
 use crate::{
+    config::Config,
     index::Index,
-    object::Object,
-    parser::decode::{decode_head_pointer, decode_index, decode_object},
-    utils::{path::object_path, sha1},
+    object::{Object, ObjectDatabase},
+    parser::decode::parse_head,
+    refs::Head,
+    utils::bytes::bytes_to_string,
     GitError,
 };
 
@@ -25,6 +25,7 @@ pub struct GitContext {
     pub head: String,
     pub repo: Object,
     pub index: Index,
+    pub database: ObjectDatabase,
 }
 
 impl GitContext {
@@ -35,41 +36,40 @@ impl GitContext {
         #[cfg(test)]
         let root_path = path.join(".git");
 
+        let format = Config::load(root_path.join("config"))?.object_format()?;
+
         let head_path = root_path.join("HEAD");
-        let head_file_content = fs::read(head_path.clone())
-            .map_err(|_e| GitError::CorruptRepo(head_path.to_str().unwrap().into()))?;
-        let head: IResult<_, _> = decode_head_pointer(&head_file_content);
-        let (_, head) =
-            head.map_err(|_e| GitError::CorruptRepo(head_path.to_str().unwrap().into()))?;
+        let head_file_content = fs::read(&head_path)?;
+        let head = parse_head(&head_file_content, &head_path)?;
 
-        let mut path = match head {
-            crate::refs::Head::Ref(reference) => {
-                let path = root_path.join(&reference);
-                dbg!(&path);
-                let content = fs::read(path).unwrap();
-                String::from_utf8(content).unwrap()
+        let (id_path, path) = match head {
+            Head::Ref(reference) => {
+                let ref_path = root_path.join(&reference);
+                let content = fs::read(&ref_path)?;
+                let content = String::from_utf8(content).map_err(|e| {
+                    let offset = e.utf8_error().valid_up_to();
+                    GitError::corrupt_file(&ref_path, offset, "not utf-8")
+                })?;
+                (ref_path, content.trim_end().to_string())
             }
-            crate::refs::Head::Pointer(pointer) => String::from_utf8(pointer).unwrap(),
+            Head::Pointer(pointer) => (head_path, bytes_to_string(&pointer)),
         };
-        path.pop(); // remove the '\n' at the end of file, a little tricky here
-
-        let full_path = object_path(&root_path, &path);
-        let deflated_content = sha1::decode_file(&full_path);
-        dbg!(&path, &full_path);
-        let commit_object: IResult<_, _> = decode_object(&deflated_content);
-        let commit_object = commit_object.unwrap();
-        dbg!(&commit_object);
-
-        let index_content = fs::read(root_path.join("index")).unwrap();
-        // let index= Index::try_new();
-        let index: IResult<_, _> = decode_index(&index_content);
-        let (_, index) = index.unwrap();
 
-        dbg!(&index);
+        let database = ObjectDatabase::new(root_path.join("objects"), format);
+        let id = path
+            .parse()
+            .map_err(|_| GitError::corrupt_file(&id_path, 0, "bad object name"))?;
+        let commit_object = database.read(&id)?;
 
-        dbg!(index.entry_map());
+        let index_path = root_path.join("index");
+        let index = Index::open(&index_path, format)?;
 
-        todo!()
+        Ok(Self {
+            head: path,
+            repo: commit_object,
+            index,
+            database,
+        })
     }
 }
 
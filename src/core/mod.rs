@@ -3,13 +3,24 @@ pub mod stage;
 pub mod working_area;
 
 use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt::Debug,
+    fs,
     path::{Path, PathBuf},
 };
 
 use walkdir::WalkDir;
 
-use crate::context::GitContext;
+use crate::{
+    attributes::convert::Convert,
+    ignore::Ignore,
+    index::{refresh::git_mode, stage::STAGE_MERGED},
+    object::ObjectId,
+    utils::path::bytes_to_path,
+    GitError,
+};
+
+use self::{repository::Repository, stage::Stage, working_area::WorkingArea};
 
 /**
  *
@@ -26,81 +37,166 @@ use crate::context::GitContext;
  *             |                      <--------------------------------------  commit file
  *             
  */
+///
+/// a path is in one of these states for each pair of trees: HEAD and the index, the
+/// index and the working area
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Status {
+    /// in HEAD or the index, and changed between HEAD and the index(`staged`), or
+    /// between the index and the working area(`unstaged`). `worktree` is the mode of
+    /// the file, 0 if there is none
+    Tracked {
+        head: Side,
+        index: Side,
+        worktree: u32,
+        staged: Option<Change>,
+        unstaged: Option<Change>,
+    },
+    /// in conflict, with the base, ours and theirs of stage 1 to 3
+    Unmerged {
+        stages: [Side; 3],
+        worktree: u32,
+    },
     Untracked,
-    Staged,
-    Modified,
-    StagedAndModified, // the status of file in 3 trees differ from each other
-    Committed,
+    Ignored,
 }
 
-#[derive(Clone, Debug)]
-pub enum File {
-    RawFile(RawFile),
-    Dir(Dir),
-}
+/// the mode and the id of a path in one of the trees, `None` if it isn't there
+pub type Side = Option<(u32, ObjectId)>;
 
-#[derive(Clone)]
-pub struct RawFile {
-    pub(crate) name: PathBuf,
-    pub(crate) content: Vec<u8>,
+/// how a path differs from one tree to the next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Deleted,
+    Modified,
+    /// a file became a symlink or a submodule, or the other way around
+    TypeChanged,
 }
 
-impl Debug for RawFile {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        #[cfg(debug_content)]
-        return f.debug_struct("RawFile").field("name", &self.name).field("content", &self.content).finish();
-        #[cfg(not(debug_content))]
-        return f.debug_struct("RawFile").field("name", &self.name).finish();
+impl Change {
+    /// the letter `status --short` shows
+    pub fn letter(self) -> char {
+        match self {
+            Self::Added => 'A',
+            Self::Deleted => 'D',
+            Self::Modified => 'M',
+            Self::TypeChanged => 'T',
+        }
     }
-}
-
-#[derive(Clone)]
-pub struct Dir {
-    pub(crate) name: PathBuf,
-    pub(crate) children: Vec<File>,
-}
 
-impl Debug for Dir {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Dir")
-            .field("name", &self.name)
-            .field("children", &self.children)
-            .finish()
+    fn between(from: Side, to: Side) -> Option<Self> {
+        match (from, to) {
+            (None, None) => None,
+            (None, Some(_)) => Some(Self::Added),
+            (Some(_), None) => Some(Self::Deleted),
+            (Some(from), Some(to)) if from == to => None,
+            (Some((from, _)), Some((to, _))) if from & S_IFMT != to & S_IFMT => {
+                Some(Self::TypeChanged)
+            }
+            _ => Some(Self::Modified),
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::VecDeque;
-    use std::fs;
-    use std::path::{Path, PathBuf};
+/// the bits of a mode telling a file from a symlink or a submodule
+const S_IFMT: u32 = 0o170000;
 
-    #[test]
-    fn test_walk_dir() {
-        let mut tree_root = std::env::current_dir().unwrap();
-        tree_root.push("src");
+/**
+ * compare the three trees path by path:
+ *
+ * 1. HEAD with the index, and the index with the working area, whose files are
+ *    trusted for their stat data, see `Index::refresh`. paths in conflict are given
+ *    with their stages instead
+ * 2. the files nothing tracks, which are untracked or, if `ignored`, ignored
+ *
+ * the tracked paths come first, sorted like the index, then the untracked ones and the
+ * ignored ones, also sorted. renames are not detected, they are a deletion and an
+ * addition
+ */
+pub fn status(
+    repository: &Repository,
+    stage: &mut Stage,
+    working_area: &WorkingArea,
+    convert: &mut Convert,
+    ignore: &mut Ignore,
+    ignored: bool,
+) -> Result<Vec<(Vec<u8>, Status)>, GitError> {
+    let refresh = stage.refresh(working_area.root(), convert)?;
+    let modified: HashSet<&[u8]> = refresh.modified.iter().map(Vec::as_slice).collect();
+    let deleted: HashSet<&[u8]> = refresh.deleted.iter().map(Vec::as_slice).collect();
+    let index = stage.index();
+    let worktree_mode =
+        |path: &[u8]| match fs::symlink_metadata(working_area.root().join(bytes_to_path(path))) {
+            Ok(meta) if !meta.is_dir() => git_mode(&meta),
+            _ => 0,
+        };
 
-        let mut stack = VecDeque::new();
-        stack.push_back(tree_root);
+    let mut tracked: BTreeMap<&[u8], Status> = BTreeMap::new();
+    for path in index.conflicts() {
+        let mut stages = [None; 3];
+        for entry in index.entries_of(path) {
+            if entry.stage() != STAGE_MERGED {
+                stages[entry.stage() as usize - 1] = Some((entry.mode, entry.sha1));
+            }
+        }
+        let worktree = worktree_mode(path);
+        tracked.insert(path, Status::Unmerged { stages, worktree });
+    }
 
-        while stack.len() > 0 {
-            let path = stack.pop_front().unwrap();
-            for entry in fs::read_dir(&path).unwrap() {
-                let dir = entry.unwrap();
-                let path = dir.path();
-                if path.is_dir() {
-                    stack.push_back(path);
-                } else {
-                    let content = fs::read(&path).unwrap();
-                    let raw_file = RawFile {
-                        name: path,
-                        content,
+    let mut paths: BTreeSet<&[u8]> = repository.paths().collect();
+    paths.extend(index.entrys.iter().map(|e| &e.filepath[..]));
+    for path in paths {
+        if tracked.contains_key(path) {
+            continue;
+        }
+        let head = repository.get(path).map(|(mode, id)| (mode.bits(), id));
+        let entry = index.entry(path, STAGE_MERGED);
+        let (index, staged, unstaged, worktree) = match entry {
+            None => (None, Change::between(head, None), None, 0),
+            // added by `add -N`, which only promises the content: git shows it as
+            // unstaged, and without its id
+            Some(entry) if entry.intent_to_add() => {
+                let worktree = worktree_mode(path);
+                (None, None, Some(Change::Added), worktree)
+            }
+            Some(entry) => {
+                let index = Some((entry.mode, entry.sha1));
+                let (unstaged, worktree) = if deleted.contains(path) {
+                    (Some(Change::Deleted), 0)
+                } else if modified.contains(path) {
+                    let worktree = worktree_mode(path);
+                    let change = match worktree & S_IFMT == entry.mode & S_IFMT {
+                        true => Change::Modified,
+                        false => Change::TypeChanged,
                     };
-                    println!("{:?}", dir.path());
-                }
+                    (Some(change), worktree)
+                } else {
+                    (None, entry.mode)
+                };
+                (index, Change::between(head, index), unstaged, worktree)
             }
+        };
+        if staged.is_some() || unstaged.is_some() {
+            let status = Status::Tracked {
+                head,
+                index,
+                worktree,
+                staged,
+                unstaged,
+            };
+            tracked.insert(path, status);
         }
     }
+
+    let found = working_area.untracked(stage, ignore)?;
+    let mut statuses: Vec<(Vec<u8>, Status)> = tracked
+        .into_iter()
+        .map(|(path, status)| (path.to_vec(), status))
+        .collect();
+    statuses.extend(found.untracked.into_iter().map(|p| (p, Status::Untracked)));
+    if ignored {
+        statuses.extend(found.ignored.into_iter().map(|p| (p, Status::Ignored)));
+    }
+    Ok(statuses)
 }
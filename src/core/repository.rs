@@ -1,17 +1,36 @@
-use std::path::Path;
+//! the tree of HEAD, as flat as the index
 
-use crate::context::GitContext;
+use std::collections::BTreeMap;
+use std::path::Path;
 
-use super::File;
+use crate::{
+    object::{ObjectDatabase, ObjectId, ObjectMode},
+    refs::resolve_head,
+    tree::{commit_tree, flatten},
+    GitError,
+};
 
-#[derive(Clone, Debug)]
-pub struct Repository<'a> {
-    root: File,
-    context: &'a GitContext,
+#[derive(Clone, Debug, Default)]
+pub struct Repository {
+    files: BTreeMap<Vec<u8>, (ObjectMode, ObjectId)>,
 }
 
-impl<'a> Repository<'a> {
-    pub fn new(path: &Path, context: &'a GitContext) -> Repository<'a> {
-        todo!()
+impl Repository {
+    /// the tree of HEAD of the repository at `git_dir`, empty on an unborn branch
+    pub fn new(git_dir: &Path, database: &ObjectDatabase) -> Result<Repository, GitError> {
+        let files = match resolve_head(git_dir)? {
+            Some(head) => flatten(database, &commit_tree(database, &head)?)?,
+            None => BTreeMap::new(),
+        };
+        Ok(Self { files })
+    }
+
+    pub fn get(&self, path: &[u8]) -> Option<(ObjectMode, ObjectId)> {
+        self.files.get(path).copied()
     }
-}
\ No newline at end of file
+
+    /// every file, in the order of the index
+    pub fn paths(&self) -> impl Iterator<Item = &[u8]> {
+        self.files.keys().map(Vec::as_slice)
+    }
+}
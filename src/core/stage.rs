@@ -1,17 +1,73 @@
-use std::path::Path;
+//! the index, between HEAD and the working area
 
-use crate::context::GitContext;
+use std::io;
+use std::path::{Path, PathBuf};
 
-use super::File;
+use crate::{
+    attributes::convert::Convert,
+    index::{refresh::Refresh, stage::STAGE_MERGED, Index, INDEX_SIGNATURE},
+    utils::hash::ObjectFormat,
+    GitError,
+};
 
 #[derive(Clone, Debug)]
-pub struct Stage<'a> {
-    root: File,
-    context: &'a GitContext,
+pub struct Stage {
+    index: Index,
+    path: PathBuf,
+    format: ObjectFormat,
 }
 
-impl<'a> Stage<'a> {
-    pub fn new(path: &Path, context: &'a GitContext) -> Stage<'a> {
-        todo!()
+impl Stage {
+    /// the index of the repository at `git_dir`, empty if there is none yet
+    pub fn new(git_dir: &Path, format: ObjectFormat) -> Result<Stage, GitError> {
+        let path = git_dir.join("index");
+        let index = match Index::open(&path, format) {
+            Ok(index) => index,
+            Err(GitError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                Index::new(INDEX_SIGNATURE, 2, 0, vec![], vec![], None)
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            index,
+            path,
+            format,
+        })
     }
-}
\ No newline at end of file
+
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    /// whether `path` has an entry, at any stage
+    pub fn is_tracked(&self, path: &[u8]) -> bool {
+        !self.index.entries_of(path).is_empty()
+    }
+
+    /// whether something under the directory `dir` has an entry
+    pub fn has_dir(&self, dir: &[u8]) -> bool {
+        let mut prefix = dir.to_vec();
+        prefix.push(b'/');
+        let i = self
+            .index
+            .position(&prefix, STAGE_MERGED)
+            .unwrap_or_else(|i| i);
+        self.index
+            .entrys
+            .get(i)
+            .is_some_and(|entry| entry.filepath.starts_with(&prefix))
+    }
+
+    /// compare the entries with the working area at `root`, see `Index::refresh`. like
+    /// git, what hashing found out is written back when the index can be locked
+    pub fn refresh(&mut self, root: &Path, convert: &mut Convert) -> Result<Refresh, GitError> {
+        let refresh = self.index.refresh(root, self.format, convert)?;
+        if refresh.hashed > 0 && self.path.exists() {
+            // the stat data is only a cache, someone else may hold the lock
+            if let Err(e) = self.index.write(&self.path, self.format) {
+                info!("index not refreshed: {}", e);
+            }
+        }
+        Ok(refresh)
+    }
+}
@@ -1,61 +1,373 @@
+//! the files of the working tree, found lazily: walking only stats them, their content
+//! is read when asked for
+
 use std::{
-    collections::VecDeque,
-    fs,
+    fs::{self, FileType, Metadata},
+    io::{self, Read},
     path::{Path, PathBuf},
 };
 
-use crate::{context::GitContext, core::RawFile};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::{
+    attributes::convert::Convert,
+    ignore::Ignore,
+    object::{Blob, Object, ObjectDatabase, ObjectId},
+    utils::path::{bytes_to_path, path_to_bytes},
+    GitError, REPO_NAME,
+};
 
-use super::{Dir, File};
+use super::stage::Stage;
 
 #[derive(Clone, Debug)]
 pub struct WorkingArea {
-    root: File,
+    root: PathBuf,
 }
 
 impl WorkingArea {
     pub fn new(tree_root: PathBuf) -> WorkingArea {
-        let root = add_child(&tree_root);
-        Self { root }
+        Self { root: tree_root }
     }
-}
 
-pub fn add_child(path: &PathBuf) -> File {
-    if path.is_dir() {
-        let res = (fs::read_dir(&path).unwrap())
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// every file, symlink and directory under the root except the repository
+    /// directories. symlinks are not followed, and the entries of a directory are
+    /// sorted by name
+    pub fn walk(&self) -> Walk {
+        let iter = WalkDir::new(&self.root)
+            .min_depth(1)
+            .sort_by_file_name()
             .into_iter()
-            .map(|entry| {
-                let dir = entry.unwrap();
-                let path = dir.path();
-                let child = add_child(&path);
-                child
-            })
-            .collect();
-        let dir = Dir {
-            name: path.clone(),
-            children: res,
-        };
+            .filter_entry(not_repository as fn(&DirEntry) -> bool);
+        Walk {
+            root: self.root.clone(),
+            iter,
+            ignore: None,
+        }
+    }
+
+    /// like `walk`, without what `ignore` ignores. ignored directories aren't entered
+    pub fn walk_unignored(&self, ignore: Ignore) -> Walk {
+        Walk {
+            ignore: Some(ignore),
+            ..self.walk()
+        }
+    }
+
+    /// the paths nothing is tracked at. like git, a directory with nothing tracked in
+    /// it is given once as `dir/`: untracked if it has an untracked file, or else
+    /// ignored if it has an ignored one. both lists are sorted
+    pub fn untracked(&self, stage: &Stage, ignore: &mut Ignore) -> Result<Untracked, GitError> {
+        let mut found = Untracked::default();
+        let mut walk = self.walk();
+        while let Some(file) = walk.next() {
+            let file = file?;
+            let path = path_to_bytes(file.path()).into_owned();
+            if stage.is_tracked(&path) {
+                // a submodule, if it's a directory
+                if file.is_dir() {
+                    walk.skip_current_dir();
+                }
+            } else if file.is_dir() {
+                if !stage.has_dir(&path) {
+                    walk.skip_current_dir();
+                    self.untracked_dir(path, ignore, &mut found)?;
+                }
+            } else if ignore.is_ignored(&path, false)? {
+                found.ignored.push(path);
+            } else {
+                found.untracked.push(path);
+            }
+        }
+        found.untracked.sort();
+        found.ignored.sort();
+        Ok(found)
+    }
+
+    fn untracked_dir(
+        &self,
+        dir: Vec<u8>,
+        ignore: &mut Ignore,
+        found: &mut Untracked,
+    ) -> Result<(), GitError> {
+        let ignored = ignore.is_ignored(&dir, true)?;
+        let mut inside = Untracked::default();
+        if !ignored {
+            for entry in fs::read_dir(self.root.join(bytes_to_path(&dir)))? {
+                let entry = entry?;
+                if entry.file_name() == REPO_NAME {
+                    continue;
+                }
+                let mut path = dir.clone();
+                path.push(b'/');
+                path.extend_from_slice(&path_to_bytes(Path::new(&entry.file_name())));
+                if entry.file_type()?.is_dir() {
+                    self.untracked_dir(path, ignore, &mut inside)?;
+                } else if ignore.is_ignored(&path, false)? {
+                    inside.ignored.push(path);
+                } else {
+                    inside.untracked.push(path);
+                }
+            }
+        }
+
+        let mut shown = dir;
+        shown.push(b'/');
+        if !inside.untracked.is_empty() {
+            found.untracked.push(shown);
+            found.ignored.append(&mut inside.ignored);
+        } else if ignored || !inside.ignored.is_empty() {
+            found.ignored.push(shown);
+        }
+        Ok(())
+    }
+}
 
-        return File::Dir(dir);
-    } else {
-        let content = fs::read(&path).unwrap();
-        let raw_file = RawFile {
-            name: path.clone(),
-            content,
+/// see `WorkingArea::untracked`, directories end with `/`
+#[derive(Clone, Debug, Default)]
+pub struct Untracked {
+    pub untracked: Vec<Vec<u8>>,
+    pub ignored: Vec<Vec<u8>>,
+}
+
+fn not_repository(entry: &DirEntry) -> bool {
+    entry.file_name() != REPO_NAME
+}
+
+pub struct Walk {
+    root: PathBuf,
+    iter: walkdir::FilterEntry<walkdir::IntoIter, fn(&DirEntry) -> bool>,
+    ignore: Option<Ignore>,
+}
+
+impl Walk {
+    /// don't descend into the directory just yielded
+    pub fn skip_current_dir(&mut self) {
+        self.iter.skip_current_dir();
+    }
+
+    fn is_ignored(&mut self, file: &WorkingFile) -> Result<bool, GitError> {
+        match self.ignore.as_mut() {
+            Some(ignore) => ignore.is_ignored(&path_to_bytes(file.path()), file.is_dir()),
+            None => Ok(false),
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<WorkingFile, GitError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let file = match self.next_file()? {
+                Ok(file) => file,
+                Err(e) => return Some(Err(e)),
+            };
+            match self.is_ignored(&file) {
+                Ok(false) => return Some(Ok(file)),
+                Ok(true) if file.is_dir() => self.skip_current_dir(),
+                Ok(true) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl Walk {
+    fn next_file(&mut self) -> Option<Result<WorkingFile, GitError>> {
+        let entry = match self.iter.next()? {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(io::Error::from(e).into())),
+        };
+        // not followed, so this is the metadata of a symlink itself
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => return Some(Err(io::Error::from(e).into())),
         };
-        return File::RawFile(raw_file);
+        let path = entry
+            .path()
+            .strip_prefix(&self.root)
+            .unwrap_or(entry.path())
+            .to_path_buf();
+        Some(Ok(WorkingFile {
+            path,
+            full_path: entry.into_path(),
+            metadata,
+        }))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkingFile {
+    /// relative to the root of the working area
+    path: PathBuf,
+    full_path: PathBuf,
+    metadata: Metadata,
+}
+
+impl WorkingFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn full_path(&self) -> &Path {
+        &self.full_path
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.metadata.file_type()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type().is_dir()
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type().is_symlink()
+    }
+
+    /// the bytes git stores for the file, for a symlink they are the target path. a
+    /// file is read as it's consumed
+    pub fn open(&self) -> io::Result<Box<dyn Read>> {
+        if self.is_symlink() {
+            let target = fs::read_link(&self.full_path)?;
+            Ok(Box::new(io::Cursor::new(
+                path_to_bytes(&target).into_owned(),
+            )))
+        } else {
+            Ok(Box::new(fs::File::open(&self.full_path)?))
+        }
+    }
+
+    /// store the file as a blob. unless it has to be converted, it's never held in
+    /// memory as a whole
+    pub fn write_blob(
+        &self,
+        database: &ObjectDatabase,
+        convert: &mut Convert,
+    ) -> Result<ObjectId, GitError> {
+        let path = path_to_bytes(&self.path);
+        if !self.is_symlink() && !convert.converts(&path)? {
+            return database.write_blob_file(&self.full_path);
+        }
+        let mut content = Vec::new();
+        self.open()?.read_to_end(&mut content)?;
+        if !self.is_symlink() {
+            content = convert.to_git(&path, content)?;
+        }
+        database.write(&Object::Blob(Blob::new(content)))
     }
 }
 
 #[cfg(test)]
-mod tests{
+mod tests {
+    use crate::{
+        attributes::{AttributeList, Attributes, ATTRIBUTES_FILE},
+        config::Config,
+        ignore::PatternList,
+        utils::hash::ObjectFormat,
+    };
 
     use super::*;
 
+    fn read(file: &WorkingFile) -> Vec<u8> {
+        let mut content = Vec::new();
+        file.open().unwrap().read_to_end(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join(REPO_NAME).join("objects")).unwrap();
+        fs::create_dir_all(root.join("src/core")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("src/core/b.txt"), "b").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("src", root.join("link")).unwrap();
+
+        let working_area = WorkingArea::new(root.to_path_buf());
+        let files: Vec<WorkingFile> = working_area.walk().map(Result::unwrap).collect();
+        let paths: Vec<&Path> = files.iter().map(|f| f.path()).collect();
+        let mut expected = vec!["a.txt", "src", "src/core", "src/core/b.txt"];
+        if cfg!(unix) {
+            expected.insert(1, "link");
+        }
+        assert_eq!(paths, expected.iter().map(Path::new).collect::<Vec<_>>());
+
+        let file = |name: &str| files.iter().find(|f| f.path() == Path::new(name)).unwrap();
+        assert!(file("src").is_dir());
+        assert_eq!(file("src/core/b.txt").metadata().len(), 1);
+        assert_eq!(read(file("src/core/b.txt")), b"b");
+        if cfg!(unix) {
+            let link = file("link");
+            assert!(link.is_symlink() && !link.is_dir());
+            assert_eq!(read(link), b"src");
+        }
+
+        let mut walk = working_area.walk();
+        let mut paths = Vec::new();
+        while let Some(file) = walk.next() {
+            let file = file.unwrap();
+            if file.path() == Path::new("src") {
+                walk.skip_current_dir();
+            }
+            paths.push(file.path);
+        }
+        assert!(!paths.contains(&PathBuf::from("src/core")));
+
+        let exclude = PatternList::parse(String::new(), Vec::new(), b"/src\n*.txt\n!b.txt\n");
+        let ignore = Ignore::new(root.to_path_buf(), vec![exclude]);
+        let paths: Vec<PathBuf> = working_area
+            .walk_unignored(ignore)
+            .map(|file| file.unwrap().path)
+            .collect();
+        let mut expected = vec![];
+        if cfg!(unix) {
+            expected.push(PathBuf::from("link"));
+        }
+        assert_eq!(paths, expected);
+    }
+
     #[test]
-    fn test(){
-        let path = Path::new("src");
-        let working_area= WorkingArea::new(path.to_path_buf());
-        dbg!(working_area);
+    fn test_write_blob() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let database =
+            ObjectDatabase::new(root.join(REPO_NAME).join("objects"), ObjectFormat::Sha1);
+        fs::create_dir_all(root.join(REPO_NAME).join("objects")).unwrap();
+        fs::write(root.join(ATTRIBUTES_FILE), "*.txt text\n").unwrap();
+        fs::write(root.join("a.bin"), "a\r\n").unwrap();
+        fs::write(root.join("a.txt"), "a\r\n").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a.txt", root.join("link")).unwrap();
+        let attributes = Attributes::new(root.to_path_buf(), AttributeList::default(), vec![]);
+        let mut convert = Convert::new(root, attributes, &Config::default());
+
+        let mut expected = vec![("a.bin", &b"a\r\n"[..]), ("a.txt", b"a\n")];
+        if cfg!(unix) {
+            expected.push(("link", b"a.txt"));
+        }
+        let working_area = WorkingArea::new(root.to_path_buf());
+        for (name, content) in expected {
+            let file = working_area
+                .walk()
+                .map(Result::unwrap)
+                .find(|f| f.path() == Path::new(name))
+                .unwrap();
+            let id = file.write_blob(&database, &mut convert).unwrap();
+            match database.read(&id).unwrap() {
+                Object::Blob(blob) => assert_eq!(blob.content, content, "{}", name),
+                object => panic!("expect a blob, got {:?}", object),
+            }
+        }
     }
-}
\ No newline at end of file
+}
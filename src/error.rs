@@ -1,12 +1,113 @@
+//! the errors of git-rs, corrupt data is reported through them instead of panics
+
+use std::path::{Path, PathBuf};
+
 use thiserror::Error;
 
-///! currently, we will ignore the errors with unwrap
+use crate::object::{tree::ObjectType, ObjectId};
+
 #[derive(Error, Debug)]
 pub enum GitError {
     #[error("create `{0}` without permission")]
     PermissionDenied(String),
     #[error("repo corrupt, please check `{0}`")]
     CorruptRepo(String),
+    #[error("not a git-rs repository (or any of the parent directories)")]
+    NotRepository,
+    #[error("object `{0}` not found")]
+    ObjectNotFound(String),
+    #[error("short object id `{prefix}` is ambiguous, the candidates are:\n{}", candidate_list(.candidates))]
+    AmbiguousObjectName {
+        prefix: String,
+        candidates: Vec<ObjectId>,
+    },
+    #[error("object `{id}` is a {actual}, not a {expected}")]
+    UnexpectedType {
+        id: ObjectId,
+        expected: ObjectType,
+        actual: ObjectType,
+    },
+    #[error("bad tree entry `{}`: {reason}", String::from_utf8_lossy(.name))]
+    BadTreeEntry { name: Vec<u8>, reason: &'static str },
+    /// an object or a file of the repository which can't be decoded, `offset` is
+    /// counted in the decompressed content for objects
+    #[error("{} is corrupt at byte {offset}: {reason}", location(.id, .path))]
+    Decode {
+        id: Option<ObjectId>,
+        path: Option<PathBuf>,
+        offset: u64,
+        reason: String,
+    },
+    #[error("index version {0} is not supported, only 2 to 4")]
+    UnsupportedIndexVersion(u32),
+    #[error("filter `{name}` failed on `{path}`: {reason}")]
+    Filter {
+        name: String,
+        path: String,
+        reason: String,
+    },
+    #[error("invalid path `{}`: {reason}", String::from_utf8_lossy(.path))]
+    InvalidPath { path: Vec<u8>, reason: &'static str },
+    #[error("local changes to these files would be overwritten:\n{}", path_list(.0))]
+    LocalChanges(Vec<Vec<u8>>),
+    #[error("these untracked files would be overwritten:\n{}", path_list(.0))]
+    UntrackedOverwritten(Vec<Vec<u8>>),
+    #[error("invalid reference `{0}`")]
+    InvalidRef(String),
+    #[error("branch `{0}` already exists")]
+    BranchExists(String),
+    #[error("`{0}` is outside the repository")]
+    OutsideRepository(PathBuf),
+    /// `check-ignore` printed nothing, which is reported by the exit code alone
+    #[error("none of the paths is ignored")]
+    NothingIgnored,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
     #[error("unknown data store error")]
     Unknown,
-}
\ No newline at end of file
+}
+
+impl GitError {
+    pub fn corrupt_object(id: ObjectId, offset: usize, reason: impl Into<String>) -> Self {
+        Self::Decode {
+            id: Some(id),
+            path: None,
+            offset: offset as u64,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn corrupt_file(path: &Path, offset: usize, reason: impl Into<String>) -> Self {
+        Self::Decode {
+            id: None,
+            path: Some(path.to_path_buf()),
+            offset: offset as u64,
+            reason: reason.into(),
+        }
+    }
+}
+
+fn location(id: &Option<ObjectId>, path: &Option<PathBuf>) -> String {
+    match (id, path) {
+        (Some(id), Some(path)) => format!("object `{}`({})", id, path.display()),
+        (Some(id), None) => format!("object `{}`", id),
+        (None, Some(path)) => format!("`{}`", path.display()),
+        (None, None) => "data".to_string(),
+    }
+}
+
+fn candidate_list(candidates: &[ObjectId]) -> String {
+    candidates
+        .iter()
+        .map(|id| format!("  {}", id))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn path_list(paths: &[Vec<u8>]) -> String {
+    paths
+        .iter()
+        .map(|path| format!("  {}", String::from_utf8_lossy(path)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
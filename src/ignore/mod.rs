@@ -0,0 +1,374 @@
+//! which untracked files to leave alone, from the `.gitignore` of every directory,
+//! `info/exclude` and the file of `core.excludesFile`
+//!
+//! a path is matched against the patterns from the closest `.gitignore` up to the
+//! root one, then `info/exclude`, then `core.excludesFile`, and in each of them the
+//! last matching pattern wins. the files in an ignored directory are ignored, no
+//! matter what the patterns inside say, since git never looks into the directory
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::Config,
+    utils::{path::bytes_to_path, wildmatch::wildmatch},
+    GitError,
+};
+
+pub const IGNORE_FILE: &str = ".gitignore";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    /// as written in the file, for `check-ignore -v`
+    pub original: Vec<u8>,
+    /// the line it's written on, starts from 1
+    pub line: usize,
+    /// starts with `!`, a matching path is not ignored
+    pub negative: bool,
+    /// ends with `/`, it only matches directories
+    pub dir_only: bool,
+    /// there's no `/` in it, so it matches the name in any directory
+    basename: bool,
+    /// the pattern without `!` and the trailing `/`
+    glob: Vec<u8>,
+}
+
+impl Pattern {
+    /// `None` for blank lines and comments
+    pub fn parse(line: &[u8], number: usize) -> Option<Self> {
+        let line = trim_trailing_spaces(line);
+        if line.is_empty() || line[0] == b'#' {
+            return None;
+        }
+        let (negative, mut glob) = match line.strip_prefix(b"!") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let dir_only = glob.last() == Some(&b'/');
+        if dir_only {
+            glob = &glob[..glob.len() - 1];
+        }
+        if glob.is_empty() {
+            return None;
+        }
+        Some(Self {
+            original: line.to_vec(),
+            line: number,
+            negative,
+            dir_only,
+            basename: !glob.contains(&b'/'),
+            glob: glob.to_vec(),
+        })
+    }
+
+    /// whether `path`, which is inside the directory `base`, matches. both are relative
+    /// to the root, `base` is empty for the root
+    pub fn matches(&self, base: &[u8], path: &[u8], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.basename {
+            let name = match path.iter().rposition(|&c| c == b'/') {
+                Some(slash) => &path[slash + 1..],
+                None => path,
+            };
+            return wildmatch(&self.glob, name, false);
+        }
+        // relative to the directory of the pattern, a leading `/` only anchors it
+        let glob = self.glob.strip_prefix(b"/").unwrap_or(&self.glob);
+        let name = if base.is_empty() {
+            path
+        } else {
+            match path.strip_prefix(base) {
+                Some([b'/', name @ ..]) => name,
+                _ => return false,
+            }
+        };
+        wildmatch(glob, name, true)
+    }
+}
+
+/// trailing spaces are cut, unless escaped with `\`
+fn trim_trailing_spaces(line: &[u8]) -> &[u8] {
+    let mut end = line.len();
+    let mut i = 0;
+    let mut last_space = None;
+    while i < line.len() {
+        match line[i] {
+            b' ' => {
+                last_space.get_or_insert(i);
+            }
+            b'\\' => {
+                i += 1;
+                last_space = None;
+            }
+            _ => last_space = None,
+        }
+        i += 1;
+    }
+    if let Some(space) = last_space {
+        end = space;
+    }
+    &line[..end]
+}
+
+/// the patterns of one file
+#[derive(Clone, Debug, Default)]
+pub struct PatternList {
+    /// where they come from, as shown by `check-ignore -v`
+    pub source: String,
+    /// the directory the patterns are relative to, empty for the root
+    base: Vec<u8>,
+    patterns: Vec<Pattern>,
+}
+
+impl PatternList {
+    pub fn parse(source: String, base: Vec<u8>, content: &[u8]) -> Self {
+        let content = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+        let patterns = content
+            .split(|&c| c == b'\n')
+            .enumerate()
+            .filter_map(|(i, line)| Pattern::parse(line, i + 1))
+            .collect();
+        Self {
+            source,
+            base,
+            patterns,
+        }
+    }
+
+    /// a missing file has no patterns
+    pub fn load(source: String, base: Vec<u8>, path: &Path) -> Result<Self, GitError> {
+        match fs::read(path) {
+            Ok(content) => Ok(Self::parse(source, base, &content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self {
+                source,
+                base,
+                patterns: Vec::new(),
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// the last pattern matching `path`
+    fn last_match(&self, path: &[u8], is_dir: bool) -> Option<&Pattern> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|p| p.matches(&self.base, path, is_dir))
+    }
+}
+
+/// the pattern deciding a path, and the file it's in
+#[derive(Clone, Copy, Debug)]
+pub struct Match<'a> {
+    pub source: &'a str,
+    pub pattern: &'a Pattern,
+}
+
+impl Match<'_> {
+    pub fn is_ignored(&self) -> bool {
+        !self.pattern.negative
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Ignore {
+    root: PathBuf,
+    /// the `.gitignore` of the directories looked at so far, by their path
+    dirs: HashMap<Vec<u8>, PatternList>,
+    /// `info/exclude`, then `core.excludesFile`
+    global: Vec<PatternList>,
+}
+
+impl Ignore {
+    /// for the working tree at `root`, whose repository is `git_dir`
+    pub fn open(root: &Path, git_dir: &Path) -> Result<Self, GitError> {
+        let config = Config::load(git_dir.join("config"))?;
+        let exclude = git_dir.join("info").join("exclude");
+        let source = match exclude.strip_prefix(root) {
+            Ok(relative) => relative.display().to_string(),
+            Err(_) => exclude.display().to_string(),
+        };
+        let mut global = vec![PatternList::load(source, Vec::new(), &exclude)?];
+        if let Some(path) = excludes_file(&config) {
+            let source = path.display().to_string();
+            global.push(PatternList::load(source, Vec::new(), &path)?);
+        }
+        Ok(Self::new(root.to_path_buf(), global))
+    }
+
+    pub fn new(root: PathBuf, global: Vec<PatternList>) -> Self {
+        Self {
+            root,
+            dirs: HashMap::new(),
+            global,
+        }
+    }
+
+    /// whether `path`(relative to the root, separated by `/`) is ignored
+    pub fn is_ignored(&mut self, path: &[u8], is_dir: bool) -> Result<bool, GitError> {
+        Ok(self.matching(path, is_dir)?.is_some_and(|m| m.is_ignored()))
+    }
+
+    /// the pattern deciding whether `path` is ignored: its own last matching pattern, or
+    /// the one ignoring a directory above it. `None` if nothing matches
+    pub fn matching(&mut self, path: &[u8], is_dir: bool) -> Result<Option<Match<'_>>, GitError> {
+        let slashes: Vec<usize> = path
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c == b'/')
+            .map(|(i, _)| i)
+            .collect();
+
+        // read the `.gitignore` of each directory, unless a directory above is ignored
+        let mut ignored_dir = None;
+        self.load_dir(b"")?;
+        for &slash in slashes.iter() {
+            let dir = &path[..slash];
+            if self.last_match(dir, true).is_some_and(|m| m.is_ignored()) {
+                ignored_dir = Some(dir);
+                break;
+            }
+            self.load_dir(dir)?;
+        }
+        Ok(match ignored_dir {
+            Some(dir) => self.last_match(dir, true),
+            None => self.last_match(path, is_dir),
+        })
+    }
+
+    fn load_dir(&mut self, dir: &[u8]) -> Result<(), GitError> {
+        if self.dirs.contains_key(dir) {
+            return Ok(());
+        }
+        let file = self.root.join(bytes_to_path(dir)).join(IGNORE_FILE);
+        let source = if dir.is_empty() {
+            IGNORE_FILE.to_string()
+        } else {
+            format!("{}/{}", String::from_utf8_lossy(dir), IGNORE_FILE)
+        };
+        let patterns = PatternList::load(source, dir.to_vec(), &file)?;
+        self.dirs.insert(dir.to_vec(), patterns);
+        Ok(())
+    }
+
+    /// from the closest `.gitignore` to the farthest one, then the global ones
+    fn last_match(&self, path: &[u8], is_dir: bool) -> Option<Match<'_>> {
+        let parents = path
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, &c)| c == b'/')
+            .map(|(i, _)| &path[..i])
+            .chain([&b""[..]]);
+        let lists = parents
+            .filter_map(|dir| self.dirs.get(dir))
+            .chain(self.global.iter());
+        for list in lists {
+            if let Some(pattern) = list.last_match(path, is_dir) {
+                return Some(Match {
+                    source: &list.source,
+                    pattern,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// `core.excludesFile`, by default `$XDG_CONFIG_HOME/git/ignore` or
+/// `~/.config/git/ignore`
+fn excludes_file(config: &Config) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    match config.get("core.excludesFile") {
+        Some(path) => match (path.strip_prefix("~/"), home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(path)),
+        },
+        None => match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(xdg) if !xdg.is_empty() => Some(PathBuf::from(xdg).join("git").join("ignore")),
+            _ => home.map(|home| home.join(".config").join("git").join("ignore")),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignore(root: &Path, exclude: &str) -> Ignore {
+        let exclude = PatternList::parse("exclude".to_string(), Vec::new(), exclude.as_bytes());
+        Ignore::new(root.to_path_buf(), vec![exclude])
+    }
+
+    #[test]
+    fn test_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mut ignore = ignore(
+            root,
+            "# comment\n*.o\n!keep.o\n/build\ndoc/**/*.html\nlogs/\n\\#hash\ntrailing\\ \n",
+        );
+        let mut ignored = |path: &str, is_dir| ignore.is_ignored(path.as_bytes(), is_dir).unwrap();
+        assert!(ignored("a.o", false));
+        assert!(ignored("src/deep/a.o", false));
+        assert!(!ignored("keep.o", false));
+        assert!(!ignored("a.c", false));
+        // anchored to the root
+        assert!(ignored("build", true));
+        assert!(!ignored("src/build", true));
+        assert!(ignored("doc/index.html", false));
+        assert!(ignored("doc/a/b/index.html", false));
+        assert!(!ignored("src/doc/index.html", false));
+        // only directories
+        assert!(ignored("logs", true));
+        assert!(!ignored("logs", false));
+        assert!(ignored("src/logs/today", false));
+        assert!(ignored("#hash", false));
+        assert!(!ignored("# comment", false));
+        assert!(ignored("trailing ", false));
+        // in an ignored directory, even if a pattern says otherwise
+        assert!(ignored("build/keep.o", false));
+    }
+
+    #[test]
+    fn test_gitignore_levels() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::write(root.join(IGNORE_FILE), "*.log\ntarget/\n/gen\n").unwrap();
+        fs::write(
+            root.join("src").join(IGNORE_FILE),
+            "!debug.log\ngen/*\n!gen/keep\n",
+        )
+        .unwrap();
+        fs::write(root.join("target").join(IGNORE_FILE), "!*\n").unwrap();
+        let mut ignore = ignore(root, "*.tmp\n!a.log\n");
+
+        assert!(ignore.is_ignored(b"a.log", false).unwrap());
+        assert!(!ignore.is_ignored(b"src/debug.log", false).unwrap());
+        assert!(ignore.is_ignored(b"src/other.log", false).unwrap());
+        assert!(ignore.is_ignored(b"src/gen/out.rs", false).unwrap());
+        assert!(!ignore.is_ignored(b"src/gen/keep", false).unwrap());
+        assert!(ignore.is_ignored(b"src/a.tmp", false).unwrap());
+        // `/gen` only applies at the root
+        assert!(!ignore.is_ignored(b"src/gen", true).unwrap());
+
+        let found = ignore
+            .matching(b"target/debug/app", false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.source, IGNORE_FILE);
+        assert_eq!(found.pattern.original, b"target/");
+        assert_eq!(found.pattern.line, 2);
+        let found = ignore.matching(b"src/debug.log", false).unwrap().unwrap();
+        assert_eq!(found.source, "src/.gitignore");
+        assert!(!found.is_ignored());
+        assert!(ignore.matching(b"src/main.rs", false).unwrap().is_none());
+    }
+}
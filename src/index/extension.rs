@@ -0,0 +1,457 @@
+//! the extensions after the entries of the index, except the cached trees(`TREE`)
+//!
+//! extension := signature(4 bytes) | size(u32) | data
+//!
+//! readers must understand the extensions whose signature starts with `A` to `Z`
+//! only if they care, the others are required
+
+use bytes::{BufMut, BytesMut};
+
+use crate::object::ObjectId;
+use crate::utils::{ewah::EwahBitmap, pack::encode_ofs_distance};
+
+pub const RESOLVE_UNDO: &[u8; 4] = b"REUC";
+pub const UNTRACKED_CACHE: &[u8; 4] = b"UNTR";
+pub const SPLIT_INDEX_LINK: &[u8; 4] = b"link";
+pub const END_OF_INDEX_ENTRIES: &[u8; 4] = b"EOIE";
+pub const INDEX_ENTRY_OFFSETS: &[u8; 4] = b"IEOT";
+pub const FS_MONITOR: &[u8; 4] = b"FSMN";
+
+/// whether readers which don't understand the extension can go on without it
+pub fn is_optional(signature: &[u8; 4]) -> bool {
+    signature[0].is_ascii_uppercase()
+}
+
+#[derive(Clone, Debug)]
+pub enum Extension {
+    ResolveUndo(Vec<ResolveUndo>),
+    UntrackedCache(Box<UntrackedCache>),
+    SplitIndexLink(SplitIndexLink),
+    /// written again with the new offset and hash whenever the index is written
+    EndOfIndexEntries(EndOfIndexEntries),
+    /// written again with the new offsets whenever the index is written
+    IndexEntryOffsets(Vec<EntryOffsetBlock>),
+    FsMonitor(FsMonitor),
+    /// an optional extension we don't understand, written back as it is
+    Unknown {
+        signature: [u8; 4],
+        data: Vec<u8>,
+    },
+}
+
+impl Extension {
+    pub fn signature(&self) -> &[u8; 4] {
+        match self {
+            Self::ResolveUndo(_) => RESOLVE_UNDO,
+            Self::UntrackedCache(_) => UNTRACKED_CACHE,
+            Self::SplitIndexLink(_) => SPLIT_INDEX_LINK,
+            Self::EndOfIndexEntries(_) => END_OF_INDEX_ENTRIES,
+            Self::IndexEntryOffsets(_) => INDEX_ENTRY_OFFSETS,
+            Self::FsMonitor(_) => FS_MONITOR,
+            Self::Unknown { signature, .. } => signature,
+        }
+    }
+}
+
+// the data of the extension, without the signature and the size
+impl From<&Extension> for Vec<u8> {
+    fn from(extension: &Extension) -> Self {
+        let mut buf = BytesMut::with_capacity(64);
+        match extension {
+            Extension::ResolveUndo(entrys) => {
+                for entry in entrys.iter() {
+                    buf.put(&entry.path[..]);
+                    buf.put_u8(b'\0');
+                    for mode in entry.modes.iter() {
+                        buf.put(format!("{:o}", mode).as_bytes());
+                        buf.put_u8(b'\0');
+                    }
+                    for id in entry.ids.iter().flatten() {
+                        buf.put(id.as_bytes());
+                    }
+                }
+            }
+            Extension::UntrackedCache(cache) => put_untracked_cache(&mut buf, cache),
+            Extension::SplitIndexLink(link) => {
+                buf.put(link.shared_index.as_bytes());
+                if let Some((delete, replace)) = &link.bitmaps {
+                    put_bitmap(&mut buf, delete);
+                    put_bitmap(&mut buf, replace);
+                }
+            }
+            Extension::EndOfIndexEntries(end) => {
+                buf.put_u32(end.offset);
+                buf.put(&end.hash[..]);
+            }
+            Extension::IndexEntryOffsets(blocks) => {
+                buf.put_u32(INDEX_ENTRY_OFFSETS_VERSION);
+                for block in blocks.iter() {
+                    buf.put_u32(block.offset);
+                    buf.put_u32(block.count);
+                }
+            }
+            Extension::FsMonitor(monitor) => {
+                match &monitor.token {
+                    FsMonitorToken::Timestamp(nanos) => {
+                        buf.put_u32(1);
+                        buf.put_u64(*nanos);
+                    }
+                    FsMonitorToken::Opaque(token) => {
+                        buf.put_u32(2);
+                        buf.put(&token[..]);
+                        buf.put_u8(b'\0');
+                    }
+                }
+                let dirty: Vec<u8> = (&monitor.dirty).into();
+                buf.put_u32(dirty.len() as u32);
+                buf.put(&dirty[..]);
+            }
+            Extension::Unknown { data, .. } => buf.put(&data[..]),
+        }
+        buf.to_vec()
+    }
+}
+
+fn put_bitmap(buf: &mut BytesMut, bitmap: &EwahBitmap) {
+    let raw: Vec<u8> = bitmap.into();
+    buf.put(&raw[..]);
+}
+
+/// the stages a conflict had before it was resolved, so it can be brought back
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolveUndo {
+    pub path: Vec<u8>,
+    /// modes of stage 1 to 3, 0 for a missing stage
+    pub modes: [u32; 3],
+    pub ids: [Option<ObjectId>; 3],
+}
+
+/// what git knew about the untracked files of each directory, valid only as long as
+/// the stat data of the directory and its `.gitignore` stay the same
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UntrackedCache {
+    /// the environments the cache was made in, like `Location /repo, system Linux`
+    pub idents: Vec<Vec<u8>>,
+    pub info_exclude: StatData,
+    pub excludes_file: StatData,
+    pub info_exclude_id: ObjectId,
+    pub excludes_file_id: ObjectId,
+    pub dir_flags: u32,
+    /// the name of the per-directory ignore files, `.gitignore`
+    pub exclude_per_dir: Vec<u8>,
+    pub root: Option<UntrackedDir>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UntrackedDir {
+    pub name: Vec<u8>,
+    /// untracked files, and directories with a trailing `/`
+    pub untracked: Vec<Vec<u8>>,
+    pub dirs: Vec<UntrackedDir>,
+    pub check_only: bool,
+    /// the stat data of the directory, if the cached listing is valid
+    pub stat: Option<StatData>,
+    /// the id of the ignore file in the directory
+    pub exclude_id: Option<ObjectId>,
+}
+
+impl UntrackedDir {
+    /// the directory and the ones below it, depth first
+    pub fn walk(&self) -> Vec<&UntrackedDir> {
+        let mut dirs = vec![self];
+        for dir in self.dirs.iter() {
+            dirs.extend(dir.walk());
+        }
+        dirs
+    }
+
+    /// call `f` on the directory and the ones below it, depth first
+    pub fn for_each_mut(&mut self, f: &mut impl FnMut(&mut UntrackedDir)) {
+        f(self);
+        for dir in self.dirs.iter_mut() {
+            dir.for_each_mut(f);
+        }
+    }
+}
+
+/// the seconds and nanoseconds of ctime and mtime, dev, ino, uid, gid and size
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatData(pub [u32; 9]);
+
+// <idents><info/exclude stat><excludes file stat><ids><flags><exclude_per_dir><NUL>
+// <dir count>[<dirs><valid><check_only><exclude_id valid><stats><ids><NUL>]
+fn put_untracked_cache(buf: &mut BytesMut, cache: &UntrackedCache) {
+    let idents: Vec<u8> = cache
+        .idents
+        .iter()
+        .flat_map(|ident| ident.iter().copied().chain([b'\0']))
+        .collect();
+    buf.put(&varint(idents.len())[..]);
+    buf.put(&idents[..]);
+    put_stat(buf, &cache.info_exclude);
+    put_stat(buf, &cache.excludes_file);
+    buf.put(cache.info_exclude_id.as_bytes());
+    buf.put(cache.excludes_file_id.as_bytes());
+    buf.put_u32(cache.dir_flags);
+    buf.put(&cache.exclude_per_dir[..]);
+    buf.put_u8(b'\0');
+
+    let root = match &cache.root {
+        Some(root) => root,
+        None => {
+            buf.put(&varint(0)[..]);
+            return;
+        }
+    };
+    let dirs = root.walk();
+    buf.put(&varint(dirs.len())[..]);
+    put_untracked_dir(buf, root);
+
+    let positions = |f: fn(&UntrackedDir) -> bool| {
+        let dirs = dirs.iter().enumerate();
+        EwahBitmap::from_positions(dirs.filter(|(_, d)| f(d)).map(|(i, _)| i))
+    };
+    put_bitmap(buf, &positions(|d| d.stat.is_some()));
+    put_bitmap(buf, &positions(|d| d.check_only));
+    put_bitmap(buf, &positions(|d| d.exclude_id.is_some()));
+    for stat in dirs.iter().filter_map(|d| d.stat.as_ref()) {
+        put_stat(buf, stat);
+    }
+    for id in dirs.iter().filter_map(|d| d.exclude_id.as_ref()) {
+        buf.put(id.as_bytes());
+    }
+    // git ends it with a NUL in case of a truncated list of names
+    buf.put_u8(b'\0');
+}
+
+fn put_untracked_dir(buf: &mut BytesMut, dir: &UntrackedDir) {
+    buf.put(&varint(dir.untracked.len())[..]);
+    buf.put(&varint(dir.dirs.len())[..]);
+    buf.put(&dir.name[..]);
+    buf.put_u8(b'\0');
+    for name in dir.untracked.iter() {
+        buf.put(&name[..]);
+        buf.put_u8(b'\0');
+    }
+    for child in dir.dirs.iter() {
+        put_untracked_dir(buf, child);
+    }
+}
+
+fn put_stat(buf: &mut BytesMut, stat: &StatData) {
+    for v in stat.0 {
+        buf.put_u32(v);
+    }
+}
+
+fn varint(n: usize) -> Vec<u8> {
+    encode_ofs_distance(n as u64)
+}
+
+/// the index is split: the entries here are only the changes to a shared index in
+/// `sharedindex.<id>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitIndexLink {
+    pub shared_index: ObjectId,
+    /// the entries of the shared index which are deleted and replaced, by position
+    pub bitmaps: Option<(EwahBitmap, EwahBitmap)>,
+}
+
+/// where the extensions start, so they can be read before the entries
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EndOfIndexEntries {
+    pub offset: u32,
+    /// the hash of the signatures and sizes of the extensions before this one
+    pub hash: Vec<u8>,
+}
+
+pub const INDEX_ENTRY_OFFSETS_VERSION: u32 = 1;
+
+/// a block of entries that can be read apart from the others
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryOffsetBlock {
+    /// from the start of the file
+    pub offset: u32,
+    pub count: u32,
+}
+
+/// what the file system monitor reported last time, and the entries that changed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsMonitor {
+    pub token: FsMonitorToken,
+    /// the entries which may have changed since, by position
+    pub dirty: EwahBitmap,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FsMonitorToken {
+    /// version 1, nanoseconds since the epoch
+    Timestamp(u64),
+    /// version 2, whatever the hook gave us
+    Opaque(Vec<u8>),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use nom::IResult;
+
+    use crate::{
+        index::Index,
+        parser::decode::{decode_index_entry, parse_index},
+        utils::hash::ObjectFormat,
+        GitError,
+    };
+
+    use super::*;
+
+    fn read(name: &str) -> (Vec<u8>, Index) {
+        let content = fs::read(name).unwrap();
+        let index = parse_index(&content, ObjectFormat::Sha1, Path::new(name)).unwrap();
+        (content, index)
+    }
+
+    fn signatures(index: &Index) -> Vec<&[u8; 4]> {
+        index.extensions.iter().map(|e| e.signature()).collect()
+    }
+
+    #[test]
+    fn test_git_extensions() {
+        // written by git after resolving a conflict, with the untracked cache on
+        let (content, index) = read("data/index_extensions");
+        assert!(index.tree_extension.is_some());
+        assert_eq!(
+            signatures(&index),
+            [RESOLVE_UNDO, UNTRACKED_CACHE, END_OF_INDEX_ENTRIES]
+        );
+        match &index.extensions[0] {
+            Extension::ResolveUndo(entrys) => {
+                assert_eq!(entrys.len(), 1);
+                assert_eq!(entrys[0].path, b"a.txt");
+                assert_eq!(entrys[0].modes, [0o100644; 3]);
+                assert!(entrys[0].ids.iter().all(|id| id.is_some()));
+            }
+            e => panic!("expect REUC, got {:?}", e),
+        }
+        match &index.extensions[1] {
+            Extension::UntrackedCache(cache) => {
+                assert_eq!(cache.exclude_per_dir, b".gitignore");
+                let root = cache.root.as_ref().unwrap();
+                assert_eq!(root.untracked, [b"untracked.txt"]);
+                assert!(root.stat.is_some());
+                let names: Vec<_> = root.walk().iter().map(|d| d.name.clone()).collect();
+                assert_eq!(names, [&b""[..], b"src", b"sub"]);
+            }
+            e => panic!("expect UNTR, got {:?}", e),
+        }
+        assert_eq!(index.encode(ObjectFormat::Sha1), content);
+
+        // a split index, watched by a file system monitor
+        let (content, index) = read("data/index_split");
+        assert_eq!(signatures(&index), [SPLIT_INDEX_LINK, FS_MONITOR]);
+        match &index.extensions[0] {
+            Extension::SplitIndexLink(SplitIndexLink {
+                bitmaps: Some((delete, replace)),
+                ..
+            }) => {
+                assert_eq!(delete.iter().count(), 0);
+                assert_eq!(replace.iter().collect::<Vec<_>>(), [0, 1]);
+            }
+            e => panic!("expect link, got {:?}", e),
+        }
+        match &index.extensions[1] {
+            Extension::FsMonitor(monitor) => {
+                assert_eq!(monitor.token, FsMonitorToken::Opaque(b"token42".to_vec()));
+                assert!(monitor.dirty.contains(0));
+            }
+            e => panic!("expect FSMN, got {:?}", e),
+        }
+        assert_eq!(index.encode(ObjectFormat::Sha1), content);
+    }
+
+    #[test]
+    fn test_offsets_rewritten() {
+        let (_, mut index) = read("data/index2");
+        let n = index.entrys.len() as u32;
+        assert!(n > 2);
+        index.version = 4;
+        let stale = |offset, count| EntryOffsetBlock { offset, count };
+        index.extensions = vec![
+            Extension::Unknown {
+                signature: *b"ABCD",
+                data: b"opaque".to_vec(),
+            },
+            Extension::IndexEntryOffsets(vec![stale(0, 1), stale(0, n - 1)]),
+            Extension::EndOfIndexEntries(EndOfIndexEntries {
+                offset: 0,
+                hash: vec![0; 20],
+            }),
+        ];
+
+        let content = index.encode(ObjectFormat::Sha1);
+        let (_, decoded) = read_content(&content);
+        assert_eq!(
+            signatures(&decoded),
+            [INDEX_ENTRY_OFFSETS, b"ABCD", END_OF_INDEX_ENTRIES]
+        );
+        let blocks = match &decoded.extensions[0] {
+            Extension::IndexEntryOffsets(blocks) => blocks.clone(),
+            e => panic!("expect IEOT, got {:?}", e),
+        };
+        assert_eq!(blocks[0], stale(12, 1));
+        // the second block can be read on its own
+        let start = &content[blocks[1].offset as usize..];
+        let r: IResult<_, _> = decode_index_entry(start, ObjectFormat::Sha1, 4, b"");
+        assert_eq!(r.unwrap().1.filepath, index.entrys[1].filepath);
+        match &decoded.extensions[2] {
+            Extension::EndOfIndexEntries(end) => {
+                assert_eq!(&content[end.offset as usize..][..4], INDEX_ENTRY_OFFSETS)
+            }
+            e => panic!("expect EOIE, got {:?}", e),
+        }
+        assert_eq!(decoded.encode(ObjectFormat::Sha1), content);
+
+        // counts which don't add up are dropped
+        index.entrys.pop();
+        let (_, decoded) = read_content(&index.encode(ObjectFormat::Sha1));
+        assert_eq!(signatures(&decoded), [b"ABCD", END_OF_INDEX_ENTRIES]);
+    }
+
+    fn read_content(content: &[u8]) -> (Vec<u8>, Index) {
+        let index = parse_index(content, ObjectFormat::Sha1, Path::new("index")).unwrap();
+        (content.to_vec(), index)
+    }
+
+    #[test]
+    fn test_unknown_required_extension() {
+        let (_, mut index) = read("data/index");
+        index.extensions = vec![Extension::Unknown {
+            signature: *b"abcd",
+            data: vec![],
+        }];
+        let content = index.encode(ObjectFormat::Sha1);
+        let e = parse_index(&content, ObjectFormat::Sha1, Path::new("index")).unwrap_err();
+        assert!(
+            e.to_string().contains("unknown required extension"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn test_corrupt_bitmap() {
+        let (_, mut index) = read("data/index");
+        for words in [vec![(0xFFFF_FFFF << 1) | 1], vec![2 << 33, 1]] {
+            index.extensions = vec![Extension::FsMonitor(FsMonitor {
+                token: FsMonitorToken::Timestamp(0),
+                dirty: EwahBitmap::new(10, words, 0),
+            })];
+            let content = index.encode(ObjectFormat::Sha1);
+            match parse_index(&content, ObjectFormat::Sha1, Path::new("index")) {
+                Err(GitError::Decode { reason, .. }) => assert!(reason.contains("bad bitmap")),
+                r => panic!("expect a decode error, got {:?}", r),
+            }
+        }
+    }
+}
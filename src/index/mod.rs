@@ -0,0 +1,541 @@
+pub mod extension;
+pub mod refresh;
+pub mod stage;
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use bytes::{BufMut, BytesMut};
+
+use crate::object::{tree::verify_path, ObjectId, ObjectMode};
+use crate::parser::decode::parse_index;
+use crate::utils::hash::ObjectFormat;
+use crate::utils::pack::encode_ofs_distance;
+use crate::utils::path::bytes_to_path;
+use crate::GitError;
+
+use self::extension::{EndOfIndexEntries, EntryOffsetBlock, Extension};
+pub use self::stage::Conflict;
+
+#[derive(Clone, Debug)]
+pub struct Index {
+    pub desc: u32,
+    pub version: u32,
+    pub num_entrys: u32,
+    pub entrys: Vec<IndexEntry>,
+    pub tree_extension: Option<TreeExtension>, // recursive, layer first structure
+    /// the other extensions, in the order they are read
+    pub extensions: Vec<Extension>,
+    pub checksum: Vec<u8>,
+    /// the mtime of the file it was read from, packed like the mtime of the entries.
+    /// entries not older than it may have changed without changing their stat data
+    pub timestamp: Option<i64>,
+}
+
+impl Index {
+    pub fn new(
+        desc: u32,
+        version: u32,
+        num_entrys: u32,
+        entrys: Vec<IndexEntry>,
+        checksum: Vec<u8>,
+        tree_extension: Option<TreeExtension>,
+    ) -> Self {
+        Self {
+            desc,
+            version,
+            num_entrys,
+            entrys,
+            checksum,
+            tree_extension,
+            extensions: Vec::new(),
+            timestamp: None,
+        }
+    }
+
+    /// read the index file at `path`
+    pub fn open(path: &Path, format: ObjectFormat) -> Result<Self, GitError> {
+        let mut file = fs::File::open(path)?;
+        let meta = file.metadata()?;
+        let mut content = Vec::with_capacity(meta.len() as usize);
+        file.read_to_end(&mut content)?;
+        let mut index = parse_index(&content, format, path)?;
+        index.timestamp = Some(refresh::packed_mtime(&meta));
+        Ok(index)
+    }
+
+    /// the ids of the merged paths, conflicted ones are left out(see `conflicts`)
+    pub fn entry_map(&self) -> HashMap<PathBuf, ObjectId> {
+        self.entrys
+            .iter()
+            .filter(|v| v.stage() == stage::STAGE_MERGED)
+            .map(|v| (bytes_to_path(&v.filepath).into_owned(), v.sha1))
+            .collect()
+    }
+
+    /// the whole index file: header, entries, extensions and the trailing checksum,
+    /// which is a hash of `format` over everything before it.
+    ///
+    /// the extensions are written in the order git writes them, and the offsets in
+    /// `EOIE` and `IEOT` are worked out again
+    pub fn encode(&self, format: ObjectFormat) -> Vec<u8> {
+        let version = self.written_version();
+        let mut buf = BytesMut::with_capacity(12 + self.entrys.len() * 72);
+        buf.put_u32(INDEX_SIGNATURE);
+        buf.put_u32(version);
+        buf.put_u32(self.entrys.len() as u32);
+
+        let mut counts = self.entry_blocks().unwrap_or_default().into_iter();
+        let mut blocks = Vec::new();
+        let mut left = 0;
+        let mut previous: &[u8] = b"";
+        let mut unrelated;
+        for entry in self.entrys.iter() {
+            if left == 0 {
+                if let Some(count) = counts.next() {
+                    blocks.push(EntryOffsetBlock {
+                        offset: buf.len() as u32,
+                        count,
+                    });
+                    left = count;
+                    // blocks are read apart, so like git we spoil the first byte of the
+                    // previous path to have nothing in common with it
+                    unrelated = previous.to_vec();
+                    if let Some(c) = unrelated.first_mut() {
+                        *c = b'\0';
+                    }
+                    previous = &unrelated;
+                }
+            }
+            left = left.saturating_sub(1);
+            entry.encode(version, previous, &mut buf);
+            previous = &entry.filepath;
+        }
+
+        let end_of_entries = buf.len() as u32;
+        // the signature and size of every extension, for `EOIE`
+        let mut headers = BytesMut::new();
+        let mut put_extension = |buf: &mut BytesMut, signature: &[u8], data: &[u8]| {
+            headers.put(signature);
+            headers.put_u32(data.len() as u32);
+            buf.put(signature);
+            buf.put_u32(data.len() as u32);
+            buf.put(data);
+        };
+        if !blocks.is_empty() {
+            let offsets: Vec<u8> = (&Extension::IndexEntryOffsets(blocks)).into();
+            put_extension(&mut buf, extension::INDEX_ENTRY_OFFSETS, &offsets);
+        }
+        let extensions = || self.extensions.iter();
+        for link in extensions().filter(|e| matches!(e, Extension::SplitIndexLink(_))) {
+            let data: Vec<u8> = link.into();
+            put_extension(&mut buf, link.signature(), &data);
+        }
+        if let Some(tree_extension) = &self.tree_extension {
+            let tree: Vec<u8> = tree_extension.into();
+            put_extension(&mut buf, b"TREE", &tree);
+        }
+        for extension in extensions() {
+            if let Extension::SplitIndexLink(_)
+            | Extension::IndexEntryOffsets(_)
+            | Extension::EndOfIndexEntries(_) = extension
+            {
+                continue;
+            }
+            let data: Vec<u8> = extension.into();
+            put_extension(&mut buf, extension.signature(), &data);
+        }
+        if extensions().any(|e| matches!(e, Extension::EndOfIndexEntries(_))) {
+            let end = Extension::EndOfIndexEntries(EndOfIndexEntries {
+                offset: end_of_entries,
+                hash: format.hash(&headers),
+            });
+            let data: Vec<u8> = (&end).into();
+            buf.put(&extension::END_OF_INDEX_ENTRIES[..]);
+            buf.put_u32(data.len() as u32);
+            buf.put(&data[..]);
+        }
+
+        let checksum = format.hash(&buf);
+        buf.put(&checksum[..]);
+        buf.to_vec()
+    }
+
+    /// keep the header in step with the entries, and drop the offsets which no longer
+    /// fit them
+    fn entries_changed(&mut self) {
+        self.num_entrys = self.entrys.len() as u32;
+        self.extensions
+            .retain(|e| !matches!(e, Extension::IndexEntryOffsets(_)));
+    }
+
+    /// the number of entries in each block of `IEOT`, if there is one which still fits
+    /// the entries
+    fn entry_blocks(&self) -> Option<Vec<u32>> {
+        let blocks = self.extensions.iter().find_map(|e| match e {
+            Extension::IndexEntryOffsets(blocks) => Some(blocks),
+            _ => None,
+        })?;
+        let counts: Vec<u32> = blocks.iter().map(|b| b.count).collect();
+        let total: u64 = counts.iter().map(|&n| n as u64).sum();
+        if total != self.entrys.len() as u64 || counts.contains(&0) {
+            warn!("the entry offset table doesn't fit the entries, dropped it");
+            return None;
+        }
+        Some(counts)
+    }
+
+    /// the version `encode` writes: extended flags don't fit in version 2, so such an
+    /// index is written as version 3, like git does
+    pub fn written_version(&self) -> u32 {
+        if self.version < 3 && self.entrys.iter().any(|e| e.extended_flags != 0) {
+            3
+        } else {
+            self.version
+        }
+    }
+
+    /// replace the index file at `path` through `<path>.lock`, so readers never see
+    /// a half written index
+    pub fn write(&self, path: &Path, format: ObjectFormat) -> Result<(), GitError> {
+        let mut lock_name = path.as_os_str().to_owned();
+        lock_name.push(".lock");
+        let lock = PathBuf::from(lock_name);
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&lock) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(GitError::Io(io::Error::new(
+                    e.kind(),
+                    format!("{} exists, is another git-rs running?", lock.display()),
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let written = file
+            .write_all(&self.encode(format))
+            .and_then(|_| file.sync_all())
+            .and_then(|_| fs::rename(&lock, path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&lock);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+/// `DIRC`, the first 4 bytes of every index file
+pub const INDEX_SIGNATURE: u32 = 0x4449_5243;
+
+/// the flags of an entry: assume-valid, extended, 2 bits of stage and 12 bits of the
+/// length of the path
+pub const FLAG_ASSUME_VALID: u16 = 0x8000;
+pub const FLAG_EXTENDED: u16 = 0x4000;
+pub const FLAG_STAGE_MASK: u16 = 0x3000;
+pub const FLAG_STAGE_SHIFT: u16 = 12;
+/// longer paths are stored as this length
+pub const FLAG_NAME_MASK: u16 = 0x0FFF;
+
+/// the versions we can read and write. 3 adds extended flags, 4 drops the padding
+/// and compresses each path against the one before it
+pub const SUPPORTED_VERSIONS: std::ops::RangeInclusive<u32> = 2..=4;
+
+/// the extended flags of version 3 and later, the other bits are reserved
+pub const EXTENDED_FLAG_SKIP_WORKTREE: u16 = 0x4000;
+pub const EXTENDED_FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+/// the NULs after a path of `path_len` bytes: at least one ends the path, and before
+/// version 4 the rest pad the entry to a multiple of 8 bytes
+pub fn padding_len(format: ObjectFormat, version: u32, extended: bool, path_len: usize) -> usize {
+    if version >= 4 {
+        return 1;
+    }
+    // ctime, mtime, 6 * u32, the id, the flags and maybe the extended flags
+    let fixed = 8 + 8 + 4 * 6 + format.len() + 2 + if extended { 2 } else { 0 };
+    8 - (fixed + path_len) % 8
+}
+
+#[derive(Clone, Debug)]
+pub struct IndexEntry {
+    pub ctime: i64,
+    pub mtime: i64,
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub filesize: u32,
+    pub sha1: ObjectId,
+    /// see `stage` and `name_len`
+    pub flags: u16,
+    /// version 3 and later, see `skip_worktree` and `intent_to_add`
+    pub extended_flags: u16,
+    /// raw bytes separated by `/`, not necessarily utf-8
+    pub filepath: Vec<u8>,
+    /// the NULs after the path
+    pub padding: usize,
+}
+
+#[allow(unused_variables)]
+impl IndexEntry {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        ctime: i64,
+        mtime: i64,
+        dev: u32,
+        ino: u32,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+        filesize: u32,
+        sha1: ObjectId,
+        flags: u16,
+        extended_flags: u16,
+        filepath: Vec<u8>,
+        padding: usize,
+    ) -> IndexEntry {
+        Self {
+            ctime,
+            mtime,
+            dev,
+            ino,
+            mode,
+            uid,
+            gid,
+            filesize,
+            sha1,
+            flags,
+            extended_flags,
+            filepath,
+            padding,
+        }
+    }
+
+    /// the entry of the file at `path`, relative to the working tree at `root`, hashed
+    /// and with its stat data. paths which aren't safe to check out are refused
+    pub fn try_new(root: &Path, path: &[u8], format: ObjectFormat) -> Result<Self, GitError> {
+        let verify = |mode| {
+            verify_path(path, mode).map_err(|reason| GitError::InvalidPath {
+                path: path.to_vec(),
+                reason,
+            })
+        };
+        // before anything outside the working tree could be looked at
+        verify(ObjectMode::Normal)?;
+        let full_path = root.join(bytes_to_path(path));
+        let meta = fs::symlink_metadata(&full_path)?;
+        let mode = refresh::git_mode(&meta);
+        if mode == refresh::MODE_SYMLINK {
+            verify(ObjectMode::SymbolLink)?;
+        }
+        let sha1 = refresh::hash_file(&full_path, &meta, format)?;
+        let mut entry = Self::with_path(path.to_vec(), mode, sha1);
+        entry.update_stat(&meta);
+        Ok(entry)
+    }
+
+    /// an entry at stage 0 without stat data, see `update_stat`
+    pub fn with_path(filepath: Vec<u8>, mode: u32, sha1: ObjectId) -> Self {
+        // no assume-valid
+        let flags = filepath.len().min(FLAG_NAME_MASK as usize) as u16;
+        let padding = padding_len(sha1.format(), 2, false, filepath.len());
+        Self::new(0, 0, 0, 0, mode, 0, 0, 0, sha1, flags, 0, filepath, padding)
+    }
+
+    /// 0 for a merged entry, 1 to 3 for the base, ours and theirs of a conflict
+    pub fn stage(&self) -> u8 {
+        ((self.flags & FLAG_STAGE_MASK) >> FLAG_STAGE_SHIFT) as u8
+    }
+
+    /// the length of the path as the flags store it, capped at `FLAG_NAME_MASK`
+    pub fn name_len(&self) -> usize {
+        (self.flags & FLAG_NAME_MASK) as usize
+    }
+
+    pub fn assume_valid(&self) -> bool {
+        self.flags & FLAG_ASSUME_VALID != 0
+    }
+
+    /// sparse checkout leaves the file out of the working tree
+    pub fn skip_worktree(&self) -> bool {
+        self.extended_flags & EXTENDED_FLAG_SKIP_WORKTREE != 0
+    }
+
+    /// added by `add -N`, the id is the empty blob's
+    pub fn intent_to_add(&self) -> bool {
+        self.extended_flags & EXTENDED_FLAG_INTENT_TO_ADD != 0
+    }
+
+    /// <ctime><mtime><dev><ino><mode><uid><gid><size><id><flags>[extended flags]<path>
+    /// <NUL padding>, and from version 4 the path is the number of bytes to strip from
+    /// the end of `previous` and a NUL terminated suffix
+    pub fn encode(&self, version: u32, previous: &[u8], buf: &mut BytesMut) {
+        let id = self.sha1.as_bytes();
+        let extended = version >= 3 && self.extended_flags != 0;
+        buf.put_i64(self.ctime);
+        buf.put_i64(self.mtime);
+        for v in [
+            self.dev,
+            self.ino,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.filesize,
+        ] {
+            buf.put_u32(v);
+        }
+        buf.put(id);
+        // the name length and the extended bit are recomputed, the rest is kept
+        let name_len = self.filepath.len().min(FLAG_NAME_MASK as usize) as u16;
+        let mut flags = self.flags & !(FLAG_NAME_MASK | FLAG_EXTENDED) | name_len;
+        if extended {
+            flags |= FLAG_EXTENDED;
+            buf.put_u16(flags);
+            buf.put_u16(self.extended_flags);
+        } else {
+            buf.put_u16(flags);
+        }
+
+        let path = if version >= 4 {
+            let common = previous
+                .iter()
+                .zip(self.filepath.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            buf.put(&encode_ofs_distance((previous.len() - common) as u64)[..]);
+            &self.filepath[common..]
+        } else {
+            &self.filepath[..]
+        };
+        buf.put(path);
+        let padding = padding_len(self.sha1.format(), version, extended, self.filepath.len());
+        buf.put_bytes(0, padding);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeExtension {
+    /// raw bytes, like the paths of entries
+    pub path: Vec<u8>,
+    pub entry_num: i32,
+    pub subtree_num: i32,
+    pub sha1: Option<ObjectId>,
+    pub children: Vec<TreeExtension>,
+}
+
+impl TreeExtension {
+    pub fn new(
+        path: Vec<u8>,
+        entry_num: i32,
+        subtree_num: i32,
+        sha1: Option<ObjectId>,
+        children: Vec<TreeExtension>,
+    ) -> Self {
+        Self {
+            path,
+            entry_num,
+            subtree_num,
+            sha1,
+            children,
+        }
+    }
+}
+
+// <path><NUL><entry_num> <subtree_num><LF>[id], then the children, without the
+// `TREE` signature and length
+impl From<&TreeExtension> for Vec<u8> {
+    fn from(extension: &TreeExtension) -> Self {
+        let mut buf = BytesMut::with_capacity(64);
+        buf.put(&extension.path[..]);
+        buf.put_u8(b'\0');
+        buf.put(format!("{} {}\n", extension.entry_num, extension.subtree_num).as_bytes());
+        // -1 entries for an invalidated tree, which has no id
+        if extension.entry_num >= 0 {
+            if let Some(id) = &extension.sha1 {
+                buf.put(id.as_bytes());
+            }
+        }
+        for child in extension.children.iter() {
+            let child: Vec<u8> = child.into();
+            buf.put(&child[..]);
+        }
+        buf.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::decode::parse_index;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_round_trip() {
+        for name in ["data/index", "data/index2"] {
+            let content = fs::read(name).unwrap();
+            let index = parse_index(&content, ObjectFormat::Sha1, Path::new(name)).unwrap();
+            assert_eq!(index.encode(ObjectFormat::Sha1), content, "{}", name);
+        }
+        let content = fs::read("data/sha256/index").unwrap();
+        let path = Path::new("data/sha256/index");
+        let index = parse_index(&content, ObjectFormat::Sha256, path).unwrap();
+        assert_eq!(index.encode(ObjectFormat::Sha256), content);
+    }
+
+    #[test]
+    fn test_entry_flags() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, b"hello world\n").unwrap();
+
+        let mut entry = IndexEntry::try_new(dir.path(), b"a.txt", ObjectFormat::Sha1).unwrap();
+        assert_eq!(entry.filepath, b"a.txt");
+        assert_eq!(entry.name_len(), entry.filepath.len());
+        assert!(matches!(
+            IndexEntry::try_new(dir.path(), b"../a.txt", ObjectFormat::Sha1),
+            Err(GitError::InvalidPath { .. })
+        ));
+        assert_eq!(entry.stage(), 0);
+        entry.flags |= 2 << FLAG_STAGE_SHIFT | FLAG_ASSUME_VALID;
+        assert_eq!(entry.stage(), 2);
+        assert!(entry.assume_valid());
+
+        let mut raw = BytesMut::new();
+        entry.encode(2, b"", &mut raw);
+        assert_eq!(raw.len() % 8, 0);
+        assert_eq!(raw.len(), 62 + entry.filepath.len() + entry.padding);
+
+        // a written index reads back the same
+        let index = Index::new(INDEX_SIGNATURE, 2, 1, vec![entry], vec![], None);
+        let index_path = dir.path().join("index");
+        index.write(&index_path, ObjectFormat::Sha1).unwrap();
+        assert!(!dir.path().join("index.lock").exists());
+        let content = fs::read(&index_path).unwrap();
+        let decoded = parse_index(&content, ObjectFormat::Sha1, &index_path).unwrap();
+        assert_eq!(decoded.entrys[0].stage(), 2);
+        assert_eq!(decoded.entrys[0].filepath, index.entrys[0].filepath);
+        assert_eq!(decoded.encode(ObjectFormat::Sha1), content);
+
+        fs::write(dir.path().join("index.lock"), b"").unwrap();
+        assert!(index.write(&index_path, ObjectFormat::Sha1).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_path() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(OsStr::from_bytes(b"caf\xe9.txt"));
+        fs::write(&path, b"hello world\n").unwrap();
+
+        let entry = IndexEntry::try_new(dir.path(), b"caf\xe9.txt", ObjectFormat::Sha1).unwrap();
+        assert_eq!(entry.filepath, b"caf\xe9.txt");
+        assert_eq!(
+            entry.sha1.to_string(),
+            "3b18e512dba79e4c8300dd08aeb37f8e728b8dad"
+        );
+        assert_eq!(dir.path().join(bytes_to_path(&entry.filepath)), path);
+    }
+}
@@ -0,0 +1,335 @@
+//! find the entries which differ from the working tree, by their stat data first
+//!
+//! a file whose stat data is the same as its entry is trusted to be unchanged, others
+//! are hashed again. but a file changed in the same second(or nanosecond, where it's
+//! recorded) as the index was written may keep its stat data, so entries not older
+//! than the index are "racy" and always hashed
+
+use std::fs::{self, Metadata};
+use std::io::{self, Read, Write};
+#[cfg(target_os = "linux")]
+use std::os::linux::fs::MetadataExt;
+#[cfg(target_os = "macos")]
+use std::os::macos::fs::MetadataExt;
+use std::path::Path;
+
+use crate::attributes::convert::Convert;
+use crate::object::{Blob, Object, ObjectId};
+use crate::utils::hash::{HashWriter, ObjectFormat};
+use crate::utils::path::{bytes_to_path, has_symlink_leading_path, path_to_bytes};
+use crate::GitError;
+
+use super::stage::STAGE_MERGED;
+use super::{Index, IndexEntry};
+
+pub const MODE_NORMAL: u32 = 0o100644;
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+pub const MODE_SYMLINK: u32 = 0o120000;
+pub const MODE_GITLINK: u32 = 0o160000;
+
+#[derive(Debug, Default)]
+pub struct Refresh {
+    /// paths whose content or type differs from their entry
+    pub modified: Vec<Vec<u8>>,
+    /// paths which are gone from the working tree
+    pub deleted: Vec<Vec<u8>>,
+    /// the number of files hashed, the others were trusted for their stat data
+    pub hashed: usize,
+}
+
+impl Index {
+    /// compare the merged entries with the working tree at `root`, whose files are
+    /// hashed after `convert` cleans them. entries found unchanged by hashing get the
+    /// new stat data, so they are cheap next time
+    pub fn refresh(
+        &mut self,
+        root: &Path,
+        format: ObjectFormat,
+        convert: &mut Convert,
+    ) -> Result<Refresh, GitError> {
+        let mut refresh = Refresh::default();
+        let empty_blob = blob_id(Vec::new(), format);
+        let timestamp = self.timestamp;
+        for entry in self.entrys.iter_mut() {
+            if entry.stage() != STAGE_MERGED
+                || entry.assume_valid()
+                || entry.skip_worktree()
+                || entry.mode == MODE_GITLINK
+            {
+                continue;
+            }
+            // a file behind a symlink isn't ours, whatever it is
+            if has_symlink_leading_path(root, &entry.filepath) {
+                refresh.deleted.push(entry.filepath.clone());
+                continue;
+            }
+            let path = root.join(bytes_to_path(&entry.filepath));
+            let meta = match fs::symlink_metadata(&path) {
+                Ok(meta) if !meta.is_dir() => meta,
+                Ok(_) => {
+                    refresh.deleted.push(entry.filepath.clone());
+                    continue;
+                }
+                Err(e) if is_missing(&e) => {
+                    refresh.deleted.push(entry.filepath.clone());
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if git_mode(&meta) != entry.mode {
+                refresh.modified.push(entry.filepath.clone());
+                continue;
+            }
+
+            // a size of 0 with content means the entry was smudged, see below
+            let smudged = entry.filesize == 0 && entry.sha1 != empty_blob;
+            let matches = entry.stat_matches(&meta) && !smudged;
+            let racy = timestamp.is_some_and(|t| entry.mtime >= t);
+            if matches && !racy {
+                continue;
+            }
+
+            refresh.hashed += 1;
+            let id = if meta.file_type().is_symlink() || !convert.converts(&entry.filepath)? {
+                hash_file(&path, &meta, format)?
+            } else {
+                blob_id(convert.to_git(&entry.filepath, fs::read(&path)?)?, format)
+            };
+            if id == entry.sha1 {
+                entry.update_stat(&meta);
+            } else {
+                if matches {
+                    // changed without a trace in the stat data: make sure it's hashed
+                    // even after the index is written again, like git does
+                    entry.filesize = 0;
+                }
+                refresh.modified.push(entry.filepath.clone());
+            }
+        }
+        Ok(refresh)
+    }
+}
+
+impl IndexEntry {
+    /// whether the stat data of the entry is still the one of the file
+    pub fn stat_matches(&self, meta: &Metadata) -> bool {
+        let mut entry = self.clone();
+        entry.update_stat(meta);
+        (
+            entry.ctime,
+            entry.mtime,
+            entry.dev,
+            entry.ino,
+            entry.uid,
+            entry.gid,
+        ) == (
+            self.ctime, self.mtime, self.dev, self.ino, self.uid, self.gid,
+        ) && entry.filesize == self.filesize
+            && git_mode(meta) == self.mode
+    }
+
+    /// take the stat data of the file, but not its mode
+    pub fn update_stat(&mut self, meta: &Metadata) {
+        self.ctime = meta.st_ctime() << 32 | meta.st_ctime_nsec() & 0xFFFF_FFFF;
+        self.mtime = packed_mtime(meta);
+        self.dev = meta.st_dev() as u32;
+        self.ino = meta.st_ino() as u32;
+        self.uid = meta.st_uid();
+        self.gid = meta.st_gid();
+        self.filesize = meta.st_size() as u32;
+    }
+}
+
+/// seconds in the high 32 bits, nanoseconds in the low ones
+pub fn packed_mtime(meta: &Metadata) -> i64 {
+    meta.st_mtime() << 32 | meta.st_mtime_nsec() & 0xFFFF_FFFF
+}
+
+/// the mode git records for a file, from its metadata which must not follow symlinks
+pub fn git_mode(meta: &Metadata) -> u32 {
+    if meta.file_type().is_symlink() {
+        MODE_SYMLINK
+    } else if meta.st_mode() & 0o100 != 0 {
+        MODE_EXECUTABLE
+    } else {
+        MODE_NORMAL
+    }
+}
+
+/// the id of the blob of the file, the target of a symlink is its content. a file is
+/// hashed as it's read, without converting it
+pub fn hash_file(path: &Path, meta: &Metadata, format: ObjectFormat) -> io::Result<ObjectId> {
+    if meta.file_type().is_symlink() {
+        return Ok(blob_id(
+            path_to_bytes(&fs::read_link(path)?).into_owned(),
+            format,
+        ));
+    }
+    let file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut writer = HashWriter::new(io::sink(), format);
+    write!(writer, "blob {}\0", size)?;
+    if io::copy(&mut file.take(size), &mut writer)? != size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the file got shorter while being read",
+        ));
+    }
+    let (_, digest) = writer.finish();
+    Ok(ObjectId::from_bytes(&digest).expect("hash of a known format"))
+}
+
+fn blob_id(content: Vec<u8>, format: ObjectFormat) -> ObjectId {
+    let raw: Vec<u8> = (&Object::Blob(Blob::new(content))).into();
+    ObjectId::from_bytes(&format.hash(&raw)).expect("hash of a known format")
+}
+
+/// a file that was removed, or a directory of its path that was replaced by a file
+pub fn is_missing(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::attributes::{AttributeList, Attributes, ATTRIBUTES_FILE};
+    use crate::config::Config;
+    use crate::index::INDEX_SIGNATURE;
+
+    use super::*;
+
+    fn entry(root: &Path, name: &str) -> IndexEntry {
+        IndexEntry::try_new(root, name.as_bytes(), ObjectFormat::Sha1).unwrap()
+    }
+
+    fn convert(root: &Path) -> Convert {
+        let attributes = Attributes::new(root.to_path_buf(), AttributeList::default(), vec![]);
+        Convert::new(root, attributes, &Config::default())
+    }
+
+    fn set_mtime(path: &Path, time: SystemTime) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn test_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(root.join(name), name).unwrap();
+        }
+        let past = SystemTime::now() - Duration::from_secs(100);
+        set_mtime(&root.join("a.txt"), past);
+        set_mtime(&root.join("b.txt"), past);
+        let entrys = ["a.txt", "b.txt", "c.txt"].map(|name| entry(root, name));
+        let mut index = Index::new(INDEX_SIGNATURE, 2, 3, entrys.to_vec(), vec![], None);
+
+        // an index older than the files can't tell
+        index.timestamp = Some(0);
+        let refresh = index
+            .refresh(root, ObjectFormat::Sha1, &mut convert(root))
+            .unwrap();
+        assert_eq!(refresh.hashed, 3, "every entry is racy");
+        index.timestamp = Some(i64::MAX);
+        let refresh = index
+            .refresh(root, ObjectFormat::Sha1, &mut convert(root))
+            .unwrap();
+        assert_eq!(refresh.hashed, 0);
+        assert!(refresh.modified.is_empty() && refresh.deleted.is_empty());
+
+        // a new mtime is hashed, and found the same
+        set_mtime(&root.join("a.txt"), past + Duration::from_secs(1));
+        fs::write(root.join("b.txt"), "changed").unwrap();
+        fs::remove_file(root.join("c.txt")).unwrap();
+        let refresh = index
+            .refresh(root, ObjectFormat::Sha1, &mut convert(root))
+            .unwrap();
+        assert_eq!(refresh.hashed, 2);
+        assert_eq!(refresh.modified, [b"b.txt"]);
+        assert_eq!(refresh.deleted, [b"c.txt"]);
+        let refresh = index
+            .refresh(root, ObjectFormat::Sha1, &mut convert(root))
+            .unwrap();
+        assert_eq!(refresh.hashed, 1, "the stat data of a.txt was updated");
+    }
+
+    #[test]
+    fn test_racy_clean() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let path = root.join("a.txt");
+        fs::write(&path, "aaa").unwrap();
+        let mut entry = entry(root, "a.txt");
+        // changed in the same instant the index was written, keeping its size
+        fs::write(&path, "bbb").unwrap();
+        entry.update_stat(&fs::symlink_metadata(&path).unwrap());
+        let mtime = entry.mtime;
+        let mut index = Index::new(INDEX_SIGNATURE, 2, 1, vec![entry], vec![], None);
+        index.timestamp = Some(mtime);
+
+        let refresh = index
+            .refresh(root, ObjectFormat::Sha1, &mut convert(root))
+            .unwrap();
+        assert_eq!(refresh.hashed, 1);
+        assert_eq!(refresh.modified, [b"a.txt"]);
+        // smudged, so it's still hashed once the index is newer than the file
+        assert_eq!(index.entrys[0].filesize, 0);
+        index.timestamp = Some(mtime + (1 << 32));
+        let refresh = index
+            .refresh(root, ObjectFormat::Sha1, &mut convert(root))
+            .unwrap();
+        assert_eq!(refresh.hashed, 1);
+        assert_eq!(refresh.modified, [b"a.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mode_changes() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("run.sh"), "echo").unwrap();
+        symlink("run.sh", root.join("link")).unwrap();
+        let link = entry(root, "link");
+        assert_eq!(link.mode, MODE_SYMLINK);
+        assert_eq!(link.sha1, blob_id(b"run.sh".to_vec(), ObjectFormat::Sha1));
+        let run = entry(root, "run.sh");
+        assert_eq!(run.mode, MODE_NORMAL);
+        let mut index = Index::new(INDEX_SIGNATURE, 2, 2, vec![link, run], vec![], None);
+
+        let mut permissions = fs::metadata(root.join("run.sh")).unwrap().permissions();
+        permissions.set_mode(0o755);
+        fs::set_permissions(root.join("run.sh"), permissions).unwrap();
+        let refresh = index
+            .refresh(root, ObjectFormat::Sha1, &mut convert(root))
+            .unwrap();
+        assert_eq!(refresh.modified, [b"run.sh"]);
+    }
+
+    #[test]
+    fn test_converted() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(ATTRIBUTES_FILE), "*.txt text\n").unwrap();
+        fs::write(root.join("a.txt"), "a\nb\n").unwrap();
+        fs::write(root.join("a.bin"), "a\r\n").unwrap();
+        let entrys = vec![entry(root, "a.bin"), entry(root, "a.txt")];
+        let mut index = Index::new(INDEX_SIGNATURE, 2, 2, entrys, vec![], None);
+        index.timestamp = Some(0);
+
+        // the blob has LF, whatever the working tree has
+        fs::write(root.join("a.txt"), "a\r\nb\r\n").unwrap();
+        fs::write(root.join("a.bin"), "a\n").unwrap();
+        let refresh = index
+            .refresh(root, ObjectFormat::Sha1, &mut convert(root))
+            .unwrap();
+        assert_eq!(refresh.hashed, 2);
+        assert_eq!(refresh.modified, [b"a.bin"]);
+    }
+}
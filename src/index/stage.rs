@@ -0,0 +1,259 @@
+//! the stages of the entries: 0 for a merged path, and while a merge is in conflict
+//! 1 to 3 for the base, ours and theirs of the path. the entries are sorted by path,
+//! then by stage
+
+use std::cmp::Ordering;
+
+use crate::object::{tree::verify_path, ObjectMode};
+use crate::GitError;
+
+use super::extension::{Extension, ResolveUndo};
+use super::refresh::MODE_SYMLINK;
+use super::{Index, IndexEntry, TreeExtension, FLAG_STAGE_MASK, FLAG_STAGE_SHIFT};
+
+pub const STAGE_MERGED: u8 = 0;
+pub const STAGE_BASE: u8 = 1;
+pub const STAGE_OURS: u8 = 2;
+pub const STAGE_THEIRS: u8 = 3;
+
+/// the sides of a conflicted path, a side is missing if the path doesn't exist there
+#[derive(Clone, Copy, Debug)]
+pub struct Conflict<'a> {
+    pub base: Option<&'a IndexEntry>,
+    pub ours: Option<&'a IndexEntry>,
+    pub theirs: Option<&'a IndexEntry>,
+}
+
+impl Index {
+    /// the position of the entry of `path` at `stage`, or where it would be inserted
+    pub fn position(&self, path: &[u8], stage: u8) -> Result<usize, usize> {
+        self.entrys
+            .binary_search_by(|entry| match entry.filepath[..].cmp(path) {
+                Ordering::Equal => entry.stage().cmp(&stage),
+                ordering => ordering,
+            })
+    }
+
+    pub fn entry(&self, path: &[u8], stage: u8) -> Option<&IndexEntry> {
+        self.position(path, stage).ok().map(|i| &self.entrys[i])
+    }
+
+    /// all the entries of `path`, by stage
+    pub fn entries_of(&self, path: &[u8]) -> &[IndexEntry] {
+        let start = self.position(path, STAGE_MERGED).unwrap_or_else(|i| i);
+        let len = self.entrys[start..]
+            .iter()
+            .take_while(|e| e.filepath == path)
+            .count();
+        &self.entrys[start..start + len]
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.entrys.iter().any(|e| e.stage() != STAGE_MERGED)
+    }
+
+    /// the paths with entries in stage 1 to 3, in order
+    pub fn conflicts(&self) -> Vec<&[u8]> {
+        let mut paths: Vec<&[u8]> = Vec::new();
+        for entry in self.entrys.iter().filter(|e| e.stage() != STAGE_MERGED) {
+            if paths.last() != Some(&&entry.filepath[..]) {
+                paths.push(&entry.filepath);
+            }
+        }
+        paths
+    }
+
+    /// the sides of `path`, `None` if it isn't conflicted
+    pub fn conflict(&self, path: &[u8]) -> Option<Conflict<'_>> {
+        let entrys = self.entries_of(path);
+        let side = |stage| entrys.iter().find(|e| e.stage() == stage);
+        let conflict = Conflict {
+            base: side(STAGE_BASE),
+            ours: side(STAGE_OURS),
+            theirs: side(STAGE_THEIRS),
+        };
+        match conflict {
+            Conflict {
+                base: None,
+                ours: None,
+                theirs: None,
+            } => None,
+            conflict => Some(conflict),
+        }
+    }
+
+    /// replace every entry of the path of `entry` with `entry` at stage 0. like git,
+    /// the stages it replaces are remembered in the resolve undo extension(`REUC`), and
+    /// the cached trees above the path are invalidated
+    pub fn resolve(&mut self, mut entry: IndexEntry) {
+        entry.flags &= !FLAG_STAGE_MASK;
+        let path = entry.filepath.clone();
+        let start = self.position(&path, STAGE_MERGED).unwrap_or_else(|i| i);
+        let end = start + self.entries_of(&path).len();
+        let replaced: Vec<IndexEntry> = self.entrys.splice(start..end, [entry]).collect();
+
+        if replaced.iter().any(|e| e.stage() != STAGE_MERGED) {
+            let mut undo = ResolveUndo {
+                path: path.clone(),
+                modes: [0; 3],
+                ids: [None; 3],
+            };
+            for old in replaced.iter().filter(|e| e.stage() != STAGE_MERGED) {
+                let i = (old.stage() - STAGE_BASE) as usize;
+                undo.modes[i] = old.mode;
+                undo.ids[i] = Some(old.sha1);
+            }
+            self.record_resolve_undo(undo);
+        }
+        if let Some(tree) = self.tree_extension.as_mut() {
+            tree.invalidate(&path);
+        }
+        self.entries_changed();
+    }
+
+    /// replace every entry of `path` with `entry` at stage 0, or remove them all when
+    /// it's `None`. unlike `resolve`, nothing is remembered to undo it. like git, paths
+    /// which aren't safe to check out are refused
+    pub fn set_path(&mut self, path: &[u8], entry: Option<IndexEntry>) -> Result<(), GitError> {
+        if let Some(entry) = &entry {
+            // only symlinks are told apart
+            let mode = match entry.mode {
+                MODE_SYMLINK => ObjectMode::SymbolLink,
+                _ => ObjectMode::Normal,
+            };
+            verify_path(path, mode).map_err(|reason| GitError::InvalidPath {
+                path: path.to_vec(),
+                reason,
+            })?;
+        }
+        let start = self.position(path, STAGE_MERGED).unwrap_or_else(|i| i);
+        let end = start + self.entries_of(path).len();
+        let entry = entry.map(|mut entry| {
+            entry.set_stage(STAGE_MERGED);
+            entry
+        });
+        self.entrys.splice(start..end, entry);
+        if let Some(tree) = self.tree_extension.as_mut() {
+            tree.invalidate(path);
+        }
+        self.entries_changed();
+        Ok(())
+    }
+
+    fn record_resolve_undo(&mut self, undo: ResolveUndo) {
+        let position = self
+            .extensions
+            .iter()
+            .position(|e| matches!(e, Extension::ResolveUndo(_)));
+        let position = position.unwrap_or_else(|| {
+            self.extensions.push(Extension::ResolveUndo(Vec::new()));
+            self.extensions.len() - 1
+        });
+        if let Extension::ResolveUndo(entrys) = &mut self.extensions[position] {
+            // sorted by path, like git keeps them
+            match entrys.binary_search_by(|e| e.path.cmp(&undo.path)) {
+                Ok(i) => entrys[i] = undo,
+                Err(i) => entrys.insert(i, undo),
+            }
+        }
+    }
+}
+
+impl IndexEntry {
+    pub fn set_stage(&mut self, stage: u8) {
+        self.flags = self.flags & !FLAG_STAGE_MASK | (stage as u16) << FLAG_STAGE_SHIFT;
+    }
+}
+
+impl TreeExtension {
+    /// forget the ids of the cached trees containing `path`, they have to be written
+    /// again
+    pub fn invalidate(&mut self, path: &[u8]) {
+        self.entry_num = -1;
+        self.sha1 = None;
+        if let Some(slash) = path.iter().position(|&c| c == b'/') {
+            let (dir, rest) = (&path[..slash], &path[slash + 1..]);
+            if let Some(child) = self.children.iter_mut().find(|c| c.path == dir) {
+                child.invalidate(rest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::{parser::decode::parse_index, utils::hash::ObjectFormat};
+
+    use super::*;
+
+    fn read(name: &str) -> (Vec<u8>, Index) {
+        let content = fs::read(name).unwrap();
+        let index = parse_index(&content, ObjectFormat::Sha1, Path::new(name)).unwrap();
+        (content, index)
+    }
+
+    #[test]
+    fn test_conflicts() {
+        // written by git in the middle of a merge
+        let (_, mut index) = read("data/index_conflict");
+        assert!(index.has_conflicts());
+        assert_eq!(index.conflicts(), [&b"a.txt"[..], b"src/b.txt"]);
+        assert!(index.conflict(b"z.txt").is_none());
+        assert!(index.entry(b"z.txt", STAGE_MERGED).is_some());
+        assert!(!index.entry_map().contains_key(Path::new("a.txt")));
+
+        let conflict = index.conflict(b"a.txt").unwrap();
+        let id = |e: Option<&IndexEntry>| e.unwrap().sha1.to_string();
+        assert_eq!(
+            id(conflict.base),
+            "78981922613b2afb6025042ff6bd878ac1994e85"
+        );
+        assert_eq!(
+            id(conflict.ours),
+            "1f7391f92b6a3792204e07e99f71f643cc35e7e1"
+        );
+        assert_eq!(
+            id(conflict.theirs),
+            "e45c9c2666d44e0327c1f9c239a74c508336053e"
+        );
+
+        // git resolved it with `add`, which gives the same bytes
+        let (resolved_content, resolved) = read("data/index_conflict_resolved");
+        let entry = resolved.entry(b"a.txt", STAGE_MERGED).unwrap().clone();
+        index.resolve(entry);
+        assert_eq!(index.conflicts(), [&b"src/b.txt"[..]]);
+        assert_eq!(index.entries_of(b"a.txt").len(), 1);
+        assert_eq!(index.encode(ObjectFormat::Sha1), resolved_content);
+    }
+
+    #[test]
+    fn test_resolve_invalidates_cached_trees() {
+        let (_, mut index) = read("data/index2");
+        let cached = |index: &Index, path: &[&[u8]]| {
+            let mut tree = index.tree_extension.as_ref().unwrap();
+            for dir in path {
+                tree = tree.children.iter().find(|c| c.path == *dir).unwrap();
+            }
+            tree.sha1.is_some()
+        };
+        assert!(cached(&index, &[b"src", b"core"]));
+        assert!(cached(&index, &[b"src", b"object"]));
+
+        let mut entry = index
+            .entry(b"src/core/stage.rs", STAGE_MERGED)
+            .unwrap()
+            .clone();
+        entry.set_stage(STAGE_THEIRS);
+        index.resolve(entry);
+
+        assert!(!cached(&index, &[b"src", b"core"]));
+        assert!(cached(&index, &[b"src", b"object"]));
+        assert!(cached(&index, &[b"doc"]));
+        // nothing was conflicted, so there is nothing to undo
+        assert!(index.extensions.is_empty());
+        let entry = index.entry(b"src/core/stage.rs", STAGE_MERGED);
+        assert_eq!(entry.unwrap().stage(), STAGE_MERGED);
+    }
+}
@@ -1,9 +1,13 @@
+pub mod attributes;
+pub mod checkout;
 pub mod commands;
+pub mod config;
 pub mod context;
 #[allow(warnings)]
 pub mod core;
 pub mod diff;
 mod error;
+pub mod ignore;
 pub mod index;
 pub mod object;
 pub mod parser;
@@ -21,21 +25,62 @@ extern crate log;
 extern crate simplelog;
 use simplelog::*;
 
-pub const APP_NAME: &'static str = "git-rs";
-pub const REPO_NAME: &'static str = ".git-rs";
+pub const APP_NAME: &str = "git-rs";
+pub const REPO_NAME: &str = ".git-rs";
 
 fn main() {
     init();
 
     let exec = Git::from_args();
-    match exec {
-        Git::Init { path } => {
-            commands::init::execute(&path);
+    let result = match exec {
+        Git::Init {
+            path,
+            object_format,
+        } => {
+            commands::init::execute(&path, object_format);
+            Ok(())
         }
         Git::Add { files } => {
             commands::add::execute(&files);
+            Ok(())
         }
-        _ => {}
+        Git::Cat { mode, objects } => commands::cat::execute(&mode, &objects),
+        Git::CheckAttr {
+            all,
+            attributes,
+            paths,
+        } => commands::check_attr::execute(all, &attributes, &paths),
+        Git::Checkout { force, name } => commands::checkout::execute(force, &name),
+        Git::CheckIgnore { verbose, paths } => commands::check_ignore::execute(verbose, &paths),
+        Git::Fsck { unreachable } => commands::fsck::execute(unreachable),
+        Git::Repack { window, depth } => commands::repack::execute(window, depth),
+        Git::Status {
+            porcelain, ignored, ..
+        } => {
+            // like git, `--porcelain` wins over `--short`
+            let format = match porcelain.as_ref().map(|v| v.as_deref()) {
+                Some(Some("v2")) => commands::status::Format::PorcelainV2,
+                Some(_) => commands::status::Format::PorcelainV1,
+                None => commands::status::Format::Short,
+            };
+            commands::status::execute(format, ignored)
+        }
+        Git::Switch {
+            create,
+            force,
+            branch,
+        } => commands::checkout::execute_switch(create, force, &branch),
+        Git::UpdateIndex { index_version } => commands::update_index::execute(index_version),
+        _ => Ok(()),
+    };
+
+    match result {
+        Err(GitError::NothingIgnored) => std::process::exit(1),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        Ok(()) => {}
     }
 }
 
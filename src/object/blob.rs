@@ -18,15 +18,15 @@ impl From<&[u8]> for Blob {
     }
 }
 
-impl Into<Vec<u8>> for &Blob {
-    fn into(self) -> Vec<u8> {
-        let length = self.content.len();
+impl From<&Blob> for Vec<u8> {
+    fn from(blob: &Blob) -> Self {
+        let length = blob.content.len();
         let s=format!("{}",length);
         let mut buf = BytesMut::with_capacity(length + 4);
         buf.put(&b"blob "[..]);
         buf.put(s.as_bytes());
         buf.put_u8(b'\0');
-        buf.put(&self.content[..]);
+        buf.put(&blob.content[..]);
         (&buf[..]).into()
     }
 }
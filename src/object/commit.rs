@@ -1,120 +1,103 @@
 use bytes::{BufMut, BytesMut};
 
+use super::{ObjectId, Signature};
+
 /// commit <content length><NUL>tree <tree sha>
 /// parent <parent sha>
 /// [parent <parent sha> if several parents from merges]
 /// author <author name> <author e-mail> <timestamp> <timezone>
 /// committer <author name> <author e-mail> <timestamp> <timezone>
+/// [other headers, such as `encoding`, `mergetag` and `gpgsig`]
 ///
 /// <commit message>
+///
+/// the value of a header may span several lines, every line after the first one
+/// starts with a space. encoding a decoded commit gives back the same bytes
 #[derive(Clone, Debug)]
 pub struct Commit {
-    pub root_sha1: String,
-    pub parents_sha1: Option<Vec<String>>,
-    pub author: Option<AuthorInfo>,
-    pub commiter: Option<CommitterInfo>,
-    pub messsage: String,
+    pub root_sha1: ObjectId,
+    pub parents_sha1: Option<Vec<ObjectId>>,
+    pub author: Option<Signature>,
+    pub committer: Option<Signature>,
+    /// (name, value) of the headers after the committer, in their order. lines of a
+    /// multi-line value are joined by `\n`, without the leading spaces
+    pub extra_headers: Vec<(Vec<u8>, Vec<u8>)>,
+    /// raw bytes, in the charset of the `encoding` header(utf-8 if there is none)
+    pub message: Vec<u8>,
 }
 
 impl Commit {
     pub fn new(
-        root_sha1: String,
-        parents_sha1: Option<Vec<String>>,
-        author: Option<AuthorInfo>,
-        commiter: Option<CommitterInfo>,
-        messsage: String,
+        root_sha1: ObjectId,
+        parents_sha1: Option<Vec<ObjectId>>,
+        author: Option<Signature>,
+        committer: Option<Signature>,
+        message: Vec<u8>,
     ) -> Self {
         Self {
             root_sha1,
             parents_sha1,
             author,
-            commiter,
-            messsage,
+            committer,
+            extra_headers: Vec::new(),
+            message,
         }
     }
-}
-
-#[derive(Clone, Debug)]
-pub struct AuthorInfo {
-    pub name: String,
-    pub email: String,
-    pub timestamp: usize,
-    pub time_zone: String,
-}
 
-#[derive(Clone, Debug)]
-pub struct CommitterInfo {
-    pub name: String,
-    pub email: String,
-    pub timestamp: usize,
-    pub time_zone: String,
-}
+    /// the value of the first extra header called `name`
+    pub fn header(&self, name: &[u8]) -> Option<&[u8]> {
+        self.extra_headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| &value[..])
+    }
 
-impl AuthorInfo {
-    pub fn new(name: String, email: String, timestamp: usize, time_zone: String) -> Self {
-        Self {
-            name,
-            email,
-            timestamp,
-            time_zone,
-        }
+    /// the charset of the message, e.g. `ISO-8859-1`
+    pub fn encoding(&self) -> Option<&[u8]> {
+        self.header(b"encoding")
     }
-}
 
-impl CommitterInfo {
-    pub fn new(name: String, email: String, timestamp: usize, time_zone: String) -> Self {
-        Self {
-            name,
-            email,
-            timestamp,
-            time_zone,
-        }
+    /// the signature(`gpgsig`, or `gpgsig-sha256` in sha256 repositories)
+    pub fn gpgsig(&self) -> Option<&[u8]> {
+        self.header(b"gpgsig")
+            .or_else(|| self.header(b"gpgsig-sha256"))
     }
 }
 
-impl Into<Vec<u8>> for &Commit {
-    fn into(self) -> Vec<u8> {
+impl From<&Commit> for Vec<u8> {
+    fn from(commit: &Commit) -> Self {
         let mut entry_buf = BytesMut::with_capacity(2048);
 
         entry_buf.put(&b"tree "[..]);
-        entry_buf.put(self.root_sha1.as_bytes());
+        entry_buf.put(commit.root_sha1.to_hex().as_bytes());
         entry_buf.put_u8(b'\n');
 
-        if let Some(ref parents) = self.parents_sha1 {
+        if let Some(ref parents) = commit.parents_sha1 {
             for entry in parents.iter() {
                 entry_buf.put(&b"parent "[..]);
-                entry_buf.put(entry.as_bytes());
+                entry_buf.put(entry.to_hex().as_bytes());
                 entry_buf.put_u8(b'\n');
             }
         }
 
-        if let Some(ref author_info)=self.author{
+        if let Some(ref author) = commit.author {
+            let author: Vec<u8> = author.into();
             entry_buf.put(&b"author "[..]);
-            entry_buf.put(author_info.name.as_bytes());
-            entry_buf.put_u8(b' ');
-            entry_buf.put(author_info.email.as_bytes());
-            entry_buf.put_u8(b' ');
-            let timestamp=format!("{}",author_info.timestamp);
-            entry_buf.put(timestamp.as_bytes());
-            entry_buf.put_u8(b' ');
-            entry_buf.put(author_info.time_zone.as_bytes());
+            entry_buf.put(&author[..]);
             entry_buf.put_u8(b'\n');
         }
 
-        if let Some(ref committer_into)=self.author{
+        if let Some(ref committer) = commit.committer {
+            let committer: Vec<u8> = committer.into();
             entry_buf.put(&b"committer "[..]);
-            entry_buf.put(committer_into.name.as_bytes());
-            entry_buf.put_u8(b' ');
-            let timestamp=format!("{}",committer_into.timestamp);
-            entry_buf.put(committer_into.email.as_bytes());
-            entry_buf.put_u8(b' ');
-            entry_buf.put(timestamp.as_bytes());
-            entry_buf.put_u8(b' ');
-            entry_buf.put(committer_into.time_zone.as_bytes());
+            entry_buf.put(&committer[..]);
             entry_buf.put_u8(b'\n');
         }
+
+        put_extra_headers(&mut entry_buf, &commit.extra_headers);
+
         entry_buf.put_u8(b'\n');
-        entry_buf.put(self.messsage.as_bytes());
+        entry_buf.put(&commit.message[..]);
 
         // commit<space><content length><NULL><content>
         // here, content is the entry buf
@@ -130,3 +113,19 @@ impl Into<Vec<u8>> for &Commit {
         (&buf[..]).into()
     }
 }
+
+/// `<name> <value>\n` for each header, a value of several lines goes on with a space
+/// at the start of every line after the first one
+pub(super) fn put_extra_headers(buf: &mut BytesMut, headers: &[(Vec<u8>, Vec<u8>)]) {
+    for (name, value) in headers.iter() {
+        buf.put(&name[..]);
+        buf.put_u8(b' ');
+        for (n, line) in value.split(|&c| c == b'\n').enumerate() {
+            if n > 0 {
+                buf.put(&b"\n "[..]);
+            }
+            buf.put(line);
+        }
+        buf.put_u8(b'\n');
+    }
+}
@@ -0,0 +1,915 @@
+//! the object database, which lives in `.git-rs/objects`
+//!
+//! every loose object is stored as `objects/xx/yyyy...`, where `xxyyyy...` is the hex
+//! encoded hash(sha1 or sha256, by the format of the repository) of its raw
+//! content(`<type> <size><NUL><content>`), and the file itself is the zlib
+//! compressed raw content.
+//!
+//! objects which are not loose are looked up in `objects/pack/*.pack`.
+//!
+//! objects may be borrowed from other repositories as well: `objects/info/alternates`
+//! and `$GIT_ALTERNATE_OBJECT_DIRECTORIES` list more `objects` directories to look
+//! in(but never to write to), which may have alternates of their own.
+
+use std::{
+    collections::BTreeSet,
+    env,
+    ffi::OsStr,
+    fs,
+    io::{self, BufReader, BufWriter, Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::Config,
+    parser::decode::parse_object,
+    utils::{
+        hash::{HashWriter, ObjectFormat},
+        pack::Pack,
+        path::{loose_object_path, tmp_file},
+        sha1,
+    },
+    GitError,
+};
+
+use super::{
+    stream::{read_loose_header, ObjectReader},
+    tree::ObjectType,
+    Object, ObjectId,
+};
+
+/// bases of REF_DELTA in other packs(or repositories) are looked up recursively, this
+/// many times at most. more is a cycle between corrupt packs
+const MAX_BASE_DEPTH: usize = 64;
+
+/// shorter prefixes are too likely to be ambiguous to be useful
+pub const MIN_ABBREV: usize = 4;
+
+/// extra `objects` directories, separated like `$PATH`
+pub const ALTERNATES_ENV: &str = "GIT_ALTERNATE_OBJECT_DIRECTORIES";
+
+/// the same limit as git, alternates of alternates of ... are ignored beyond it
+const MAX_ALTERNATE_DEPTH: usize = 5;
+
+#[derive(Clone, Debug)]
+pub struct ObjectDatabase {
+    objects_dir: PathBuf,
+    format: ObjectFormat,
+    packs: Vec<Pack>,
+    /// every alternate, nested ones included, in the order they are looked up.
+    /// they have no alternates of their own
+    alternates: Vec<ObjectDatabase>,
+}
+
+impl ObjectDatabase {
+    /// `objects_dir` is the `objects` directory of the repository, not the repository itself.
+    /// the alternates listed in `objects_dir/info/alternates` are followed
+    pub fn new(objects_dir: PathBuf, format: ObjectFormat) -> Self {
+        let mut database = Self::without_alternates(objects_dir, format);
+        for dir in read_alternates(&database.objects_dir) {
+            database.add_alternate(dir, 1);
+        }
+        database
+    }
+
+    /// the database of the repository at `git_dir`, in the format of its config,
+    /// with the alternates from the environment as well
+    pub fn open(git_dir: &Path) -> Result<Self, GitError> {
+        let format = Config::load(git_dir.join("config"))?.object_format()?;
+        let mut database = Self::new(git_dir.join("objects"), format);
+        if let Some(dirs) = env::var_os(ALTERNATES_ENV) {
+            database.add_alternates_from(&dirs);
+        }
+        Ok(database)
+    }
+
+    fn without_alternates(objects_dir: PathBuf, format: ObjectFormat) -> Self {
+        let mut database = Self {
+            objects_dir,
+            format,
+            packs: Vec::new(),
+            alternates: Vec::new(),
+        };
+        database.reload_packs();
+        database
+    }
+
+    /// `dirs` is like `$GIT_ALTERNATE_OBJECT_DIRECTORIES`, relative paths are
+    /// relative to the current directory
+    fn add_alternates_from(&mut self, dirs: &OsStr) {
+        for dir in env::split_paths(dirs) {
+            if !dir.as_os_str().is_empty() {
+                self.add_alternate(dir, 1);
+            }
+        }
+    }
+
+    /// add `dir` and its own alternates. directories already known(including
+    /// this one) are skipped, so that cycles end
+    fn add_alternate(&mut self, dir: PathBuf, depth: usize) {
+        if depth > MAX_ALTERNATE_DEPTH {
+            warn!("skip alternate {:?}: nested too deeply", dir);
+            return;
+        }
+        let dir = match fs::canonicalize(&dir) {
+            Ok(dir) if dir.is_dir() => dir,
+            _ => {
+                warn!("skip alternate {:?}: not a directory", dir);
+                return;
+            }
+        };
+        let known = std::iter::once(&self.objects_dir)
+            .chain(
+                self.alternates
+                    .iter()
+                    .map(|alternate| &alternate.objects_dir),
+            )
+            .any(|known| fs::canonicalize(known).is_ok_and(|known| known == dir));
+        if known {
+            return;
+        }
+
+        let nested = read_alternates(&dir);
+        self.alternates
+            .push(Self::without_alternates(dir, self.format));
+        for nested_dir in nested {
+            self.add_alternate(nested_dir, depth + 1);
+        }
+    }
+
+    pub fn alternates(&self) -> &[ObjectDatabase] {
+        &self.alternates
+    }
+
+    /// this database first, and then the alternates
+    fn stores(&self) -> impl Iterator<Item = &ObjectDatabase> {
+        std::iter::once(self).chain(self.alternates.iter())
+    }
+
+    /// the loose file of the object, here or in an alternate
+    fn find_loose(&self, id: &ObjectId) -> Option<(&ObjectDatabase, PathBuf)> {
+        self.stores()
+            .map(|store| (store, store.path(id)))
+            .find(|(_, path)| path.exists())
+    }
+
+    pub fn objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    pub fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    pub fn packs(&self) -> &[Pack] {
+        &self.packs
+    }
+
+    /// scan `objects/pack` again(of the alternates as well), packs which can't be
+    /// opened are skipped
+    pub fn reload_packs(&mut self) {
+        self.packs.clear();
+        for alternate in self.alternates.iter_mut() {
+            alternate.reload_packs();
+        }
+
+        let entries = match fs::read_dir(self.objects_dir.join("pack")) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let mut idx_paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+            .collect();
+        idx_paths.sort();
+
+        for idx_path in idx_paths {
+            match Pack::open(&idx_path, self.format) {
+                Ok(pack) => self.packs.push(pack),
+                Err(e) => warn!("skip pack {:?}: {}", idx_path, e),
+            }
+        }
+    }
+
+    /// the name of the object, without writing it anywhere
+    pub fn hash_object(&self, object: &Object) -> ObjectId {
+        let content: Vec<u8> = object.into();
+        self.hash_raw(&content)
+    }
+
+    fn hash_raw(&self, content: &[u8]) -> ObjectId {
+        ObjectId::from_bytes(&self.format.hash(content)).expect("hash of a known format")
+    }
+
+    /// where the object is written as a loose object, it may be in an alternate instead
+    pub fn path(&self, id: &ObjectId) -> PathBuf {
+        loose_object_path(&self.objects_dir, &id.to_hex())
+    }
+
+    pub fn exists(&self, id: &ObjectId) -> bool {
+        if id.format() != self.format {
+            return false;
+        }
+        self.find_loose(id).is_some()
+            || self
+                .stores()
+                .flat_map(|store| store.packs.iter())
+                .any(|pack| pack.contains(id.as_bytes()))
+    }
+
+    /// the only object whose name starts with `prefix`(at least [`MIN_ABBREV`] hex
+    /// digits), looking at both loose objects and packs. full names work as well
+    pub fn resolve_prefix(&self, prefix: &str) -> Result<ObjectId, GitError> {
+        let not_found = || GitError::ObjectNotFound(prefix.to_string());
+        let lower = prefix.to_ascii_lowercase();
+        if lower.len() < MIN_ABBREV
+            || lower.len() > self.format.hex_len()
+            || !lower.bytes().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(not_found());
+        }
+        if lower.len() == self.format.hex_len() {
+            let id: ObjectId = lower.parse().map_err(|_| not_found())?;
+            return if self.exists(&id) {
+                Ok(id)
+            } else {
+                Err(not_found())
+            };
+        }
+
+        let mut candidates = BTreeSet::new();
+        for store in self.stores() {
+            if let Ok(entries) = fs::read_dir(store.objects_dir.join(&lower[..2])) {
+                for entry in entries.flatten() {
+                    let name = format!("{}{}", &lower[..2], entry.file_name().to_string_lossy());
+                    if name.starts_with(&lower) {
+                        if let Ok(id) = name.parse::<ObjectId>() {
+                            candidates.insert(id);
+                        }
+                    }
+                }
+            }
+            for pack in store.packs.iter() {
+                candidates.extend(
+                    pack.index()
+                        .ids_with_prefix(&lower)
+                        .filter_map(ObjectId::from_bytes),
+                );
+            }
+        }
+
+        let mut candidates = candidates.into_iter();
+        match (candidates.next(), candidates.len()) {
+            (None, _) => Err(not_found()),
+            (Some(id), 0) => Ok(id),
+            (Some(id), _) => Err(GitError::AmbiguousObjectName {
+                prefix: prefix.to_string(),
+                candidates: std::iter::once(id).chain(candidates).collect(),
+            }),
+        }
+    }
+
+    /// write the object if it doesn't exist yet(here or in an alternate), and return
+    /// its name. trees which git would consider broken are refused
+    pub fn write(&self, object: &Object) -> Result<ObjectId, GitError> {
+        if let Object::Tree(tree) = object {
+            tree.validate()?;
+        }
+        let content: Vec<u8> = object.into();
+        let id = self.hash_raw(&content);
+
+        let path = self.path(&id);
+        if self.exists(&id) {
+            return Ok(id);
+        }
+
+        let compressed = sha1::encode(&content);
+        self.write_atomic(&path, &compressed)?;
+        Ok(id)
+    }
+
+    /// loose objects first, and then the packs, of this database and then of the
+    /// alternates
+    pub fn read(&self, id: &ObjectId) -> Result<Object, GitError> {
+        if id.format() != self.format {
+            return Err(GitError::ObjectNotFound(id.to_string()));
+        }
+
+        let (kind, content) = self.read_raw(id)?;
+        let mut raw = format!("{} {}\0", kind, content.len()).into_bytes();
+        raw.extend_from_slice(&content);
+
+        parse_object(&raw, self.format, id)
+    }
+
+    /// type and content of the object, without the `<type> <size><NUL>` header
+    pub fn read_raw(&self, id: &ObjectId) -> Result<(ObjectType, Vec<u8>), GitError> {
+        if id.format() != self.format {
+            return Err(GitError::ObjectNotFound(id.to_string()));
+        }
+
+        self.read_base(id, 0)
+    }
+
+    /// like `read_raw`, for the base of a delta which was found `depth` packs away
+    pub fn read_base(
+        &self,
+        id: &ObjectId,
+        depth: usize,
+    ) -> Result<(ObjectType, Vec<u8>), GitError> {
+        match self.find_loose(id) {
+            Some((store, _)) => store.read_loose(id),
+            None => self.read_packed(id, depth),
+        }
+    }
+
+    /// the loose copy of the object only, even if it's in a pack as well
+    pub fn read_loose(&self, id: &ObjectId) -> Result<(ObjectType, Vec<u8>), GitError> {
+        use flate2::bufread::ZlibDecoder;
+
+        let path = self.path(id);
+        let compressed = fs::read(&path)?;
+        let corrupt = |offset: usize, reason: &str| GitError::Decode {
+            id: Some(*id),
+            path: Some(path.clone()),
+            offset: offset as u64,
+            reason: reason.to_string(),
+        };
+        let mut content = Vec::new();
+        ZlibDecoder::new(&compressed[..])
+            .read_to_end(&mut content)
+            .map_err(|e| corrupt(content.len(), &e.to_string()))?;
+
+        let nul = content
+            .iter()
+            .position(|&c| c == b'\0')
+            .ok_or_else(|| corrupt(0, "no header"))?;
+        let (kind, size) = split_header(&content[..nul]).ok_or_else(|| corrupt(0, "bad header"))?;
+        if content.len() - nul - 1 != size {
+            return Err(corrupt(
+                content.len(),
+                "the size in the header doesn't match the content",
+            ));
+        }
+        content.drain(..=nul);
+        Ok((kind, content))
+    }
+
+    /// names of every loose object, in no particular order
+    pub fn loose_ids(&self) -> Result<Vec<ObjectId>, GitError> {
+        let mut ids = Vec::new();
+        let entries = match fs::read_dir(&self.objects_dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(ids),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let dir_name = entry.file_name().to_string_lossy().to_string();
+            if dir_name.len() != 2 || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(entry.path())? {
+                let name = format!("{}{}", dir_name, file?.file_name().to_string_lossy());
+                match name.parse::<ObjectId>() {
+                    Ok(id) if id.format() == self.format => ids.push(id),
+                    // temporary files, garbage and so on
+                    _ => continue,
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// hash and compress `size` bytes from `reader` straight into a temporary file,
+    /// and move it into place once its name is known. fails if `reader` doesn't give
+    /// exactly `size` bytes, e.g. when the file is changed while being read
+    pub fn write_stream<R: Read>(
+        &self,
+        kind: ObjectType,
+        size: u64,
+        reader: R,
+    ) -> Result<ObjectId, GitError> {
+        use flate2::{write::ZlibEncoder, Compression};
+
+        let tmp_path = self.tmp_path()?;
+        let result = (|| -> Result<ObjectId, GitError> {
+            let file = BufWriter::new(fs::File::create(&tmp_path)?);
+            let encoder = ZlibEncoder::new(file, Compression::fast());
+            let mut writer = HashWriter::new(encoder, self.format);
+
+            write!(writer, "{} {}\0", kind, size)?;
+            let mut reader = reader.take(size);
+            let copied = io::copy(&mut reader, &mut writer)?;
+            let mut reader = reader.into_inner();
+            if copied != size || reader.read(&mut [0u8; 1])? != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "expect {} bytes, but the content changed while reading",
+                        size
+                    ),
+                )
+                .into());
+            }
+
+            let (encoder, digest) = writer.finish();
+            let file = encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
+            Ok(ObjectId::from_bytes(&digest).expect("hash of a known format"))
+        })();
+
+        match result {
+            Ok(id) => {
+                if self.exists(&id) {
+                    fs::remove_file(&tmp_path)?;
+                } else {
+                    self.install(&tmp_path, &self.path(&id))?;
+                }
+                Ok(id)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path);
+                Err(e)
+            }
+        }
+    }
+
+    /// store the file as a blob, without reading it into memory
+    pub fn write_blob_file(&self, path: &Path) -> Result<ObjectId, GitError> {
+        let file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        self.write_stream(ObjectType::Blob, size, BufReader::new(file))
+    }
+
+    /// open the object for reading, loose objects and undeltified packed objects are
+    /// inflated on demand. deltas have to be resolved in memory anyway, which is fine
+    /// since big files are hardly worth deltifying
+    pub fn open_stream(&self, id: &ObjectId) -> Result<ObjectReader, GitError> {
+        if id.format() != self.format {
+            return Err(GitError::ObjectNotFound(id.to_string()));
+        }
+
+        if let Some((store, _)) = self.find_loose(id) {
+            return store.open_loose(id);
+        }
+
+        for pack in self.stores().flat_map(|store| store.packs.iter()) {
+            let offset = match pack.index().find(id.as_bytes()) {
+                Some(offset) => offset,
+                None => continue,
+            };
+            if let Some((kind, size, reader)) = pack.stream_at(offset)? {
+                return Ok(ObjectReader::new(kind, size, Box::new(reader)));
+            }
+            break;
+        }
+
+        let (kind, content) = self.read_packed(id, 0)?;
+        let size = content.len() as u64;
+        Ok(ObjectReader::new(
+            kind,
+            size,
+            Box::new(Cursor::new(content)),
+        ))
+    }
+
+    /// the loose copy of the object only, inflated on demand
+    pub fn open_loose(&self, id: &ObjectId) -> Result<ObjectReader, GitError> {
+        use flate2::bufread::ZlibDecoder;
+
+        let path = self.path(id);
+        let corrupt = |reason: String| GitError::Decode {
+            id: Some(*id),
+            path: Some(path.clone()),
+            offset: 0,
+            reason,
+        };
+        let mut decoder = ZlibDecoder::new(BufReader::new(fs::File::open(&path)?));
+        let header = read_loose_header(&mut decoder).map_err(|e| corrupt(e.to_string()))?;
+        let (kind, size) =
+            split_header(&header).ok_or_else(|| corrupt("bad header".to_string()))?;
+        Ok(ObjectReader::new(kind, size as u64, Box::new(decoder)))
+    }
+
+    fn read_packed(&self, id: &ObjectId, depth: usize) -> Result<(ObjectType, Vec<u8>), GitError> {
+        if depth > MAX_BASE_DEPTH {
+            return Err(GitError::CorruptRepo(format!(
+                "{}: delta bases nested too deeply across packs",
+                id
+            )));
+        }
+        for pack in self.stores().flat_map(|store| store.packs.iter()) {
+            // bases of REF_DELTA may live in another pack(or repository) or be loose
+            let resolved = pack.read(id.as_bytes(), |base| {
+                let base = ObjectId::from_bytes(base)
+                    .ok_or_else(|| GitError::CorruptRepo(pack.path().display().to_string()))?;
+                self.read_base(&base, depth + 1)
+            })?;
+            if let Some(resolved) = resolved {
+                return Ok(resolved);
+            }
+        }
+        Err(GitError::ObjectNotFound(id.to_string()))
+    }
+
+    /// readers should never see a half written object, so we write into a temporary
+    /// file inside `objects` first, and then rename it to the final place
+    fn write_atomic(&self, path: &Path, content: &[u8]) -> Result<(), GitError> {
+        let tmp_path = self.tmp_path()?;
+        let result = (|| {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(content)?;
+            file.sync_all()
+        })();
+        if let Err(e) = result {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e.into());
+        }
+        self.install(&tmp_path, path)
+    }
+
+    fn tmp_path(&self) -> io::Result<PathBuf> {
+        tmp_file(&self.objects_dir, "tmp_obj")
+    }
+
+    /// move the fully written temporary file to `path`
+    fn install(&self, tmp_path: &Path, path: &Path) -> Result<(), GitError> {
+        let result = (|| {
+            // objects are immutable once written
+            let mut permissions = fs::metadata(tmp_path)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(tmp_path, permissions)?;
+
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::rename(tmp_path, path)
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(tmp_path);
+            // someone else may have written the same object at the same time
+            if path.exists() {
+                return Ok(());
+            }
+        }
+        Ok(result?)
+    }
+}
+
+/// the directories listed in `objects_dir/info/alternates`, one per line. relative
+/// paths are relative to `objects_dir`
+fn read_alternates(objects_dir: &Path) -> Vec<PathBuf> {
+    let content = match fs::read_to_string(objects_dir.join("info").join("alternates")) {
+        Ok(content) => content,
+        Err(_) => return Vec::new(),
+    };
+    content
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| objects_dir.join(line))
+        .collect()
+}
+
+/// `<type> <size>`
+fn split_header(header: &[u8]) -> Option<(ObjectType, usize)> {
+    let space = header.iter().position(|&c| c == b' ')?;
+    let kind = ObjectType::try_from(&header[..space]).ok()?;
+    let size = &header[space + 1..];
+    if size.is_empty() || !size.iter().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let size = std::str::from_utf8(size).ok()?.parse().ok()?;
+    Some((kind, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object::{Blob, Object};
+
+    use super::*;
+
+    const HELLO: &str = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad";
+
+    fn id(hex: &str) -> ObjectId {
+        hex.parse().unwrap()
+    }
+
+    #[test]
+    fn test_read_loose_object() {
+        let database = ObjectDatabase::new("data/objects".into(), ObjectFormat::Sha1);
+        let object = database.read(&id(HELLO)).unwrap();
+        match object {
+            Object::Blob(blob) => assert_eq!(blob.content, b"hello world\n"),
+            _ => panic!("expect blob"),
+        }
+
+        let r = database.read(&ObjectId::null(ObjectFormat::Sha1));
+        assert!(matches!(r, Err(GitError::ObjectNotFound(_))));
+    }
+
+    #[test]
+    fn test_read_packed_object() {
+        let database = ObjectDatabase::new("data/objects".into(), ObjectFormat::Sha1);
+        assert_eq!(database.packs().len(), 1);
+
+        // only in the pack, and stored as a delta against b862596e
+        let packed = id("f7f1a4590c98f633eeb834446a7669e2da5d3b56");
+        assert!(!database.path(&packed).exists());
+        assert!(database.exists(&packed));
+
+        let object = database.read(&packed).unwrap();
+        assert_eq!(database.hash_object(&object), packed);
+
+        let (kind, content) = database.read_raw(&packed).unwrap();
+        assert_eq!(kind, ObjectType::Blob);
+        assert!(
+            content.ends_with(b"line 120 of the delta fixture, long enough to be worth a delta\n")
+        );
+
+        // loose objects are still found when packs exist
+        let (kind, content) = database.read_raw(&id(HELLO)).unwrap();
+        assert_eq!(kind, ObjectType::Blob);
+        assert_eq!(content, b"hello world\n");
+    }
+
+    #[test]
+    fn test_write_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = ObjectDatabase::new(dir.path().to_path_buf(), ObjectFormat::Sha1);
+
+        let object = Object::Blob(Blob::new(b"hello world\n".to_vec()));
+        let id = database.write(&object).unwrap();
+        assert_eq!(id.to_string(), HELLO);
+        assert_eq!(id, database.hash_object(&object));
+
+        // the same as the one written by git
+        let written = fs::read(database.path(&id)).unwrap();
+        let expected = fs::read("data/objects/3b/18e512dba79e4c8300dd08aeb37f8e728b8dad").unwrap();
+        assert_eq!(
+            sha1::decode(&written).unwrap(),
+            sha1::decode(&expected).unwrap()
+        );
+
+        // writing twice is a no-op
+        assert_eq!(database.write(&object).unwrap(), id);
+
+        let read: Vec<u8> = Into::into(&database.read(&id).unwrap());
+        let raw: Vec<u8> = Into::into(&object);
+        assert_eq!(read, raw);
+
+        // no temporary file left behind
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["3b"]);
+    }
+
+    #[test]
+    fn test_sha256_database() {
+        let database = ObjectDatabase::new("data/sha256/objects".into(), ObjectFormat::Sha256);
+        let blob_id = id("0bd69098bd9b9cc5934a610ab65da429b525361147faa7b5b922919e9a23143d");
+        match database.read(&blob_id).unwrap() {
+            Object::Blob(blob) => assert_eq!(blob.content, b"hello world\n"),
+            _ => panic!("expect blob"),
+        }
+        // sha1 names are not valid here
+        assert!(!database.exists(&id(HELLO)));
+
+        let dir = tempfile::tempdir().unwrap();
+        let database = ObjectDatabase::new(dir.path().to_path_buf(), ObjectFormat::Sha256);
+        let object = Object::Blob(Blob::new(b"hello world\n".to_vec()));
+        assert_eq!(database.write(&object).unwrap(), blob_id);
+        assert!(database.exists(&blob_id));
+    }
+
+    #[test]
+    fn test_resolve_prefix() {
+        let database = ObjectDatabase::new("data/objects".into(), ObjectFormat::Sha1);
+        // loose
+        assert_eq!(database.resolve_prefix("3b18e5").unwrap(), id(HELLO));
+        assert_eq!(database.resolve_prefix("3B18E5").unwrap(), id(HELLO));
+        assert_eq!(database.resolve_prefix(HELLO).unwrap(), id(HELLO));
+        // packed only
+        assert_eq!(
+            database.resolve_prefix("f7f1a459").unwrap(),
+            id("f7f1a4590c98f633eeb834446a7669e2da5d3b56")
+        );
+
+        assert!(matches!(
+            database.resolve_prefix("3b1"),
+            Err(GitError::ObjectNotFound(_))
+        ));
+        assert!(matches!(
+            database.resolve_prefix("3b18g5"),
+            Err(GitError::ObjectNotFound(_))
+        ));
+        assert!(matches!(
+            database.resolve_prefix("00000000"),
+            Err(GitError::ObjectNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_ambiguous_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = ObjectDatabase::new(dir.path().to_path_buf(), ObjectFormat::Sha1);
+
+        // find two blobs sharing the first 4 hex digits
+        let mut seen = std::collections::HashMap::new();
+        let (first, second) = (0..)
+            .find_map(|i| {
+                let blob = Object::Blob(Blob::new(format!("{}", i).into_bytes()));
+                let id = database.hash_object(&blob);
+                let prefix = id.to_hex()[..4].to_string();
+                seen.insert(prefix, blob.clone()).map(|other| (other, blob))
+            })
+            .unwrap();
+        let first = database.write(&first).unwrap();
+        let second = database.write(&second).unwrap();
+
+        let prefix = &first.to_hex()[..4];
+        match database.resolve_prefix(prefix) {
+            Err(GitError::AmbiguousObjectName { candidates, .. }) => {
+                let mut expected = vec![first, second];
+                expected.sort();
+                assert_eq!(candidates, expected);
+            }
+            r => panic!("expect ambiguous, got {:?}", r),
+        }
+
+        // a longer prefix tells them apart
+        let hex = first.to_hex();
+        let longer = (5..=hex.len())
+            .map(|n| &hex[..n])
+            .find(|p| !second.starts_with_hex(p))
+            .unwrap();
+        assert_eq!(database.resolve_prefix(longer).unwrap(), first);
+    }
+
+    #[test]
+    fn test_stream_blob_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let objects_dir = dir.path().join("objects");
+        fs::create_dir(&objects_dir).unwrap();
+        let database = ObjectDatabase::new(objects_dir.clone(), ObjectFormat::Sha1);
+
+        let content: Vec<u8> = (0..1 << 20).map(|i| (i * 7 % 251) as u8).collect();
+        let file_path = dir.path().join("big.bin");
+        fs::write(&file_path, &content).unwrap();
+
+        let id = database.write_blob_file(&file_path).unwrap();
+        let blob = Object::Blob(Blob::new(content.clone()));
+        assert_eq!(id, database.hash_object(&blob));
+
+        let mut reader = database.open_stream(&id).unwrap();
+        assert_eq!(reader.kind(), ObjectType::Blob);
+        assert_eq!(reader.size(), content.len() as u64);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, content);
+
+        // readable by the whole-object reader as well
+        let (_, raw) = database.read_raw(&id).unwrap();
+        assert_eq!(raw, content);
+
+        // the size doesn't match the content
+        let r = database.write_stream(ObjectType::Blob, 3, &b"hello"[..]);
+        assert!(r.is_err());
+        let r = database.write_stream(ObjectType::Blob, 10, &b"hello"[..]);
+        assert!(r.is_err());
+        let names: Vec<_> = fs::read_dir(&objects_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 1, "no temporary file left: {:?}", names);
+    }
+
+    #[test]
+    fn test_alternates() {
+        let dir = tempfile::tempdir().unwrap();
+        let objects_dir = |name: &str| {
+            let path = dir.path().join(name).join("objects");
+            fs::create_dir_all(path.join("info")).unwrap();
+            path
+        };
+        let (base_dir, shared_dir, clone_dir) = (
+            objects_dir("base"),
+            objects_dir("shared"),
+            objects_dir("clone"),
+        );
+
+        // clone -> shared(relative) -> base(absolute) -> clone(a cycle)
+        fs::write(
+            clone_dir.join("info/alternates"),
+            "# comment\n../../shared/objects\n",
+        )
+        .unwrap();
+        fs::write(
+            shared_dir.join("info/alternates"),
+            format!("{}\n", base_dir.display()),
+        )
+        .unwrap();
+        fs::write(
+            base_dir.join("info/alternates"),
+            format!("{}\n", clone_dir.display()),
+        )
+        .unwrap();
+
+        let hello = Object::Blob(Blob::new(b"hello world\n".to_vec()));
+        let hello_id = ObjectDatabase::new(base_dir.clone(), ObjectFormat::Sha1)
+            .write(&hello)
+            .unwrap();
+
+        let database = ObjectDatabase::new(clone_dir.clone(), ObjectFormat::Sha1);
+        assert_eq!(database.alternates().len(), 2);
+        assert!(database.exists(&hello_id));
+        assert!(!database.path(&hello_id).exists());
+        assert_eq!(database.resolve_prefix("3b18e5").unwrap(), hello_id);
+        let (kind, content) = database.read_raw(&hello_id).unwrap();
+        assert_eq!(
+            (kind, &content[..]),
+            (ObjectType::Blob, &b"hello world\n"[..])
+        );
+        assert_eq!(database.open_stream(&hello_id).unwrap().size(), 12);
+
+        // nothing is copied, and new objects are written locally
+        assert_eq!(database.write(&hello).unwrap(), hello_id);
+        assert!(!database.path(&hello_id).exists());
+        let other = database
+            .write(&Object::Blob(Blob::new(b"other".to_vec())))
+            .unwrap();
+        assert!(database.path(&other).exists());
+        assert!(!database.alternates()[0].exists(&other));
+
+        // packs of alternates are searched too
+        let packed = ObjectDatabase::new("data/objects".into(), ObjectFormat::Sha1);
+        let packed_id = id("f7f1a4590c98f633eeb834446a7669e2da5d3b56");
+        assert!(!database.exists(&packed_id));
+        let mut database = ObjectDatabase::new(clone_dir, ObjectFormat::Sha1);
+        database.add_alternates_from(packed.objects_dir().as_os_str());
+        assert!(database.exists(&packed_id));
+        assert_eq!(database.read_raw(&packed_id).unwrap().0, ObjectType::Blob);
+    }
+
+    #[test]
+    fn test_cross_pack_delta_cycle() {
+        // two packs of a single REF_DELTA each, whose base is in the other one
+        let dir = tempfile::tempdir().unwrap();
+        let pack_dir = dir.path().join("pack");
+        fs::create_dir_all(&pack_dir).unwrap();
+        let (a, b) = ([0xaau8; 20], [0xbbu8; 20]);
+        for (name, id, base) in [("pack-a", a, b), ("pack-b", b, a)] {
+            let mut pack = b"PACK\0\0\0\x02\0\0\0\x01".to_vec();
+            // a delta from an empty base to `x`
+            let delta = [0u8, 1, 1, b'x'];
+            pack.push(0x70 | delta.len() as u8);
+            pack.extend_from_slice(&base);
+            let mut z = flate2::write::ZlibEncoder::new(Vec::new(), Default::default());
+            z.write_all(&delta).unwrap();
+            pack.extend(z.finish().unwrap());
+            pack.extend_from_slice(&[0; 20]);
+            fs::write(pack_dir.join(format!("{}.pack", name)), &pack).unwrap();
+
+            let mut idx = b"\xfftOc\0\0\0\x02".to_vec();
+            for c in 0..=255u8 {
+                idx.extend_from_slice(&u32::from(c >= id[0]).to_be_bytes());
+            }
+            idx.extend_from_slice(&id);
+            idx.extend_from_slice(&[0; 4]);
+            idx.extend_from_slice(&12u32.to_be_bytes());
+            idx.extend_from_slice(&[0; 40]);
+            fs::write(pack_dir.join(format!("{}.idx", name)), &idx).unwrap();
+        }
+
+        let database = ObjectDatabase::new(dir.path().to_path_buf(), ObjectFormat::Sha1);
+        assert_eq!(database.packs().len(), 2);
+        let id = ObjectId::from_bytes(&a).unwrap();
+        assert!(matches!(
+            database.read_raw(&id),
+            Err(GitError::CorruptRepo(reason)) if reason.contains("nested too deeply")
+        ));
+    }
+
+    #[test]
+    fn test_stream_packed_object() {
+        let database = ObjectDatabase::new("data/objects".into(), ObjectFormat::Sha1);
+        let ids: Vec<_> = database.packs()[0]
+            .index()
+            .ids()
+            .map(|raw| ObjectId::from_bytes(raw).unwrap())
+            .collect();
+        // both undeltified entries and deltas
+        for id in ids {
+            let (kind, content) = database.read_raw(&id).unwrap();
+            let mut reader = database.open_stream(&id).unwrap();
+            assert_eq!(reader.kind(), kind);
+            assert_eq!(reader.size(), content.len() as u64);
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+            assert_eq!(read, content);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! the name of an object, which is the hash of its raw content.
+//!
+//! it's always stored raw, and only turned into hex when shown to people or written
+//! into text objects(commits and tags)
+
+use std::{fmt, str::FromStr};
+
+use crate::utils::{
+    bytes::{bytes_to_hex, double_hex_to_bytes, hex_prefix_matches},
+    hash::ObjectFormat,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ObjectId {
+    Sha1([u8; 20]),
+    Sha256([u8; 32]),
+}
+
+impl ObjectId {
+    /// the format is decided by the length of `raw`
+    pub fn from_bytes(raw: &[u8]) -> Option<Self> {
+        match raw.len() {
+            20 => Some(Self::Sha1(raw.try_into().ok()?)),
+            32 => Some(Self::Sha256(raw.try_into().ok()?)),
+            _ => None,
+        }
+    }
+
+    /// 40 or 64 hex digits, in either case
+    pub fn from_hex(hex: &[u8]) -> Option<Self> {
+        if !hex.len().is_multiple_of(2) || !hex.iter().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Self::from_bytes(&double_hex_to_bytes(hex))
+    }
+
+    /// all zeros, which stands for "no object", e.g. in reflogs
+    pub fn null(format: ObjectFormat) -> Self {
+        match format {
+            ObjectFormat::Sha1 => Self::Sha1([0; 20]),
+            ObjectFormat::Sha256 => Self::Sha256([0; 32]),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.as_bytes().iter().all(|&c| c == 0)
+    }
+
+    pub fn format(&self) -> ObjectFormat {
+        match self {
+            Self::Sha1(_) => ObjectFormat::Sha1,
+            Self::Sha256(_) => ObjectFormat::Sha256,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Sha1(raw) => raw,
+            Self::Sha256(raw) => raw,
+        }
+    }
+
+    pub fn to_hex(&self) -> String {
+        bytes_to_hex(self.as_bytes())
+    }
+
+    /// whether the hex name starts with `prefix`, which must be lowercase
+    pub fn starts_with_hex(&self, prefix: &str) -> bool {
+        hex_prefix_matches(self.as_bytes(), prefix)
+    }
+}
+
+impl AsRef<[u8]> for ObjectId {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s.as_bytes()).ok_or_else(|| format!("invalid object name `{}`", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        let hex = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad";
+        let id: ObjectId = hex.parse().unwrap();
+        assert_eq!(id.format(), ObjectFormat::Sha1);
+        assert_eq!(id.to_string(), hex);
+        assert_eq!(hex.to_uppercase().parse::<ObjectId>(), Ok(id));
+        assert_eq!(ObjectId::from_bytes(id.as_bytes()), Some(id));
+
+        let id: ObjectId = "0bd69098bd9b9cc5934a610ab65da429b525361147faa7b5b922919e9a23143d"
+            .parse()
+            .unwrap();
+        assert_eq!(id.format(), ObjectFormat::Sha256);
+
+        assert!("3b18e5".parse::<ObjectId>().is_err());
+        assert!("zb18e512dba79e4c8300dd08aeb37f8e728b8dad"
+            .parse::<ObjectId>()
+            .is_err());
+        assert!(ObjectId::null(ObjectFormat::Sha1).is_null());
+    }
+
+    #[test]
+    fn test_hex_prefix() {
+        let id: ObjectId = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad".parse().unwrap();
+        assert!(id.starts_with_hex("3b1"));
+        assert!(id.starts_with_hex("3b18e512dba79e4c8300dd08aeb37f8e728b8dad"));
+        assert!(!id.starts_with_hex("3b2"));
+        assert!(!id.starts_with_hex("3b18e512dba79e4c8300dd08aeb37f8e728b8dad0"));
+    }
+}
@@ -1,26 +1,52 @@
 pub mod blob;
 pub mod commit;
+pub mod database;
+pub mod id;
+pub mod signature;
+pub mod stream;
+pub mod tag;
 pub mod tree;
+pub mod walk;
 
 pub use blob::Blob;
 pub use commit::Commit;
+pub use database::ObjectDatabase;
+pub use id::ObjectId;
+pub use signature::Signature;
+pub use tag::Tag;
 pub use tree::{Tree, TreeEntry};
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug)]
 pub enum Object {
     Blob(Blob),
     Tree(Tree),
     Commit(Commit),
+    Tag(Tag),
+}
+
+impl Object {
+    pub fn kind(&self) -> tree::ObjectType {
+        match self {
+            Self::Blob(_) => tree::ObjectType::Blob,
+            Self::Tree(_) => tree::ObjectType::Tree,
+            Self::Commit(_) => tree::ObjectType::Commit,
+            Self::Tag(_) => tree::ObjectType::Tag,
+        }
+    }
 }
 
 /// mode of object, which will be encoded in tree object's content
-/// no supporting submodules yet, whose code is 160000
-#[derive(Clone)]
+///
+/// `Gitlink` is a submodule, whose sha1 is a commit in another repository,
+/// so it must never be looked up in our own object database
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum ObjectMode {
     Dir,
     Normal,
     Executable,
     SymbolLink,
+    Gitlink,
 }
 
 impl std::fmt::Debug for ObjectMode {
@@ -30,6 +56,7 @@ impl std::fmt::Debug for ObjectMode {
             Self::Normal => write!(f, "Normal"),
             Self::Executable => write!(f, "Executable"),
             Self::SymbolLink => write!(f, "SymbolLink"),
+            Self::Gitlink => write!(f, "Gitlink"),
         }
     }
 }
@@ -40,31 +67,46 @@ impl std::fmt::Display for ObjectMode {
             Self::Normal => write!(f, "100644"),
             Self::Executable => write!(f, "100755"),
             Self::SymbolLink => write!(f, "120000"),
+            Self::Gitlink => write!(f, "160000"),
         }
     }
 }
 
-impl From<&[u8]> for ObjectMode {
-    fn from(raw: &[u8]) -> Self {
-        debug_assert!(raw.len() == 6 || (raw.len() == 5 && raw==b"40000"));
-        // dbg!(raw);
+impl ObjectMode {
+    /// the mode as the index stores it
+    pub fn bits(self) -> u32 {
+        match self {
+            Self::Dir => 0o040000,
+            Self::Normal => 0o100644,
+            Self::Executable => 0o100755,
+            Self::SymbolLink => 0o120000,
+            Self::Gitlink => 0o160000,
+        }
+    }
+}
 
+impl TryFrom<&[u8]> for ObjectMode {
+    type Error = ();
+
+    fn try_from(raw: &[u8]) -> Result<Self, Self::Error> {
         match raw {
-            b"40000" => Self::Dir,
-            b"100644" => Self::Normal,
-            b"100755" => Self::Executable,
-            b"120000" => Self::SymbolLink,
-            _ => unreachable!(),
+            b"40000" => Ok(Self::Dir),
+            b"100644" => Ok(Self::Normal),
+            b"100755" => Ok(Self::Executable),
+            b"120000" => Ok(Self::SymbolLink),
+            b"160000" => Ok(Self::Gitlink),
+            _ => Err(()),
         }
     }
 }
 
-impl Into<Vec<u8>> for &Object {
-    fn into(self) -> Vec<u8> {
-        match self {
+impl From<&Object> for Vec<u8> {
+    fn from(object: &Object) -> Self {
+        match object {
             Object::Blob(blob) => blob.into(),
             Object::Tree(tree) => tree.into(),
             Object::Commit(commit) => commit.into(),
+            Object::Tag(tag) => tag.into(),
         }
     }
 }
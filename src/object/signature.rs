@@ -0,0 +1,38 @@
+use bytes::{BufMut, BytesMut};
+
+/// <name> <<email>> <timestamp> <timezone>
+///
+/// name and email are kept as raw bytes, since they are not necessarily utf-8
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    pub name: Vec<u8>,
+    pub email: Vec<u8>,
+    pub timestamp: usize,
+    pub time_zone: String,
+}
+
+impl Signature {
+    pub fn new(name: Vec<u8>, email: Vec<u8>, timestamp: usize, time_zone: String) -> Self {
+        Self {
+            name,
+            email,
+            timestamp,
+            time_zone,
+        }
+    }
+}
+
+impl From<&Signature> for Vec<u8> {
+    fn from(signature: &Signature) -> Self {
+        let timestamp = format!("{}", signature.timestamp);
+        let mut buf = BytesMut::with_capacity(signature.name.len() + signature.email.len() + 32);
+        buf.put(&signature.name[..]);
+        buf.put(&b" <"[..]);
+        buf.put(&signature.email[..]);
+        buf.put(&b"> "[..]);
+        buf.put(timestamp.as_bytes());
+        buf.put_u8(b' ');
+        buf.put(signature.time_zone.as_bytes());
+        (&buf[..]).into()
+    }
+}
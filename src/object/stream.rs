@@ -0,0 +1,114 @@
+//! streaming access to objects, so that big blobs never have to be held in memory
+
+use std::io::{self, Read};
+
+use super::tree::ObjectType;
+
+/// the content of an object(without the `<type> <size><NUL>` header), inflated on
+/// demand. reading fails if the content turns out to be shorter than the header says
+pub struct ObjectReader {
+    kind: ObjectType,
+    size: u64,
+    remaining: u64,
+    inner: Box<dyn Read>,
+}
+
+impl ObjectReader {
+    pub fn new(kind: ObjectType, size: u64, inner: Box<dyn Read>) -> Self {
+        Self {
+            kind,
+            size,
+            remaining: size,
+            inner,
+        }
+    }
+
+    pub fn kind(&self) -> ObjectType {
+        self.kind
+    }
+
+    /// size of the whole content, however much has been read
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.remaining == 0 {
+            return Ok(0);
+        }
+        let max = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.inner.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "object is shorter than its header says",
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+impl std::fmt::Debug for ObjectReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObjectReader")
+            .field("kind", &self.kind)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+/// read the `<type> <size><NUL>` header of a loose object, and nothing more
+pub fn read_loose_header<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    // `commit 18446744073709551615` is the longest valid one
+    const MAX_HEADER: usize = 32;
+
+    let mut header = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        reader.read_exact(&mut byte)?;
+        if byte[0] == b'\0' {
+            return Ok(header);
+        }
+        if header.len() == MAX_HEADER {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "object header too long",
+            ));
+        }
+        header.push(byte[0]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_reader() {
+        let mut reader = ObjectReader::new(ObjectType::Blob, 5, Box::new(&b"hello world"[..]));
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"hello");
+
+        // truncated
+        let mut reader = ObjectReader::new(ObjectType::Blob, 20, Box::new(&b"hello world"[..]));
+        let mut content = Vec::new();
+        let e = reader.read_to_end(&mut content).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_read_loose_header() {
+        let mut content = &b"blob 12\0hello world\n"[..];
+        assert_eq!(read_loose_header(&mut content).unwrap(), b"blob 12");
+        assert_eq!(content, b"hello world\n");
+
+        let mut content = &[b'a'; 64][..];
+        assert!(read_loose_header(&mut content).is_err());
+    }
+}
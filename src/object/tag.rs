@@ -0,0 +1,116 @@
+use bytes::{BufMut, BytesMut};
+
+use super::{commit::put_extra_headers, signature::Signature, tree::ObjectType, ObjectId};
+
+/// tag <content length><NUL>object <object sha>
+/// type <object type>
+/// tag <tag name>
+/// tagger <name> <email> <timestamp> <timezone>
+///
+/// <tag message>
+/// [<signature>]
+///
+/// the tagger is missing in some very old tags, and the signature(if any) is
+/// appended to the message, starting with a `-----BEGIN ...` line. like for commits,
+/// a tagger line which wouldn't be encoded into the same bytes is kept as it is, as
+/// an extra header
+#[derive(Clone, Debug)]
+pub struct Tag {
+    pub object: ObjectId,
+    pub kind: ObjectType,
+    pub tag: String,
+    pub tagger: Option<Signature>,
+    /// the headers after `tagger`, in their order
+    pub extra_headers: Vec<(Vec<u8>, Vec<u8>)>,
+    pub message: Vec<u8>,
+    pub signature: Option<Vec<u8>>,
+}
+
+const SIGNATURE_STARTS: [&[u8]; 3] = [
+    b"-----BEGIN PGP SIGNATURE-----",
+    b"-----BEGIN PGP MESSAGE-----",
+    b"-----BEGIN SSH SIGNATURE-----",
+];
+
+impl Tag {
+    pub fn new(
+        object: ObjectId,
+        kind: ObjectType,
+        tag: String,
+        tagger: Option<Signature>,
+        message: Vec<u8>,
+        signature: Option<Vec<u8>>,
+    ) -> Self {
+        Self {
+            object,
+            kind,
+            tag,
+            tagger,
+            extra_headers: Vec::new(),
+            message,
+            signature,
+        }
+    }
+
+    /// split the body after the headers into message and signature
+    pub fn split_signature(body: &[u8]) -> (Vec<u8>, Option<Vec<u8>>) {
+        let mut line_start = 0;
+        while line_start < body.len() {
+            let line = &body[line_start..];
+            if SIGNATURE_STARTS.iter().any(|start| line.starts_with(start)) {
+                return (
+                    body[..line_start].to_vec(),
+                    Some(body[line_start..].to_vec()),
+                );
+            }
+            match line.iter().position(|&c| c == b'\n') {
+                Some(end) => line_start += end + 1,
+                None => break,
+            }
+        }
+        (body.to_vec(), None)
+    }
+}
+
+impl From<&Tag> for Vec<u8> {
+    fn from(tag: &Tag) -> Self {
+        let mut entry_buf = BytesMut::with_capacity(1024);
+
+        entry_buf.put(&b"object "[..]);
+        entry_buf.put(tag.object.to_hex().as_bytes());
+        entry_buf.put_u8(b'\n');
+
+        entry_buf.put(&b"type "[..]);
+        entry_buf.put(tag.kind.to_string().as_bytes());
+        entry_buf.put_u8(b'\n');
+
+        entry_buf.put(&b"tag "[..]);
+        entry_buf.put(tag.tag.as_bytes());
+        entry_buf.put_u8(b'\n');
+
+        if let Some(ref tagger) = tag.tagger {
+            let tagger: Vec<u8> = tagger.into();
+            entry_buf.put(&b"tagger "[..]);
+            entry_buf.put(&tagger[..]);
+            entry_buf.put_u8(b'\n');
+        }
+        put_extra_headers(&mut entry_buf, &tag.extra_headers);
+
+        entry_buf.put_u8(b'\n');
+        entry_buf.put(&tag.message[..]);
+        if let Some(ref signature) = tag.signature {
+            entry_buf.put(&signature[..]);
+        }
+
+        // tag<space><content length><NULL><content>
+        let entry_contents = &entry_buf[..];
+        let length = entry_contents.len();
+        let s = format!("{}", length);
+        let mut buf = BytesMut::with_capacity(length + 5 + 10);
+        buf.put(&b"tag "[..]);
+        buf.put(s.as_bytes());
+        buf.put_u8(b'\0');
+        buf.put(entry_contents);
+        (&buf[..]).into()
+    }
+}
@@ -1,11 +1,15 @@
+use std::{cmp::Ordering, collections::HashSet};
+
 use bytes::{BufMut, BytesMut};
-use nom::AsBytes;
 
-use crate::utils::bytes::double_hex_to_bytes;
+use crate::{GitError, REPO_NAME};
 
-use super::ObjectMode;
+use super::{ObjectId, ObjectMode};
 
 // tree <content length><NUL><file mode> <filename><NUL><item sha>...
+//
+// the entries keep the order they are given(decoded trees must encode into the same
+// bytes), build new trees with `TreeBuilder` to get the canonical order
 #[derive(Clone, Debug)]
 pub struct Tree {
     pub entrys: Vec<TreeEntry>,
@@ -15,43 +19,269 @@ impl Tree {
     pub fn new(entrys: Vec<TreeEntry>) -> Self {
         Self { entrys }
     }
+
+    /// whether git would accept the tree: valid unique names, in the canonical order.
+    /// the object database refuses to write trees which fail this
+    pub fn validate(&self) -> Result<(), GitError> {
+        let mut names = HashSet::with_capacity(self.entrys.len());
+        let mut last: Option<&TreeEntry> = None;
+        for entry in self.entrys.iter() {
+            entry.check_name()?;
+            if !names.insert(&entry.filename[..]) {
+                return Err(entry.error("duplicate entry"));
+            }
+            if let Some(last) = last {
+                if last.compare(entry) != Ordering::Less {
+                    return Err(entry.error("entries not sorted"));
+                }
+            }
+            last = Some(entry);
+        }
+        Ok(())
+    }
 }
 
-#[derive(Clone, Debug)]
+/// collects the entries of a new tree, and sorts them the way git does
+#[derive(Clone, Debug, Default)]
+pub struct TreeBuilder {
+    entrys: Vec<TreeEntry>,
+    names: HashSet<Vec<u8>>,
+}
+
+impl TreeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// names must be unique, non-empty, without `/` or NUL, and none of `.`, `..`
+    /// and `.git`
+    pub fn insert(&mut self, entry: TreeEntry) -> Result<&mut Self, GitError> {
+        entry.check_name()?;
+        if !self.names.insert(entry.filename.clone()) {
+            return Err(entry.error("duplicate entry"));
+        }
+        self.entrys.push(entry);
+        Ok(self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entrys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entrys.is_empty()
+    }
+
+    pub fn build(mut self) -> Tree {
+        self.entrys.sort_by(TreeEntry::compare);
+        Tree::new(self.entrys)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ObjectType {
     Blob,
     Tree,
     Commit,
+    Tag,
+}
+
+impl TryFrom<&[u8]> for ObjectType {
+    type Error = ();
+
+    fn try_from(raw: &[u8]) -> Result<Self, Self::Error> {
+        match raw {
+            b"blob" => Ok(Self::Blob),
+            b"tree" => Ok(Self::Tree),
+            b"commit" => Ok(Self::Commit),
+            b"tag" => Ok(Self::Tag),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Blob => write!(f, "blob"),
+            Self::Tree => write!(f, "tree"),
+            Self::Commit => write!(f, "commit"),
+            Self::Tag => write!(f, "tag"),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct TreeEntry {
     pub mode: ObjectMode,
-    pub sha1: String,
-    pub filename: String,
+    pub sha1: ObjectId,
+    /// raw bytes, not necessarily utf-8
+    pub filename: Vec<u8>,
 }
 
 impl TreeEntry {
-    pub fn new(mode: ObjectMode, sha1: String, filename: String) -> Self {
+    pub fn new(mode: ObjectMode, sha1: ObjectId, filename: Vec<u8>) -> Self {
         Self {
             mode,
             sha1,
             filename,
         }
     }
+
+    /// the canonical order of entries in a tree
+    pub fn compare(&self, other: &Self) -> Ordering {
+        compare_entry_names(
+            &self.filename,
+            self.mode == ObjectMode::Dir,
+            &other.filename,
+            other.mode == ObjectMode::Dir,
+        )
+    }
+
+    /// the name must be valid for fsck, and safe to check out
+    fn check_name(&self) -> Result<(), GitError> {
+        check_entry_name(&self.filename)
+            .and_then(|_| verify_path(&self.filename, self.mode))
+            .map_err(|reason| self.error(reason))
+    }
+
+    fn error(&self, reason: &'static str) -> GitError {
+        GitError::BadTreeEntry {
+            name: self.filename.clone(),
+            reason,
+        }
+    }
+}
+
+/// why a tree entry can't be called `name`, as git's fsck sees it
+pub fn check_entry_name(name: &[u8]) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("empty filename");
+    }
+    if name.contains(&b'/') {
+        return Err("filename contains `/`");
+    }
+    if name.contains(&b'\0') {
+        return Err("filename contains NUL");
+    }
+    if name == b"." || name == b".." {
+        return Err("filename is `.` or `..`");
+    }
+    if name.eq_ignore_ascii_case(b".git") {
+        return Err("filename is `.git`");
+    }
+    Ok(())
+}
+
+/// why git's `verify_path` refuses to check out `path`, whose components are separated
+/// by `/`. besides `.`, `..` and `.git`, the names NTFS or HFS+ would open as `.git`
+/// are refused(wherever we run, the tree may be checked out on those too), and so is
+/// our own repository directory. a symlink can't be `.gitmodules`
+pub fn verify_path(path: &[u8], mode: ObjectMode) -> Result<(), &'static str> {
+    let mut components = path.split(|&c| c == b'/').peekable();
+    while let Some(name) = components.next() {
+        let is_symlink = components.peek().is_none() && mode == ObjectMode::SymbolLink;
+        verify_component(name, is_symlink)?;
+    }
+    Ok(())
+}
+
+fn verify_component(name: &[u8], is_symlink: bool) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("empty path component");
+    }
+    if name == b"." || name == b".." {
+        return Err("`.` or `..` in path");
+    }
+    if cfg!(windows) && name.contains(&b'\\') {
+        return Err("backslash in path");
+    }
+    if is_dot_file(name, b".git") {
+        return Err("`.git` in path");
+    }
+    if is_dot_file(name, REPO_NAME.as_bytes()) {
+        return Err("repository directory in path");
+    }
+    if is_symlink && is_dot_file(name, b".gitmodules") {
+        return Err("`.gitmodules` is a symlink");
+    }
+    Ok(())
+}
+
+/// whether a file called `name` is `dot_file`(like `.git`) on some filesystem: names
+/// differing in case, HFS+ ignoring some invisible code points, and NTFS dropping
+/// trailing dots and spaces, reading `name:stream` as `name`, and opening short names
+/// such as `GIT~1`
+fn is_dot_file(name: &[u8], dot_file: &[u8]) -> bool {
+    if strip_hfs_ignorable(name).eq_ignore_ascii_case(dot_file) {
+        return true;
+    }
+    let end = name.iter().position(|&c| c == b':').unwrap_or(name.len());
+    let mut ntfs = &name[..end];
+    while let [rest @ .., b' ' | b'.'] = ntfs {
+        ntfs = rest;
+    }
+    if ntfs.eq_ignore_ascii_case(dot_file) {
+        return true;
+    }
+    // up to 6 characters after the dot, `~` and a digit
+    let base = &dot_file[1..dot_file.len().min(7)];
+    ntfs.len() == base.len() + 2
+        && ntfs[..base.len()].eq_ignore_ascii_case(base)
+        && ntfs[base.len()] == b'~'
+        && (b'1'..=b'4').contains(&ntfs[base.len() + 1])
+}
+
+/// the utf-8 of the code points HFS+ ignores in names: U+200C to U+200F, U+202A to
+/// U+202E, U+206A to U+206F and U+FEFF
+fn strip_hfs_ignorable(name: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(name.len());
+    let mut i = 0;
+    while i < name.len() {
+        let ignorable = matches!(
+            name[i..],
+            [0xE2, 0x80, 0x8C..=0x8F, ..]
+                | [0xE2, 0x80, 0xAA..=0xAE, ..]
+                | [0xE2, 0x81, 0xAA..=0xAF, ..]
+                | [0xEF, 0xBB, 0xBF, ..]
+        );
+        if ignorable {
+            i += 3;
+        } else {
+            stripped.push(name[i]);
+            i += 1;
+        }
+    }
+    stripped
 }
 
-impl Into<Vec<u8>> for &Tree {
-    fn into(self) -> Vec<u8> {
+/// the order of entries in a tree: by name, as if the name of a subtree ended with `/`,
+/// so `a.txt` comes before the directory `a`, which comes before `a0`
+pub fn compare_entry_names(a: &[u8], a_is_dir: bool, b: &[u8], b_is_dir: bool) -> Ordering {
+    let len = a.len().min(b.len());
+    match a[..len].cmp(&b[..len]) {
+        Ordering::Equal => {}
+        ordering => return ordering,
+    }
+    let end = |name: &[u8], is_dir: bool| match name.get(len) {
+        Some(&c) => Some(c),
+        None if is_dir => Some(b'/'),
+        None => None,
+    };
+    end(a, a_is_dir).cmp(&end(b, b_is_dir))
+}
+
+impl From<&Tree> for Vec<u8> {
+    fn from(tree: &Tree) -> Self {
         let mut entry_buf = BytesMut::with_capacity(1024);
 
-        for entry in self.entrys.iter() {
+        for entry in tree.entrys.iter() {
             let mode = format!("{}", entry.mode);
             entry_buf.put(mode.as_bytes());
             entry_buf.put_u8(b' ');
-            entry_buf.put(entry.filename.as_bytes());
+            entry_buf.put(&entry.filename[..]);
             entry_buf.put_u8(b'\0');
-            entry_buf.put(double_hex_to_bytes(entry.sha1.as_bytes()).as_bytes());
+            entry_buf.put(entry.sha1.as_bytes());
         }
         // tree<space><content length><NULL><content>
         // here, content is the entry buf
@@ -67,3 +297,128 @@ impl Into<Vec<u8>> for &Tree {
         (&buf[..]).into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_entry_names() {
+        assert_eq!(
+            compare_entry_names(b"a.txt", false, b"a", true),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_entry_names(b"a", true, b"a0", false),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_entry_names(b"a", false, b"a.txt", false),
+            Ordering::Less
+        );
+        assert_eq!(compare_entry_names(b"a", false, b"a", true), Ordering::Less);
+        assert_eq!(
+            compare_entry_names(b"b", false, b"a", true),
+            Ordering::Greater
+        );
+        assert_eq!(compare_entry_names(b"a", true, b"a", true), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_tree_builder() {
+        let id: ObjectId = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad".parse().unwrap();
+        let mut builder = TreeBuilder::new();
+        for (mode, name) in [
+            (ObjectMode::Normal, "a0"),
+            (ObjectMode::Dir, "a"),
+            (ObjectMode::Normal, "a.txt"),
+            (ObjectMode::Executable, "B"),
+        ] {
+            builder
+                .insert(TreeEntry::new(mode, id, name.into()))
+                .unwrap();
+        }
+
+        for (name, reason) in [
+            ("a0", "duplicate entry"),
+            ("a/b", "filename contains `/`"),
+            ("", "empty filename"),
+            ("..", "filename is `.` or `..`"),
+            (".GIT", "filename is `.git`"),
+            ("a\0b", "filename contains NUL"),
+        ] {
+            match builder.insert(TreeEntry::new(ObjectMode::Normal, id, name.into())) {
+                Err(GitError::BadTreeEntry { reason: r, .. }) => assert_eq!(r, reason),
+                r => panic!("expect `{}` to be rejected, got {:?}", name, r.map(|_| ())),
+            }
+        }
+        assert_eq!(builder.len(), 4);
+
+        let tree = builder.build();
+        let names: Vec<_> = tree.entrys.iter().map(|e| &e.filename[..]).collect();
+        assert_eq!(names, [&b"B"[..], b"a.txt", b"a", b"a0"]);
+        assert!(tree.validate().is_ok());
+
+        // the order of the vec is kept as it is, but can't be written
+        let mut unsorted = tree.clone();
+        unsorted.entrys.swap(0, 1);
+        assert!(unsorted.validate().is_err());
+        let mut duplicated = tree;
+        duplicated.entrys[1].filename = b"a".to_vec();
+        assert!(duplicated.validate().is_err());
+    }
+
+    #[test]
+    fn test_verify_path() {
+        use crate::{object::Object, parser::decode::parse_object, utils::hash::ObjectFormat};
+
+        for (path, mode, reason) in [
+            ("src/a.txt", ObjectMode::Normal, None),
+            (".gitignore", ObjectMode::SymbolLink, None),
+            (".github/x", ObjectMode::Normal, None),
+            ("a//b", ObjectMode::Normal, Some("empty path component")),
+            ("/a", ObjectMode::Normal, Some("empty path component")),
+            ("a/../../b", ObjectMode::Normal, Some("`.` or `..` in path")),
+            ("a/.GIT/config", ObjectMode::Normal, Some("`.git` in path")),
+            (".git. . ", ObjectMode::Normal, Some("`.git` in path")),
+            (
+                ".git::$INDEX_ALLOCATION/x",
+                ObjectMode::Normal,
+                Some("`.git` in path"),
+            ),
+            ("GIT~1/config", ObjectMode::Normal, Some("`.git` in path")),
+            (
+                ".g\u{200c}it/hooks",
+                ObjectMode::Normal,
+                Some("`.git` in path"),
+            ),
+            (
+                ".Git-rs/index",
+                ObjectMode::Normal,
+                Some("repository directory in path"),
+            ),
+            (
+                "GIT-RS~1",
+                ObjectMode::Normal,
+                Some("repository directory in path"),
+            ),
+            (
+                "a/.gitmodules",
+                ObjectMode::SymbolLink,
+                Some("`.gitmodules` is a symlink"),
+            ),
+            ("a/.gitmodules", ObjectMode::Normal, None),
+        ] {
+            assert_eq!(verify_path(path.as_bytes(), mode).err(), reason, "{}", path);
+        }
+
+        // decoded trees are checked as well, not only the ones we write
+        let id: ObjectId = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad".parse().unwrap();
+        let tree = Tree::new(vec![TreeEntry::new(ObjectMode::Dir, id, b"GIT~1".to_vec())]);
+        let raw: Vec<u8> = (&Object::Tree(tree)).into();
+        match parse_object(&raw, ObjectFormat::Sha1, &id) {
+            Err(GitError::Decode { reason, .. }) => assert!(reason.contains("`.git` in path")),
+            r => panic!("expect the tree to be rejected, got {:?}", r.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! walk the object graph: commit -> tree and parents, tree -> blobs and subtrees
+
+use std::collections::HashSet;
+
+use crate::GitError;
+
+use super::{tree::ObjectType, Object, ObjectDatabase, ObjectId, ObjectMode};
+
+/// every object reachable from `tips`, each of them only once.
+/// commits and tags come first, in the order we meet them, followed by trees and blobs
+pub fn reachable(
+    database: &ObjectDatabase,
+    tips: &[ObjectId],
+) -> Result<Vec<(ObjectId, ObjectType)>, GitError> {
+    let mut seen = HashSet::new();
+    let mut commits = Vec::new();
+    let mut others = Vec::new();
+
+    let mut stack: Vec<ObjectId> = tips.iter().rev().cloned().collect();
+    while let Some(id) = stack.pop() {
+        if seen.contains(&id) {
+            continue;
+        }
+
+        match database.read(&id)? {
+            Object::Commit(commit) => {
+                seen.insert(id);
+                commits.push((id, ObjectType::Commit));
+                if let Some(ref parents) = commit.parents_sha1 {
+                    stack.extend(parents.iter().rev().cloned());
+                }
+                walk_tree(database, &commit.root_sha1, &mut seen, &mut others)?;
+            }
+            Object::Tag(tag) => {
+                seen.insert(id);
+                commits.push((id, ObjectType::Tag));
+                stack.push(tag.object);
+            }
+            Object::Tree(_) => walk_tree(database, &id, &mut seen, &mut others)?,
+            Object::Blob(_) => {
+                seen.insert(id);
+                others.push((id, ObjectType::Blob));
+            }
+        }
+    }
+
+    commits.extend(others);
+    Ok(commits)
+}
+
+fn walk_tree(
+    database: &ObjectDatabase,
+    id: &ObjectId,
+    seen: &mut HashSet<ObjectId>,
+    objects: &mut Vec<(ObjectId, ObjectType)>,
+) -> Result<(), GitError> {
+    if !seen.insert(*id) {
+        return Ok(());
+    }
+
+    let tree = match database.read(id)? {
+        Object::Tree(tree) => tree,
+        _ => return Err(GitError::CorruptRepo(format!("{} is not a tree", id))),
+    };
+    objects.push((*id, ObjectType::Tree));
+
+    for entry in tree.entrys.iter() {
+        match entry.mode {
+            ObjectMode::Dir => walk_tree(database, &entry.sha1, seen, objects)?,
+            // a commit of the submodule, which lives in another repository
+            ObjectMode::Gitlink => continue,
+            _ => {
+                if !seen.insert(entry.sha1) {
+                    continue;
+                }
+                // no need to read the whole blob, knowing it's there is enough
+                if !database.exists(&entry.sha1) {
+                    return Err(GitError::ObjectNotFound(entry.sha1.to_string()));
+                }
+                objects.push((entry.sha1, ObjectType::Blob));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        object::{Blob, Tree, TreeEntry},
+        utils::hash::ObjectFormat,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_reachable_from_tree() {
+        let database = ObjectDatabase::new("data/objects".into(), ObjectFormat::Sha1);
+        // tree of the pack fixture, with `hello.txt` and `lines.txt`
+        let objects = reachable(
+            &database,
+            &["385277dd12e0bd9805036270d7822d1b1679ea51".parse().unwrap()],
+        )
+        .unwrap();
+
+        assert_eq!(objects.len(), 3);
+        assert_eq!(objects[0].1, ObjectType::Tree);
+        assert!(objects[1..]
+            .iter()
+            .all(|(_, kind)| *kind == ObjectType::Blob));
+    }
+
+    #[test]
+    fn test_skip_gitlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = ObjectDatabase::new(dir.path().to_path_buf(), ObjectFormat::Sha1);
+
+        let blob_id = database
+            .write(&Object::Blob(Blob::new(b"hello world\n".to_vec())))
+            .unwrap();
+        let tree = Tree::new(vec![
+            TreeEntry::new(ObjectMode::Normal, blob_id, "hello.txt".into()),
+            // not in this repository at all
+            TreeEntry::new(
+                ObjectMode::Gitlink,
+                "688c04ef42b9c43ef7586e57e44aba3a71b3b0b1".parse().unwrap(),
+                "submodule".into(),
+            ),
+        ]);
+        let tree_id = database.write(&Object::Tree(tree)).unwrap();
+
+        let objects = reachable(&database, &[tree_id]).unwrap();
+        assert_eq!(objects.len(), 2);
+    }
+}
@@ -1,134 +1,330 @@
 use nom::{
-    branch::alt,
-    bytes::complete::{tag, take, take_till, take_while},
-    error::ParseError,
-    multi::{many0, many_m_n},
+    bytes::complete::{tag, take, take_till, take_while1},
+    character::complete::digit1,
+    combinator::{all_consuming, map, opt, rest, verify},
+    error::{context, ContextError, ErrorKind, ParseError},
+    multi::{count, length_data, length_value, many0},
+    number::complete::{be_u32, be_u64},
     sequence::{delimited, preceded, terminated, tuple},
-    IResult, Parser,
+    IResult,
 };
 
+use std::path::Path;
+
 use crate::{
-    index::{self, Index, IndexEntry},
+    index::{
+        self,
+        extension::{self, *},
+        Index, IndexEntry, INDEX_SIGNATURE,
+    },
     object::{
-        commit::{AuthorInfo, Commit, CommitterInfo},
-        Blob, Object, Tree, TreeEntry,
+        commit::Commit,
+        tree::{check_entry_name, verify_path, ObjectType},
+        Blob, Object, ObjectId, ObjectMode, Signature, Tag, Tree, TreeEntry,
     },
+    parser::error::finish,
     refs::Head,
-    utils::bytes::{bytes_to_hex, bytes_to_string, bytes_to_usize, hex_to_i32},
+    utils::{
+        bytes::bytes_to_string, ewah::EwahBitmap, hash::ObjectFormat, pack::read_ofs_distance,
+    },
+    GitError,
 };
 
-pub fn decode_object<'a, E: ParseError<&'a [u8]>>(content: &'a [u8]) -> IResult<&[u8], Object, E> {
-    let mut parser = alt((decode_blob, decode_tree, decode_commit));
-    Ok(parser.parse(content)?)
+/// `format` decides the length of the object names inside trees, commits and tags
+pub fn decode_object<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    format: ObjectFormat,
+) -> IResult<&'a [u8], Object, E> {
+    let (_, kind) = take_till(|c| c == b' ')(content)?;
+    match kind {
+        b"blob" => decode_blob(content),
+        b"tree" => decode_tree(content, format),
+        b"commit" => decode_commit(content, format),
+        b"tag" => decode_tag(content, format),
+        _ => Err(invalid(content, "unknown object type")),
+    }
+}
+
+/// a failure which stops decoding at `input`, the reason ends up in [`crate::GitError::Decode`]
+fn invalid<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    input: &'a [u8],
+    reason: &'static str,
+) -> nom::Err<E> {
+    nom::Err::Failure(E::add_context(
+        input,
+        reason,
+        E::from_error_kind(input, ErrorKind::Verify),
+    ))
+}
+
+/// the content after the `<type> <size><NUL>` header must be exactly `size` bytes
+fn check_size<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    size: usize,
+) -> Result<(), nom::Err<E>> {
+    if content.len() == size {
+        Ok(())
+    } else {
+        Err(invalid(
+            content,
+            "the size in the header doesn't match the content",
+        ))
+    }
+}
+
+/// a decimal number which fits into `T`
+fn decimal<T: std::str::FromStr>(raw: &[u8]) -> Option<T> {
+    if raw.is_empty() || !raw.iter().all(|c| c.is_ascii_digit() || *c == b'-') {
+        return None;
+    }
+    std::str::from_utf8(raw).ok()?.parse().ok()
+}
+
+/// an object name stored raw, as in trees and the index
+fn raw_id<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    raw: &'a [u8],
+    format: ObjectFormat,
+) -> Result<ObjectId, nom::Err<E>> {
+    ObjectId::from_bytes(raw)
+        .filter(|id| id.format() == format)
+        .ok_or_else(|| invalid(raw, "bad object name"))
+}
+
+/// an object name stored in hex, as in commits and tags
+fn hex_id<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    hex: &'a [u8],
+    format: ObjectFormat,
+) -> Result<ObjectId, nom::Err<E>> {
+    ObjectId::from_hex(hex)
+        .filter(|id| id.format() == format)
+        .ok_or_else(|| invalid(hex, "bad object name"))
+}
+
+/// the `<size><NUL>` after the type of every object
+fn decode_size<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+) -> IResult<&'a [u8], usize, E> {
+    let (rest, size) = context("bad size", terminated(digit1, tag(b"\0")))(content)?;
+    let size = decimal(size).ok_or_else(|| invalid(content, "bad size"))?;
+    Ok((rest, size))
 }
 
-pub fn decode_blob<'a, E: ParseError<&'a [u8]>>(content: &'a [u8]) -> IResult<&[u8], Object, E> {
+pub fn decode_blob<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+) -> IResult<&'a [u8], Object, E> {
     let parser = tag("blob ".as_bytes());
     let (content, _blob) = parser(content)?;
-    let (content, size) = terminated(take_till(|c| c == b'\0'), tag(b"\0"))(content)?;
+    let (content, size) = decode_size(content)?;
+    let (content, body) = context("content shorter than the size", take(size))(content)?;
 
-    let size = bytes_to_usize(size);
-    let blob = Blob::new(content[0..size].to_owned());
+    let blob = Blob::new(body.to_owned());
     Ok((content, Object::Blob(blob)))
 }
 
-pub fn decode_tree<'a, E: ParseError<&'a [u8]>>(content: &'a [u8]) -> IResult<&[u8], Object, E> {
+/// (mode, filename, hash) of a tree entry, before any conversion
+type RawTreeEntry<'a> = (&'a [u8], &'a [u8], &'a [u8]);
+
+pub fn decode_tree<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    format: ObjectFormat,
+) -> IResult<&'a [u8], Object, E> {
     let parser = tag("tree ".as_bytes());
     let (content, _tree) = parser(content)?;
 
-    let (content, _size) = terminated(take_till(|c| c == b'\0'), tag(b"\0"))(content)?;
+    let (content, size) = decode_size(content)?;
+    check_size(content, size)?;
 
     let mode_parser = take_till(|c| c == b' ');
     let name_parser = delimited(tag(b" "), take_till(|c| c == b'\0'), tag(b"\0"));
-    let hex_paser = take(20usize);
+    let hex_paser = take(format.len());
 
     let entry_parser = tuple((mode_parser, name_parser, hex_paser));
     let mut parser = many0(entry_parser);
-    let (content, lines): (_, Vec<(&[u8], &[u8], &[u8])>) = parser(content)?;
+    let (content, lines): (_, Vec<RawTreeEntry>) = parser(content)?;
+    if !content.is_empty() {
+        return Err(invalid(content, "truncated tree entry"));
+    }
 
     let mut entrys = Vec::with_capacity(lines.len());
     for (mode, filename, hex) in lines {
-        let mode = mode.into();
-        let filename = bytes_to_string(filename);
-        let hex = bytes_to_hex(hex);
-        let child = TreeEntry::new(mode, hex, filename);
+        let mode = ObjectMode::try_from(mode).map_err(|_| invalid(mode, "bad mode"))?;
+        // names which would escape the working tree or reach into a repository
+        check_entry_name(filename)
+            .and_then(|_| verify_path(filename, mode))
+            .map_err(|reason| invalid(filename, reason))?;
+        let child = TreeEntry::new(mode, raw_id(hex, format)?, filename.to_vec());
         entrys.push(child);
     }
     Ok((content, Object::Tree(Tree::new(entrys))))
 }
 
-pub fn decode_commit<'a, E: ParseError<&'a [u8]>>(content: &'a [u8]) -> IResult<&[u8], Object, E> {
+pub fn decode_commit<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    format: ObjectFormat,
+) -> IResult<&'a [u8], Object, E> {
     let parser = tag(b"commit ");
     let (content, _commit) = parser(content)?;
 
-    let (content, _size) = terminated(take_till(|c| c == b'\0'), tag(b"\0"))(content)?;
+    let (content, size) = decode_size(content)?;
+    check_size(content, size)?;
 
-    let mut tree_parser = tuple((
-        tag(b"tree"),
-        delimited(tag(b" "), take(40usize), tag(b"\n")),
-    ));
+    let mut tree_parser = context(
+        "bad tree line",
+        tuple((
+            tag(b"tree"),
+            delimited(tag(b" "), take(format.hex_len()), tag(b"\n")),
+        )),
+    );
     let (content, tree_attr) = tree_parser(content)?;
 
-    let hex = tree_attr.1;
-    let root_sha1 = bytes_to_string(hex);
+    let root_sha1 = hex_id(tree_attr.1, format)?;
 
     let parent_entry_parser = tuple((
         tag(b"parent"),
-        delimited(tag(b" "), take(40usize), tag(b"\n")),
+        delimited(tag(b" "), take(format.hex_len()), tag(b"\n")),
     ));
     let mut parents_parser = many0(parent_entry_parser);
     let (content, parent_attrs): (_, Vec<(&[u8], &[u8])>) = parents_parser(content)?;
 
-    let parents_sha1 = if parent_attrs.len() == 0 {
+    let parents_sha1 = if parent_attrs.is_empty() {
         None
     } else {
         let v = parent_attrs
             .iter()
-            .map(|(_, hex)| bytes_to_string(hex))
-            .collect();
+            .map(|(_, hex)| hex_id(hex, format))
+            .collect::<Result<_, _>>()?;
         Some(v)
     };
 
     // author hsqStephenZhang <2250015961@qq.com> 1638597231 +0000\n
     // committer hsqStephenZhang <2250015961@qq.com> 1638597231 +0000\n\nadd tree parse test\n
     // \nmessage
+    let (content, author) = opt(|c| decode_signature_header(b"author", c))(content)?;
+    let (content, committer) = opt(|c| decode_signature_header(b"committer", c))(content)?;
 
-    let raw_infos = bytes_to_string(content);
-    let infos: Vec<_> = raw_infos.splitn(4, "\n").collect();
-    debug_assert!(infos.len() == 4);
+    // other headers(such as `encoding` and `gpgsig`) are kept in their order
+    let (content, extra_headers) = many0(decode_extra_header)(content)?;
+    let (content, _) = context("bad header", tag(b"\n"))(content)?;
+    let (content, message) = rest(content)?;
 
-    let raw_author = infos[0];
-    debug_assert!(raw_author.starts_with("author"));
-    let items = raw_author.split(" ").collect::<Vec<_>>();
-    debug_assert!(items.len() == 5);
+    let mut commit = Commit::new(root_sha1, parents_sha1, author, committer, message.to_vec());
+    commit.extra_headers = extra_headers;
+    Ok((content, Object::Commit(commit)))
+}
 
-    let author_info = Some(AuthorInfo::new(
-        items[1].into(),
-        items[2].into(),
-        bytes_to_usize(items[3].as_bytes()),
-        items[4].into(),
-    ));
+/// `<name> <signature>\n`. a signature which wouldn't be encoded into the same bytes
+/// is not taken, so that it's kept as an extra header instead
+fn decode_signature_header<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    name: &'static [u8],
+    content: &'a [u8],
+) -> IResult<&'a [u8], Signature, E> {
+    let (content, line) = delimited(
+        tuple((tag(name), tag(b" "))),
+        take_till(|c| c == b'\n'),
+        tag(b"\n"),
+    )(content)?;
+    let (_, signature) = decode_signature(line)?;
+    let encoded: Vec<u8> = (&signature).into();
+    if encoded != line {
+        return Err(nom::Err::Error(E::from_error_kind(line, ErrorKind::Verify)));
+    }
+    Ok((content, signature))
+}
 
-    let raw_commiter = infos[1];
-    debug_assert!(raw_commiter.starts_with("committer"));
-    let items = raw_commiter.split(" ").collect::<Vec<_>>();
-    debug_assert!(items.len() == 5);
+/// `<name> <value>\n`, followed by continuation lines which start with a space
+fn decode_extra_header<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+) -> IResult<&'a [u8], (Vec<u8>, Vec<u8>), E> {
+    let (content, name) = take_while1(|c| c != b' ' && c != b'\n')(content)?;
+    let (content, first) = delimited(tag(b" "), take_till(|c| c == b'\n'), tag(b"\n"))(content)?;
+    let (content, continuations) =
+        many0(delimited(tag(b" "), take_till(|c| c == b'\n'), tag(b"\n")))(content)?;
+
+    let mut value = first.to_vec();
+    for line in continuations {
+        value.push(b'\n');
+        value.extend_from_slice(line);
+    }
+    Ok((content, (name.to_vec(), value)))
+}
 
-    let commiter_info = Some(CommitterInfo::new(
-        items[1].into(),
-        items[2].into(),
-        bytes_to_usize(items[3].as_bytes()),
-        items[4].into(),
-    ));
+pub fn decode_tag<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    format: ObjectFormat,
+) -> IResult<&'a [u8], Object, E> {
+    let parser = tag(b"tag ");
+    let (content, _tag) = parser(content)?;
+
+    let (content, size) = decode_size(content)?;
+    check_size(content, size)?;
+
+    let header = |name: &'static [u8], reason: &'static str| {
+        context(
+            reason,
+            delimited(
+                tuple((tag(name), tag(b" "))),
+                take_till(|c| c == b'\n'),
+                tag(b"\n"),
+            ),
+        )
+    };
+    let (content, object) = header(b"object", "bad object line")(content)?;
+    let (content, kind) = header(b"type", "bad type line")(content)?;
+    let (content, name) = header(b"tag", "bad tag line")(content)?;
+    let (content, tagger) = opt(|c| decode_signature_header(b"tagger", c))(content)?;
+    let (content, extra_headers) = many0(decode_extra_header)(content)?;
+    let (content, _) = opt(tag(b"\n"))(content)?;
+    let (content, body) = rest(content)?;
+
+    let object = hex_id(object, format)?;
+    let kind = ObjectType::try_from(kind).map_err(|_| invalid(kind, "unknown object type"))?;
+    let name =
+        String::from_utf8(name.to_vec()).map_err(|_| invalid(name, "tag name is not utf-8"))?;
+    let (message, signature) = Tag::split_signature(body);
+
+    let mut tag = Tag::new(object, kind, name, tagger, message, signature);
+    tag.extra_headers = extra_headers;
+    Ok((content, Object::Tag(tag)))
+}
 
-    let message = infos[3].into();
+/// <name> <<email>> <timestamp> <timezone>, until the end of line.
+/// the name may contain spaces, so we locate it by the angle brackets
+pub fn decode_signature<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+) -> IResult<&'a [u8], Signature, E> {
+    let (content, line) = take_till(|c| c == b'\n')(content)?;
+
+    let lt = line.iter().position(|&c| c == b'<');
+    let gt = line.iter().rposition(|&c| c == b'>');
+    let (lt, gt) = match (lt, gt) {
+        (Some(lt), Some(gt)) if lt < gt => (lt, gt),
+        _ => return Err(nom::Err::Error(E::from_error_kind(line, ErrorKind::Verify))),
+    };
+
+    let name = &line[..lt];
+    let name = name.strip_suffix(b" ").unwrap_or(name);
+    let email = &line[lt + 1..gt];
+
+    let (_, (_, timestamp, _, time_zone)) =
+        tuple((tag(b" "), digit1, tag(b" "), rest))(&line[gt + 1..])?;
+    let timestamp = decimal(timestamp)
+        .ok_or_else(|| nom::Err::Error(E::from_error_kind(timestamp, ErrorKind::Digit)))?;
 
-    let commit = Commit::new(root_sha1, parents_sha1, author_info, commiter_info, message);
-    Ok(("".as_bytes(), Object::Commit(commit)))
+    let signature = Signature::new(
+        name.to_vec(),
+        email.to_vec(),
+        timestamp,
+        bytes_to_string(time_zone),
+    );
+    Ok((content, signature))
 }
 
-pub fn decode_index_entry<'a, E: ParseError<&'a [u8]>>(
+/// `previous` is the path of the entry before, which version 4 compresses paths against
+pub fn decode_index_entry<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
     content: &'a [u8],
+    format: ObjectFormat,
+    version: u32,
+    previous: &[u8],
 ) -> IResult<&'a [u8], IndexEntry, E> {
     use nom::number::complete::u16 as p_u16;
     let p_u16 = p_u16(nom::number::Endianness::Big);
@@ -147,14 +343,42 @@ pub fn decode_index_entry<'a, E: ParseError<&'a [u8]>>(
         p_u32,
         p_u32,
         p_u32,
-        take(20usize),
+        take(format.len()),
         p_u16,
     ));
 
     let (content, (ctime, mtime, dev, kino, mode, uid, gid, files, hex, flags)) = parser(content)?;
 
-    let (content, filepath) = take_till(|c| c == b'\0')(content)?;
-    let (content, padding) = take_while(|c| c == b'\0')(content)?;
+    let extended = flags & index::FLAG_EXTENDED != 0;
+    let (content, extended_flags) = if !extended {
+        (content, 0)
+    } else if version < 3 {
+        return Err(invalid(content, "extended flags in a version 2 index"));
+    } else {
+        context("truncated index entry", p_u16)(content)?
+    };
+
+    let (content, filepath) = if version >= 4 {
+        // the first entry of a block has no previous path, and what it says to strip
+        // is ignored, like git does
+        let (rest, strip) = varint(content)?;
+        let keep = match previous.len().checked_sub(strip) {
+            Some(keep) => keep,
+            None if previous.is_empty() => 0,
+            None => return Err(invalid(content, "bad path compression")),
+        };
+        let (content, suffix) = take_till(|c| c == b'\0')(rest)?;
+        (content, [&previous[..keep], suffix].concat())
+    } else {
+        let (content, filepath) = take_till(|c| c == b'\0')(content)?;
+        (content, filepath.to_vec())
+    };
+    // 1 to 8 NULs, which end the path and pad the entry to a multiple of 8 bytes
+    let padding = index::padding_len(format, version, extended, filepath.len());
+    let (content, nuls) = context("bad padding of index entry", take(padding))(content)?;
+    if nuls.iter().any(|&c| c != b'\0') {
+        return Err(invalid(nuls, "bad padding of index entry"));
+    }
 
     let entry = IndexEntry::new(
         ctime,
@@ -165,17 +389,19 @@ pub fn decode_index_entry<'a, E: ParseError<&'a [u8]>>(
         uid,
         gid,
         files,
-        hex.into(),
+        raw_id(hex, format)?,
         flags,
-        filepath.iter().map(|&v|v as char).collect(),
-        padding.len(),
+        extended_flags,
+        filepath,
+        padding,
     );
 
     Ok((content, entry))
 }
 
-pub fn decode_tree_extension<'a, E: ParseError<&'a [u8]>>(
+pub fn decode_tree_extension<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
     content: &'a [u8],
+    format: ObjectFormat,
 ) -> IResult<&'a [u8], index::TreeExtension, E> {
     use nom::number::complete::u32 as p_u32;
     let p_u32 = p_u32(nom::number::Endianness::Big);
@@ -184,67 +410,409 @@ pub fn decode_tree_extension<'a, E: ParseError<&'a [u8]>>(
 
     // ignore the content length here
     let (content, (_tree, _, root)) =
-        tuple((tree, length, decode_tree_extension_subtree))(content)?;
+        tuple((tree, length, |c| decode_subtree(c, format, 0)))(content)?;
+
+    Ok((content, root))
+}
 
-    return Ok((content, root));
+pub fn decode_tree_extension_subtree<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    format: ObjectFormat,
+) -> IResult<&'a [u8], index::TreeExtension, E> {
+    decode_subtree(content, format, 0)
 }
 
-pub fn decode_tree_extension_subtree<'a, E: ParseError<&'a [u8]>>(
+/// deeper than any sane tree, and shallow enough not to overflow the stack
+const MAX_SUBTREE_DEPTH: usize = 4096;
+
+fn decode_subtree<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
     content: &'a [u8],
+    format: ObjectFormat,
+    depth: usize,
 ) -> IResult<&'a [u8], index::TreeExtension, E> {
+    if depth > MAX_SUBTREE_DEPTH {
+        return Err(invalid(content, "cached trees nested too deeply"));
+    }
     let path = take_till(|c| c == b'\0');
     let entry_num_parser = preceded(tag(b"\0"), take_till(|c| c == b' '));
     let subtree_num_parser = delimited(tag(b" "), take_till(|c| c == b'\n'), tag(b"\n"));
-    let mut tree_meta_parser = tuple((path, entry_num_parser, subtree_num_parser));
+    let mut tree_meta_parser = context(
+        "bad cached tree",
+        tuple((path, entry_num_parser, subtree_num_parser)),
+    );
     let (content, (path, entry_num, subtree_num)) = tree_meta_parser(content)?;
 
-    let path = String::from_utf8(path.to_owned()).unwrap();
-    let entry_num = hex_to_i32(entry_num);
-    let subtree_num = hex_to_i32(subtree_num);
+    let path = path.to_vec();
+    let entry_num: i32 = decimal(entry_num)
+        .filter(|&n| n >= -1)
+        .ok_or_else(|| invalid(entry_num, "bad entry count of cached tree"))?;
+    let subtree_num: i32 = decimal(subtree_num)
+        .filter(|&n| n >= 0)
+        .ok_or_else(|| invalid(subtree_num, "bad subtree count of cached tree"))?;
 
     let (mut content, hex) = if entry_num != -1 {
-        let hex_parser = take(20usize);
+        let mut hex_parser = context("truncated cached tree", take(format.len()));
         let (content, hex) = hex_parser(content)?;
-        (content, Some(hex.to_owned()))
+        (content, Some(raw_id(hex, format)?))
     } else {
         (content, None)
     };
 
-    let mut subtrees = Vec::with_capacity(subtree_num as usize);
+    // every subtree takes a few bytes at least
+    let mut subtrees = Vec::with_capacity((subtree_num as usize).min(content.len()));
 
     for _ in 0..subtree_num as usize {
-        let (__content, subtree) = decode_tree_extension_subtree(content)?;
+        let (__content, subtree) = decode_subtree(content, format, depth + 1)?;
         content = __content;
         subtrees.push(subtree);
     }
 
-    return Ok((
+    Ok((
         content,
         index::TreeExtension::new(path, entry_num, subtree_num, hex, subtrees),
-    ));
+    ))
 }
 
-pub fn decode_index<'a, E: ParseError<&'a [u8]>>(content: &'a [u8]) -> IResult<&'a [u8], Index, E> {
-    use nom::number::complete::u32 as p_u32;
-    let p_u32 = p_u32(nom::number::Endianness::Big);
-    let mut parser = tuple((p_u32, p_u32, p_u32));
-    let (content, (dirc, version, num_entrys)) = parser(content)?;
+/// a number as git writes them in index version 4 and the untracked cache
+fn varint<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+) -> IResult<&'a [u8], usize, E> {
+    let mut reader = content;
+    let n = read_ofs_distance(&mut reader).map_err(|_| invalid(content, "bad number"))?;
+    Ok((reader, n as usize))
+}
 
-    let mut entrys_parser = many_m_n(num_entrys as usize, num_entrys as usize, decode_index_entry);
-    let checksum_parser = take(20usize);
+/// a compressed bitmap
+pub fn decode_ewah<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+) -> IResult<&'a [u8], EwahBitmap, E> {
+    let (content, (bit_size, word_count)) =
+        context("truncated bitmap", tuple((be_u32, be_u32)))(content)?;
+    // check the count before trusting it for the allocation
+    let size = (word_count as usize)
+        .checked_mul(8)
+        .filter(|&n| n <= content.len());
+    let size = size.ok_or_else(|| invalid(content, "truncated bitmap"))?;
+    let (content, raw) = take(size)(content)?;
+    let words = raw
+        .chunks(8)
+        .map(|w| u64::from_be_bytes(w.try_into().expect("8 bytes")))
+        .collect();
+    let (rest, rlw) = context("truncated bitmap", be_u32)(content)?;
+    let bitmap = EwahBitmap::new(bit_size, words, rlw);
+    if rlw >= word_count || !bitmap.is_consistent() {
+        return Err(invalid(content, "bad bitmap"));
+    }
+    Ok((rest, bitmap))
+}
 
-    let (content, entrys) = entrys_parser(content)?;
+fn is_known_extension(signature: &[u8; 4]) -> bool {
+    [
+        RESOLVE_UNDO,
+        UNTRACKED_CACHE,
+        SPLIT_INDEX_LINK,
+        END_OF_INDEX_ENTRIES,
+        INDEX_ENTRY_OFFSETS,
+        FS_MONITOR,
+    ]
+    .contains(&signature)
+}
 
-    let (content, tree_extension) = if content.len() >= 4 && &content[..4] == b"TREE" {
-        let (content, tree_extension) = decode_tree_extension(content)?;
-        (content, Some(tree_extension))
-    } else {
-        (content, None)
+/// every byte of the data of an extension must be understood
+fn finish_extension<'a, T, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    data: &'a [u8],
+    reason: &'static str,
+    parser: impl FnMut(&'a [u8]) -> IResult<&'a [u8], T, E>,
+) -> Result<T, nom::Err<E>> {
+    context(reason, all_consuming(parser))(data).map(|(_, value)| value)
+}
+
+/// the data of an extension other than `TREE`, unknown ones are kept as they are
+pub fn decode_extension<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    signature: [u8; 4],
+    data: &'a [u8],
+    format: ObjectFormat,
+) -> Result<Extension, nom::Err<E>> {
+    let extension = match &signature {
+        RESOLVE_UNDO => {
+            let entrys = many0(|c| decode_resolve_undo(c, format));
+            Extension::ResolveUndo(finish_extension(data, "bad REUC extension", entrys)?)
+        }
+        UNTRACKED_CACHE => {
+            let cache = |c| decode_untracked_cache(c, format);
+            Extension::UntrackedCache(Box::new(finish_extension(
+                data,
+                "bad UNTR extension",
+                cache,
+            )?))
+        }
+        SPLIT_INDEX_LINK => {
+            let bitmaps = opt(tuple((decode_ewah, decode_ewah)));
+            let link = tuple((take(format.len()), bitmaps));
+            let (id, bitmaps) = finish_extension(data, "bad link extension", link)?;
+            let shared_index = raw_id(id, format)?;
+            Extension::SplitIndexLink(SplitIndexLink {
+                shared_index,
+                bitmaps,
+            })
+        }
+        END_OF_INDEX_ENTRIES => {
+            let end = tuple((be_u32, take(format.len())));
+            let (offset, hash) = finish_extension(data, "bad EOIE extension", end)?;
+            Extension::EndOfIndexEntries(EndOfIndexEntries {
+                offset,
+                hash: hash.to_vec(),
+            })
+        }
+        INDEX_ENTRY_OFFSETS => {
+            let block = map(tuple((be_u32, be_u32)), |(offset, count)| {
+                EntryOffsetBlock { offset, count }
+            });
+            let version = verify(be_u32, |&v| v == INDEX_ENTRY_OFFSETS_VERSION);
+            let blocks = preceded(version, many0(block));
+            Extension::IndexEntryOffsets(finish_extension(data, "bad IEOT extension", blocks)?)
+        }
+        FS_MONITOR => Extension::FsMonitor(finish_extension(
+            data,
+            "bad FSMN extension",
+            decode_fs_monitor,
+        )?),
+        _ => Extension::Unknown {
+            signature,
+            data: data.to_vec(),
+        },
+    };
+    Ok(extension)
+}
+
+/// <path><NUL><3 octal modes, each ended by NUL><ids of the stages whose mode isn't 0>
+fn decode_resolve_undo<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    format: ObjectFormat,
+) -> IResult<&'a [u8], ResolveUndo, E> {
+    let field = || terminated(take_till(|c| c == b'\0'), tag(b"\0"));
+    let (mut content, (path, raw_modes)) = tuple((field(), count(field(), 3)))(content)?;
+    let mut modes = [0; 3];
+    let mut ids = [None; 3];
+    for (i, raw) in raw_modes.into_iter().enumerate() {
+        modes[i] = std::str::from_utf8(raw)
+            .ok()
+            .and_then(|raw| u32::from_str_radix(raw, 8).ok())
+            .ok_or_else(|| invalid(raw, "bad mode"))?;
+    }
+    for i in 0..3 {
+        if modes[i] != 0 {
+            let (rest, id) = take(format.len())(content)?;
+            ids[i] = Some(raw_id(id, format)?);
+            content = rest;
+        }
+    }
+    Ok((
+        content,
+        ResolveUndo {
+            path: path.to_vec(),
+            modes,
+            ids,
+        },
+    ))
+}
+
+fn decode_stat<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+) -> IResult<&'a [u8], StatData, E> {
+    let (content, values) = count(be_u32, 9)(content)?;
+    Ok((content, StatData(values.try_into().expect("9 values"))))
+}
+
+/// see `put_untracked_cache` in `index::extension` for the layout
+fn decode_untracked_cache<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    format: ObjectFormat,
+) -> IResult<&'a [u8], UntrackedCache, E> {
+    let (content, idents) = length_data(varint)(content)?;
+    // NUL terminated strings
+    let ident = terminated(take_till(|c| c == b'\0'), tag(b"\0"));
+    let (_, idents) = context("bad ident", all_consuming(many0(ident)))(idents)?;
+    let idents = idents.into_iter().map(|ident| ident.to_vec()).collect();
+    let ids = tuple((take(format.len()), take(format.len())));
+    let name = terminated(take_till(|c| c == b'\0'), tag(b"\0"));
+    let (
+        content,
+        (
+            info_exclude,
+            excludes_file,
+            (info_id, excludes_id),
+            dir_flags,
+            exclude_per_dir,
+            dir_count,
+        ),
+    ) = tuple((decode_stat, decode_stat, ids, be_u32, name, varint))(content)?;
+    let mut cache = UntrackedCache {
+        idents,
+        info_exclude,
+        excludes_file,
+        info_exclude_id: raw_id(info_id, format)?,
+        excludes_file_id: raw_id(excludes_id, format)?,
+        dir_flags,
+        exclude_per_dir: exclude_per_dir.to_vec(),
+        root: None,
+    };
+    if dir_count == 0 {
+        return Ok((content, cache));
+    }
+
+    let mut dir_left = dir_count;
+    let (content, mut root) = decode_untracked_dir(content, &mut dir_left, 0)?;
+    if dir_left != 0 {
+        return Err(invalid(content, "bad directory count"));
+    }
+    let (mut content, (valid, check_only, id_valid)) =
+        tuple((decode_ewah, decode_ewah, decode_ewah))(content)?;
+    let mut stats = vec![None; dir_count];
+    let mut check_onlys = vec![false; dir_count];
+    let mut ids = vec![None; dir_count];
+    for i in valid.iter() {
+        let stat = stats
+            .get_mut(i)
+            .ok_or_else(|| invalid(content, "bad directory bitmap"))?;
+        let (rest, data) = decode_stat(content)?;
+        *stat = Some(data);
+        content = rest;
+    }
+    for i in check_only.iter() {
+        *check_onlys
+            .get_mut(i)
+            .ok_or_else(|| invalid(content, "bad directory bitmap"))? = true;
+    }
+    for i in id_valid.iter() {
+        let slot = ids
+            .get_mut(i)
+            .ok_or_else(|| invalid(content, "bad directory bitmap"))?;
+        let (rest, id) = take(format.len())(content)?;
+        *slot = Some(raw_id(id, format)?);
+        content = rest;
+    }
+    let mut i = 0;
+    root.for_each_mut(&mut |dir| {
+        dir.stat = stats[i];
+        dir.check_only = check_onlys[i];
+        dir.exclude_id = ids[i];
+        i += 1;
+    });
+    // the NUL git puts at the end
+    let (content, _) = opt(tag(b"\0"))(content)?;
+    cache.root = Some(root);
+    Ok((content, cache))
+}
+
+/// <untracked count><dir count><name><NUL><untracked names, each ended by NUL><dirs>
+fn decode_untracked_dir<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    dir_left: &mut usize,
+    depth: usize,
+) -> IResult<&'a [u8], UntrackedDir, E> {
+    if *dir_left == 0 || depth > MAX_SUBTREE_DEPTH {
+        return Err(invalid(content, "bad directory count"));
+    }
+    *dir_left -= 1;
+    let name = || terminated(take_till(|c| c == b'\0'), tag(b"\0"));
+    let (mut content, (untracked_count, dir_count, dir_name)) =
+        tuple((varint, varint, name()))(content)?;
+    // every name takes a byte at least
+    let mut untracked = Vec::with_capacity(untracked_count.min(content.len()));
+    for _ in 0..untracked_count {
+        let (rest, name) = name()(content)?;
+        untracked.push(name.to_vec());
+        content = rest;
+    }
+    let mut dirs = Vec::with_capacity(dir_count.min(*dir_left));
+    for _ in 0..dir_count {
+        let (rest, dir) = decode_untracked_dir(content, dir_left, depth + 1)?;
+        dirs.push(dir);
+        content = rest;
+    }
+    let dir = UntrackedDir {
+        name: dir_name.to_vec(),
+        untracked,
+        dirs,
+        check_only: false,
+        stat: None,
+        exclude_id: None,
+    };
+    Ok((content, dir))
+}
+
+/// <version><nanoseconds(version 1) or token ended by NUL(version 2)><bitmap size><bitmap>
+fn decode_fs_monitor<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+) -> IResult<&'a [u8], FsMonitor, E> {
+    let (content, version) = be_u32(content)?;
+    let (content, token) = match version {
+        1 => map(be_u64, FsMonitorToken::Timestamp)(content)?,
+        2 => map(
+            terminated(take_till(|c| c == b'\0'), tag(b"\0")),
+            |t: &[u8]| FsMonitorToken::Opaque(t.to_vec()),
+        )(content)?,
+        _ => return Err(invalid(content, "unknown version")),
     };
+    let (content, dirty) = length_value(be_u32, all_consuming(decode_ewah))(content)?;
+    Ok((content, FsMonitor { token, dirty }))
+}
+
+pub fn decode_index<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
+    content: &'a [u8],
+    format: ObjectFormat,
+) -> IResult<&'a [u8], Index, E> {
+    use nom::number::complete::u32 as p_u32;
+    let p_u32 = p_u32(nom::number::Endianness::Big);
+    let parser = tuple((p_u32, p_u32, p_u32));
+    let (content, (dirc, version, num_entrys)) = context("truncated header", parser)(content)?;
+    if dirc != INDEX_SIGNATURE {
+        return Err(invalid(content, "not an index file"));
+    }
+    if !index::SUPPORTED_VERSIONS.contains(&version) {
+        return Err(invalid(content, "unsupported index version"));
+    }
+
+    let mut checksum_parser = context("truncated checksum", take(format.len()));
+
+    // don't trust the count for the allocation, an entry takes 62 bytes at least
+    let mut content = content;
+    let mut entrys = Vec::with_capacity((num_entrys as usize).min(content.len() / 62));
+    for _ in 0..num_entrys {
+        let previous = entrys
+            .last()
+            .map_or(&b""[..], |e: &IndexEntry| &e.filepath[..]);
+        let (rest, entry) = context("bad index entry", |c| {
+            decode_index_entry(c, format, version, previous)
+        })(content)?;
+        content = rest;
+        entrys.push(entry);
+    }
+
+    // whatever is left before the checksum are extensions
+    let mut tree_extension = None;
+    let mut extensions = Vec::new();
+    while content.len() > format.len() {
+        let header = tuple((take(4usize), length_data(be_u32)));
+        let (rest, (signature, data)) = context("truncated extension", header)(content)?;
+        let signature: [u8; 4] = signature.try_into().expect("4 bytes");
+        match &signature {
+            b"TREE" => {
+                let tree = |c| decode_tree_extension_subtree(c, format);
+                tree_extension = Some(finish_extension(data, "bad TREE extension", tree)?);
+            }
+            _ if extension::is_optional(&signature) || is_known_extension(&signature) => {
+                extensions.push(decode_extension(signature, data, format)?);
+            }
+            _ => return Err(invalid(content, "unknown required extension")),
+        }
+        content = rest;
+    }
 
     let (content, checksum) = checksum_parser(content)?;
 
-    let index = Index::new(
+    let mut index = Index::new(
         dirc,
         version,
         num_entrys,
@@ -252,42 +820,80 @@ pub fn decode_index<'a, E: ParseError<&'a [u8]>>(content: &'a [u8]) -> IResult<&
         checksum.into(),
         tree_extension,
     );
+    index.extensions = extensions;
     Ok((content, index))
 }
 
 // 1. ref: refs/heads/master
 // 2. 48b3d19840c917d8e5990ae629273a0a1cd2b606
-pub fn decode_head_pointer<'a, E: ParseError<&'a [u8]>>(
+pub fn decode_head_pointer<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
     content: &'a [u8],
 ) -> IResult<&'a [u8], Head, E> {
-    let ref_parser = tuple((tag(b"ref: "), take_till(|c| c == b'\0' || c == b'\n'))).map(
-        |(_, res): (&[u8], &[u8])| {
-            let s = String::from_utf8(res.into()).unwrap();
-            Head::Ref(s)
-        },
-    );
-    let sha1_parser = take(20usize).map(|res: &[u8]| Head::Pointer(res.into()));
+    let ref_parser = preceded(tag(b"ref: "), take_till(|c| c == b'\0' || c == b'\n'));
+    let (rest, reference) = opt(ref_parser)(content)?;
+    if let Some(reference) = reference {
+        let reference = String::from_utf8(reference.into())
+            .map_err(|_| invalid(reference, "ref name is not utf-8"))?;
+        return Ok((rest, Head::Ref(reference)));
+    }
+
+    let mut sha1_parser = context("bad HEAD", take_while1(|c: u8| c.is_ascii_hexdigit()));
+    let (content, pointer) = sha1_parser(content)?;
+    Ok((content, Head::Pointer(pointer.into())))
+}
+
+/// decode the whole raw object(`<type> <size><NUL><content>`), `id` is only for
+/// the error
+pub fn parse_object(
+    content: &[u8],
+    format: ObjectFormat,
+    id: &ObjectId,
+) -> Result<Object, GitError> {
+    finish(content, decode_object(content, format))
+        .map_err(|(offset, reason)| GitError::corrupt_object(*id, offset, reason))
+}
 
-    let mut head_parser = alt((ref_parser, sha1_parser));
-    let (content, head) = head_parser.parse(content)?;
-    Ok((content, head))
+/// decode the whole index file read from `path`
+pub fn parse_index(content: &[u8], format: ObjectFormat, path: &Path) -> Result<Index, GitError> {
+    finish(content, decode_index(content, format))
+        .map_err(|(offset, reason)| GitError::corrupt_file(path, offset, reason))
+}
+
+/// decode `HEAD`(or another ref file) read from `path`, the trailing newline is optional
+pub fn parse_head(content: &[u8], path: &Path) -> Result<Head, GitError> {
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    finish(content, decode_head_pointer(content))
+        .map_err(|(offset, reason)| GitError::corrupt_file(path, offset, reason))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
+
     use nom::{AsBytes, IResult};
 
     use crate::{
-        parser::decode::{decode_commit, decode_object, decode_tree},
-        utils::sha1::decode_file,
+        object::{tree::ObjectType, Object, ObjectId, ObjectMode},
+        parser::decode::{decode_commit, decode_object, decode_tag, decode_tree},
+        utils::{bytes::double_hex_to_bytes, hash::ObjectFormat, sha1::decode_file},
+        GitError,
     };
 
-    use super::{decode_blob, decode_head_pointer, decode_index};
+    use super::{
+        decode_blob, decode_head_pointer, decode_index, parse_head, parse_index, parse_object,
+    };
+
+    /// the inflated content of the loose object at `path`
+    fn read_object<P: AsRef<Path>>(path: P) -> Vec<u8> {
+        let mut content = Vec::new();
+        decode_file(path, &mut content).unwrap();
+        content
+    }
 
     #[test]
     fn test_blob_decode_encode() {
         // 3b18e512dba79e4c8300dd08aeb37f8e728b8dad
-        let content = decode_file("data/objects/3b/18e512dba79e4c8300dd08aeb37f8e728b8dad");
+        let content = read_object("data/objects/3b/18e512dba79e4c8300dd08aeb37f8e728b8dad");
         let content = content.as_bytes();
 
         let r: IResult<_, _> = decode_blob(content);
@@ -303,11 +909,11 @@ mod tests {
     #[test]
     fn test_tree_decode_encode() {
         // 8465cd187d9bad9e5a7931c2119f16311f9923a7
-        let content = decode_file("data/objects/84/65cd187d9bad9e5a7931c2119f16311f9923a7");
+        let content = read_object("data/objects/84/65cd187d9bad9e5a7931c2119f16311f9923a7");
         let content = content.as_bytes();
         // println!("content:{:?}\n\n", content);
 
-        let r: IResult<_, _> = decode_tree(content);
+        let r: IResult<_, _> = decode_tree(content, ObjectFormat::Sha1);
         let (_, obj) = r.unwrap();
         dbg!(&obj);
 
@@ -320,11 +926,11 @@ mod tests {
     #[test]
     fn test_tree_decode_encode2() {
         //3bf04fcd182b02137c1d0e9946a2c77ec9ac1164
-        let content = decode_file("data/objects/3b/f04fcd182b02137c1d0e9946a2c77ec9ac1164");
+        let content = read_object("data/objects/3b/f04fcd182b02137c1d0e9946a2c77ec9ac1164");
         let content = content.as_bytes();
         // println!("content:{:?}\n\n", content);
 
-        let r: IResult<_, _> = decode_tree(content);
+        let r: IResult<_, _> = decode_tree(content, ObjectFormat::Sha1);
         let (_, obj) = r.unwrap();
         dbg!(&obj);
 
@@ -334,14 +940,70 @@ mod tests {
         assert_eq!(content, &r[..]);
     }
 
+    #[test]
+    fn test_gitlink_tree_decode_encode() {
+        let mut body = b"100644 hello.txt\0".to_vec();
+        body.extend(double_hex_to_bytes(
+            b"3b18e512dba79e4c8300dd08aeb37f8e728b8dad",
+        ));
+        body.extend_from_slice(b"160000 submodule\0");
+        body.extend(double_hex_to_bytes(
+            b"688c04ef42b9c43ef7586e57e44aba3a71b3b0b1",
+        ));
+        let mut content = format!("tree {}\0", body.len()).into_bytes();
+        content.extend(body);
+
+        let r: IResult<_, _> = decode_tree(&content, ObjectFormat::Sha1);
+        let (_, obj) = r.unwrap();
+        match obj {
+            Object::Tree(ref tree) => {
+                assert_eq!(tree.entrys[1].mode, ObjectMode::Gitlink);
+                assert_eq!(format!("{}", tree.entrys[1].mode), "160000");
+                assert_eq!(tree.entrys[1].filename, b"submodule");
+            }
+            _ => panic!("expect tree"),
+        }
+
+        let r: Vec<u8> = Into::into(&obj);
+        assert_eq!(content, r);
+    }
+
+    #[test]
+    fn test_non_utf8_tree_decode_encode() {
+        // `café.txt` in latin-1, and in utf-8
+        let mut body = b"100644 caf\xe9.txt\0".to_vec();
+        body.extend(double_hex_to_bytes(
+            b"3b18e512dba79e4c8300dd08aeb37f8e728b8dad",
+        ));
+        body.extend_from_slice("100644 café.txt\0".as_bytes());
+        body.extend(double_hex_to_bytes(
+            b"3b18e512dba79e4c8300dd08aeb37f8e728b8dad",
+        ));
+        let mut content = format!("tree {}\0", body.len()).into_bytes();
+        content.extend(body);
+
+        let r: IResult<_, _> = decode_tree(&content, ObjectFormat::Sha1);
+        let (_, obj) = r.unwrap();
+        match obj {
+            Object::Tree(ref tree) => {
+                assert_eq!(tree.entrys[0].filename, b"caf\xe9.txt");
+                assert_eq!(tree.entrys[1].filename, "café.txt".as_bytes());
+            }
+            _ => panic!("expect tree"),
+        }
+
+        let r: Vec<u8> = Into::into(&obj);
+        assert_eq!(content, r);
+    }
+
     #[test]
     fn test_commit_decode_encode() {
         // ef074b7c01f72b2a16eea122c90035ff7649d855
-        let content = decode_file("data/objects/ef/074b7c01f72b2a16eea122c90035ff7649d855");
+        let content = read_object("data/objects/ef/074b7c01f72b2a16eea122c90035ff7649d855");
         let content = content.as_bytes();
         // println!("content:{:?}\n\n", content);
 
-        let r: IResult<_, _> = decode_commit(content);
+        let r: IResult<_, _> = decode_commit(content, ObjectFormat::Sha1);
         let (_, obj) = r.unwrap();
         dbg!(&obj);
 
@@ -354,11 +1016,11 @@ mod tests {
     #[test]
     fn test_commit_decode_encode2() {
         // 6f4f12edec17b6c97795f14fe703fc680111ad83
-        let content = decode_file("data/objects/6f/4f12edec17b6c97795f14fe703fc680111ad83");
+        let content = read_object("data/objects/6f/4f12edec17b6c97795f14fe703fc680111ad83");
         let content = content.as_bytes();
         // println!("content:{:?}\n\n", content);
 
-        let r: IResult<_, _> = decode_commit(content);
+        let r: IResult<_, _> = decode_commit(content, ObjectFormat::Sha1);
         let (_, obj) = r.unwrap();
 
         dbg!(&obj);
@@ -369,13 +1031,86 @@ mod tests {
         assert_eq!(content, &r[..]);
     }
 
+    #[test]
+    fn test_signed_commit_decode_encode() {
+        let body = b"tree 385277dd12e0bd9805036270d7822d1b1679ea51\n\
+            parent 688c04ef42b9c43ef7586e57e44aba3a71b3b0b1\n\
+            parent 6f4f12edec17b6c97795f14fe703fc680111ad83\n\
+            author A U Thor <author@example.com> 1638597231 -0130\n\
+            committer C O Mitter <committer@example.com> 1638597299 +0800\n\
+            encoding ISO-8859-1\n\
+            mergetag object 6f4f12edec17b6c97795f14fe703fc680111ad83\n type commit\n tag v1.0\n\
+            \x20\n merged tag\n\
+            gpgsig -----BEGIN PGP SIGNATURE-----\n \n c2lnbmF0dXJl\n -----END PGP SIGNATURE-----\n\
+            x-unknown-header some value\n\
+            \nmessage in latin-1: \xe9t\xe9\n";
+        let mut content = format!("commit {}\0", body.len()).into_bytes();
+        content.extend_from_slice(body);
+
+        let r: IResult<_, _> = decode_commit(&content, ObjectFormat::Sha1);
+        let (_, obj) = r.unwrap();
+        match obj {
+            Object::Commit(ref commit) => {
+                assert_eq!(commit.parents_sha1.as_ref().unwrap().len(), 2);
+                assert_eq!(commit.author.as_ref().unwrap().name, b"A U Thor");
+                let committer = commit.committer.as_ref().unwrap();
+                assert_eq!(committer.name, b"C O Mitter");
+                assert_eq!(committer.timestamp, 1638597299);
+                assert_eq!(commit.encoding(), Some(&b"ISO-8859-1"[..]));
+                assert_eq!(
+                    commit.gpgsig(),
+                    Some(&b"-----BEGIN PGP SIGNATURE-----\n\nc2lnbmF0dXJl\n-----END PGP SIGNATURE-----"[..])
+                );
+                assert!(commit
+                    .header(b"mergetag")
+                    .unwrap()
+                    .ends_with(b"\n\nmerged tag"));
+                assert_eq!(commit.header(b"x-unknown-header"), Some(&b"some value"[..]));
+                assert_eq!(commit.message, b"message in latin-1: \xe9t\xe9\n");
+            }
+            _ => panic!("expect commit"),
+        }
+
+        let r: Vec<u8> = Into::into(&obj);
+        assert_eq!(content, r);
+    }
+
+    #[test]
+    fn test_odd_commit_decode_encode() {
+        // no author, and a committer which can't be encoded the same way
+        let body = b"tree 385277dd12e0bd9805036270d7822d1b1679ea51\n\
+            committer Nobody<nobody@example.com> 0 +0000\n\
+            \n\n\nno message on the first lines";
+        let mut content = format!("commit {}\0", body.len()).into_bytes();
+        content.extend_from_slice(body);
+
+        let r: IResult<_, _> = decode_commit(&content, ObjectFormat::Sha1);
+        let (_, obj) = r.unwrap();
+        match obj {
+            Object::Commit(ref commit) => {
+                assert!(commit.parents_sha1.is_none());
+                assert!(commit.author.is_none());
+                assert!(commit.committer.is_none());
+                assert_eq!(
+                    commit.header(b"committer"),
+                    Some(&b"Nobody<nobody@example.com> 0 +0000"[..])
+                );
+                assert_eq!(commit.message, b"\n\nno message on the first lines");
+            }
+            _ => panic!("expect commit"),
+        }
+
+        let r: Vec<u8> = Into::into(&obj);
+        assert_eq!(content, r);
+    }
+
     #[test]
     fn test_object_decode() {
         // 6f4f12edec17b6c97795f14fe703fc680111ad83
-        let content = decode_file("data/objects/6f/4f12edec17b6c97795f14fe703fc680111ad83");
+        let content = read_object("data/objects/6f/4f12edec17b6c97795f14fe703fc680111ad83");
         let content = content.as_bytes();
 
-        let r: IResult<_, _> = decode_object(content);
+        let r: IResult<_, _> = decode_object(content, ObjectFormat::Sha1);
         let (_, obj) = r.unwrap();
 
         let r: Vec<u8> = Into::into(&obj);
@@ -383,12 +1118,125 @@ mod tests {
         assert_eq!(content, &r[..]);
     }
 
+    #[test]
+    fn test_tag_decode_encode() {
+        // ddc30ff15205e95dda4737268147fc75bd564431
+        let content = read_object("data/objects/dd/c30ff15205e95dda4737268147fc75bd564431");
+        let content = content.as_bytes();
+
+        let r: IResult<_, _> = decode_tag(content, ObjectFormat::Sha1);
+        let (_, obj) = r.unwrap();
+        match obj {
+            Object::Tag(ref tag) => {
+                assert_eq!(
+                    tag.object.to_string(),
+                    "688c04ef42b9c43ef7586e57e44aba3a71b3b0b1"
+                );
+                assert_eq!(tag.kind, ObjectType::Commit);
+                assert_eq!(tag.tag, "v1.0");
+                let tagger = tag.tagger.as_ref().unwrap();
+                assert_eq!(tagger.name, b"C O Mitter");
+                assert_eq!(tagger.email, b"committer@example.com");
+                assert_eq!(tagger.timestamp, 1638597231);
+                assert_eq!(tagger.time_zone, "+0800");
+                assert_eq!(tag.message, b"release 1.0\n\nwith a body\n");
+                assert!(tag.signature.is_none());
+            }
+            _ => panic!("expect tag"),
+        }
+
+        let r: Vec<u8> = Into::into(&obj);
+        assert_eq!(content, &r[..]);
+
+        // through the generic decoder as well
+        let r: IResult<_, _> = decode_object(content, ObjectFormat::Sha1);
+        assert!(matches!(r.unwrap().1, Object::Tag(_)));
+    }
+
+    #[test]
+    fn test_signed_tag_decode_encode() {
+        let body = b"object 688c04ef42b9c43ef7586e57e44aba3a71b3b0b1\ntype commit\ntag v2.0\n\
+            tagger A U Thor <author@example.com> 1638597231 -0130\n\nsigned\n\
+            -----BEGIN PGP SIGNATURE-----\n\nc2lnbmF0dXJl\n-----END PGP SIGNATURE-----\n";
+        let mut content = format!("tag {}\0", body.len()).into_bytes();
+        content.extend_from_slice(body);
+
+        let r: IResult<_, _> = decode_tag(&content, ObjectFormat::Sha1);
+        let (_, obj) = r.unwrap();
+        match obj {
+            Object::Tag(ref tag) => {
+                assert_eq!(tag.tagger.as_ref().unwrap().name, b"A U Thor");
+                assert_eq!(tag.message, b"signed\n");
+                assert!(tag
+                    .signature
+                    .as_ref()
+                    .unwrap()
+                    .starts_with(b"-----BEGIN PGP SIGNATURE-----\n"));
+            }
+            _ => panic!("expect tag"),
+        }
+
+        let r: Vec<u8> = Into::into(&obj);
+        assert_eq!(content, r);
+    }
+
+    #[test]
+    fn test_malformed_tagger() {
+        // no email, kept as it is instead of ending up in the message
+        let body = b"object 688c04ef42b9c43ef7586e57e44aba3a71b3b0b1\ntype commit\ntag v3.0\n\
+            tagger Nobody 1638597231 +0000\n\nmessage\n";
+        let mut content = format!("tag {}\0", body.len()).into_bytes();
+        content.extend_from_slice(body);
+
+        let r: IResult<_, _> = decode_tag(&content, ObjectFormat::Sha1);
+        let (_, obj) = r.unwrap();
+        match obj {
+            Object::Tag(ref tag) => {
+                assert!(tag.tagger.is_none());
+                assert_eq!(
+                    tag.extra_headers,
+                    [(b"tagger".to_vec(), b"Nobody 1638597231 +0000".to_vec())]
+                );
+                assert_eq!(tag.message, b"message\n");
+            }
+            _ => panic!("expect tag"),
+        }
+
+        let r: Vec<u8> = Into::into(&obj);
+        assert_eq!(content, r);
+    }
+
+    #[test]
+    fn test_sha256_decode_encode() {
+        for name in [
+            "a1/9537d9068b2fcc6206eda7375379df5f1eaf4c36874d022d95db12f0793892",
+            "30/20818bf0daf8ec747f9d2e83aee95c441f7152ee5ae14c374be417159ff755",
+            "da/85a39978699f15ee08aa4c04b7b5e58b2d7bb9387b5baa365f5633a256be72",
+        ] {
+            let content = read_object(format!("data/sha256/objects/{}", name));
+            let r: IResult<_, _> = decode_object(&content, ObjectFormat::Sha256);
+            let (_, obj) = r.unwrap();
+            let r: Vec<u8> = Into::into(&obj);
+            assert_eq!(content, r);
+        }
+    }
+
+    #[test]
+    fn test_decode_sha256_index() {
+        let content = std::fs::read("data/sha256/index").unwrap();
+        let r: IResult<_, _> = decode_index(&content, ObjectFormat::Sha256);
+        let (rest, index) = r.unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(index.entrys.len(), 2);
+        assert_eq!(index.entrys[0].sha1.format(), ObjectFormat::Sha256);
+    }
+
     #[test]
     fn test_decode_index() {
         let content = std::fs::read("data/index").unwrap();
         let content = content.as_bytes();
 
-        let r: IResult<_, _> = decode_index(content);
+        let r: IResult<_, _> = decode_index(content, ObjectFormat::Sha1);
         let r = r.unwrap();
         println!("{:?}", r);
     }
@@ -398,7 +1246,7 @@ mod tests {
         let content = std::fs::read("data/index2").unwrap();
         let content = content.as_bytes();
 
-        let r: IResult<_, _> = decode_index(content);
+        let r: IResult<_, _> = decode_index(content, ObjectFormat::Sha1);
         let r = r.unwrap();
         println!("{:?}", r);
     }
@@ -408,7 +1256,7 @@ mod tests {
         let content = std::fs::read(".git/index").unwrap();
         let content = content.as_bytes();
 
-        let r: IResult<_, _> = decode_index(content);
+        let r: IResult<_, _> = decode_index(content, ObjectFormat::Sha1);
         let r = r.unwrap();
         println!("{:?}", r);
     }
@@ -426,4 +1274,77 @@ mod tests {
         let (_, head) = r.unwrap();
         println!("{:?}", head);
     }
+
+    /// (offset, reason) of the error of decoding the raw object
+    fn object_error(content: &[u8]) -> (u64, String) {
+        let id = ObjectId::null(ObjectFormat::Sha1);
+        match parse_object(content, ObjectFormat::Sha1, &id) {
+            Err(GitError::Decode {
+                id: Some(e_id),
+                offset,
+                reason,
+                ..
+            }) => {
+                assert_eq!(e_id, id);
+                (offset, reason)
+            }
+            r => panic!("expect a decode error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(
+            object_error(b"blob 12\0hello"),
+            (8, "content shorter than the size".into())
+        );
+        assert_eq!(
+            object_error(b"blob 1\0hello"),
+            (8, "4 unexpected bytes at the end".into())
+        );
+        assert_eq!(
+            object_error(b"blub 1\0h"),
+            (0, "unknown object type".into())
+        );
+        assert_eq!(object_error(b"blob x\0h"), (5, "bad size".into()));
+
+        let mut tree = b"tree 28\x00100644 a\0".to_vec();
+        tree.extend_from_slice(&[0xaa; 19]);
+        assert_eq!(object_error(&tree), (8, "truncated tree entry".into()));
+        let mut tree = b"tree 29\x00100645 a\0".to_vec();
+        tree.extend_from_slice(&[0xaa; 20]);
+        assert_eq!(object_error(&tree), (8, "bad mode".into()));
+
+        let commit = b"commit 10\0tree 1234\n";
+        assert_eq!(object_error(commit), (15, "bad tree line".into()));
+        let commit = b"commit 53\0tree 385277dd12e0bd9805036270d7822d1b1679ea51\nmessage";
+        assert_eq!(object_error(commit), (56, "bad header".into()));
+
+        let path = Path::new("index");
+        let mut index = b"DIRX\0\0\0\x02\0\0\0\x01".to_vec();
+        match parse_index(&index, ObjectFormat::Sha1, path) {
+            Err(GitError::Decode {
+                path: Some(p),
+                offset: 12,
+                reason,
+                ..
+            }) => {
+                assert_eq!(p, path);
+                assert_eq!(reason, "not an index file");
+            }
+            r => panic!("expect a decode error, got {:?}", r),
+        }
+        // claims 4 billion entries
+        index[3] = b'C';
+        index[8..12].copy_from_slice(&[0xff; 4]);
+        let e = parse_index(&index, ObjectFormat::Sha1, path).unwrap_err();
+        assert!(e.to_string().contains("bad index entry"), "{}", e);
+        index[7] = 5;
+        let e = parse_index(&index, ObjectFormat::Sha1, path).unwrap_err();
+        assert!(e.to_string().contains("unsupported index version"), "{}", e);
+
+        let e = parse_head(b"ref: refs/heads/\xff\n", Path::new("HEAD")).unwrap_err();
+        assert!(e.to_string().contains("ref name is not utf-8"), "{}", e);
+        assert!(parse_head(b"not a head\n", Path::new("HEAD")).is_err());
+    }
 }
@@ -0,0 +1,112 @@
+//! the error of the decoders, which remembers where and why decoding failed
+//!
+//! nom only hands a slice of the input to errors, so they remember where the slice
+//! starts, and the offset is worked out at the end by [`finish`], which knows the
+//! whole input
+
+use nom::{
+    error::{ContextError, ErrorKind, ParseError},
+    IResult,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    /// address of the input where decoding failed, it's never dereferenced
+    at: usize,
+    reason: String,
+    /// whether `reason` is written by us, rather than the name of a nom parser
+    has_context: bool,
+}
+
+impl DecodeError {
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl<'a> ParseError<&'a [u8]> for DecodeError {
+    fn from_error_kind(input: &'a [u8], kind: ErrorKind) -> Self {
+        Self {
+            at: input.as_ptr() as usize,
+            reason: format!("unexpected input for {}", kind.description()),
+            has_context: false,
+        }
+    }
+
+    /// the innermost error is the most precise one
+    fn append(_input: &'a [u8], _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    /// of the alternatives, the one which went further
+    fn or(self, other: Self) -> Self {
+        if other.at > self.at {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+impl<'a> ContextError<&'a [u8]> for DecodeError {
+    fn add_context(_input: &'a [u8], context: &'static str, mut other: Self) -> Self {
+        other.reason = if other.has_context {
+            format!("{}: {}", context, other.reason)
+        } else {
+            context.to_string()
+        };
+        other.has_context = true;
+        other
+    }
+}
+
+/// run the result of a decoder over the whole `input` into its value, or the offset
+/// and reason of the failure. leftover input is an error as well
+pub fn finish<'a, T>(
+    input: &'a [u8],
+    result: IResult<&'a [u8], T, DecodeError>,
+) -> Result<T, (usize, String)> {
+    match result {
+        Ok((rest, _)) if !rest.is_empty() => Err((
+            input.len() - rest.len(),
+            format!("{} unexpected bytes at the end", rest.len()),
+        )),
+        Ok((_, value)) => Ok(value),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            let start = input.as_ptr() as usize;
+            let offset = e.at.saturating_sub(start).min(input.len());
+            Err((offset, e.reason))
+        }
+        Err(nom::Err::Incomplete(_)) => Err((input.len(), "unexpected end".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nom::{
+        bytes::complete::tag,
+        error::context,
+        sequence::{preceded, tuple},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_finish() {
+        let input = &b"tree 12\0"[..];
+        let r = tuple((tag(b"tree "), tag(b"12\0")))(input);
+        assert!(finish(input, r).is_ok());
+
+        let r = preceded(tag(b"tree "), context("bad size", tag(b"13\0")))(input);
+        assert_eq!(finish(input, r), Err((5, "bad size".to_string())));
+
+        let r = context("bad header", preceded(tag(b"tree "), tag(b"13\0")))(input);
+        assert_eq!(finish(input, r), Err((5, "bad header".to_string())));
+
+        let r = tag(b"tree")(input);
+        assert_eq!(
+            finish(input, r),
+            Err((4, "4 unexpected bytes at the end".to_string()))
+        );
+    }
+}
@@ -1,5 +1,199 @@
+//! references, which are either loose files under `refs/`(and `HEAD`),
+//! or lines of `packed-refs`. a loose ref always shadows the packed one.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{object::ObjectId, GitError};
+
 #[derive(Clone, Debug)]
 pub enum Head {
     Ref(String),
     Pointer(Vec<u8>),
 }
+
+/// symbolic refs pointing to each other more than this are treated as a loop
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// resolve `HEAD`, returns `None` if it points to an unborn branch
+pub fn resolve_head(git_dir: &Path) -> Result<Option<ObjectId>, GitError> {
+    read_ref(git_dir, "HEAD")
+}
+
+/// resolve the ref(such as `HEAD` or `refs/heads/master`) to an object name,
+/// following symbolic refs
+pub fn read_ref(git_dir: &Path, name: &str) -> Result<Option<ObjectId>, GitError> {
+    let mut name = name.to_string();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        let value = match read_loose_ref(git_dir, &name)? {
+            Some(value) => value,
+            None => {
+                return Ok(packed_refs(git_dir)?
+                    .into_iter()
+                    .find(|r| r.0 == name)
+                    .map(|r| r.1))
+            }
+        };
+        match value.strip_prefix("ref: ") {
+            Some(target) => name = target.to_string(),
+            None => {
+                let id = value.parse().map_err(|_| {
+                    GitError::CorruptRepo(git_dir.join(&name).display().to_string())
+                })?;
+                return Ok(Some(id));
+            }
+        }
+    }
+    Err(GitError::CorruptRepo(format!(
+        "symbolic ref loop at `{}`",
+        name
+    )))
+}
+
+/// every ref under `refs/`, sorted by name
+pub fn list_refs(git_dir: &Path) -> Result<Vec<(String, ObjectId)>, GitError> {
+    let mut refs = packed_refs(git_dir)?;
+
+    let mut loose = Vec::new();
+    collect_loose_refs(git_dir, &git_dir.join("refs"), &mut loose)?;
+    for name in loose {
+        if let Some(id) = read_ref(git_dir, &name)? {
+            refs.retain(|r| r.0 != name);
+            refs.push((name, id));
+        }
+    }
+
+    refs.sort();
+    Ok(refs)
+}
+
+/// `<hex> <name>` lines, comments start with `#`, and `^<hex>` lines are the
+/// peeled value of the tag above them, which we don't need
+pub fn packed_refs(git_dir: &Path) -> Result<Vec<(String, ObjectId)>, GitError> {
+    let path = git_dir.join("packed-refs");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path)?;
+    let mut refs = Vec::new();
+    for line in content.lines() {
+        if line.starts_with('#') || line.starts_with('^') || line.is_empty() {
+            continue;
+        }
+        let corrupt = || GitError::CorruptRepo(path.display().to_string());
+        let (id, name) = line.split_once(' ').ok_or_else(corrupt)?;
+        refs.push((name.to_string(), id.parse().map_err(|_| corrupt())?));
+    }
+    Ok(refs)
+}
+
+/// point the ref to `id` directly
+pub fn write_ref(git_dir: &Path, name: &str, id: &ObjectId) -> Result<(), GitError> {
+    write_ref_file(&git_dir.join(name), format!("{}\n", id))
+}
+
+/// point the ref to another one, like `HEAD` to `refs/heads/master`
+pub fn write_symbolic_ref(git_dir: &Path, name: &str, target: &str) -> Result<(), GitError> {
+    write_ref_file(&git_dir.join(name), format!("ref: {}\n", target))
+}
+
+/// the ref `HEAD` points to, `None` if it's detached
+pub fn head_ref(git_dir: &Path) -> Result<Option<String>, GitError> {
+    let value = read_loose_ref(git_dir, "HEAD")?;
+    Ok(value.and_then(|value| value.strip_prefix("ref: ").map(str::to_string)))
+}
+
+fn write_ref_file(path: &Path, content: String) -> Result<(), GitError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let lock = lock_path(path);
+    fs::write(&lock, content)?;
+    fs::rename(&lock, path)?;
+    Ok(())
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut lock = path.as_os_str().to_owned();
+    lock.push(".lock");
+    lock.into()
+}
+
+fn read_loose_ref(git_dir: &Path, name: &str) -> Result<Option<String>, GitError> {
+    let path = git_dir.join(name);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    Ok(Some(content.trim_end().to_string()))
+}
+
+fn collect_loose_refs(git_dir: &Path, dir: &Path, refs: &mut Vec<String>) -> Result<(), GitError> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_loose_refs(git_dir, &path, refs)?;
+        } else if path.extension().is_some_and(|ext| ext == "lock") {
+            continue;
+        } else if let Ok(name) = path.strip_prefix(git_dir) {
+            // ref names always use `/`, whatever the platform is
+            let name: Vec<_> = name.iter().map(|c| c.to_string_lossy()).collect();
+            refs.push(name.join("/"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID1: &str = "ef074b7c01f72b2a16eea122c90035ff7649d855";
+    const ID2: &str = "6f4f12edec17b6c97795f14fe703fc680111ad83";
+
+    fn id(hex: &str) -> ObjectId {
+        hex.parse().unwrap()
+    }
+
+    #[test]
+    fn test_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let git_dir = dir.path();
+
+        write_symbolic_ref(git_dir, "HEAD", "refs/heads/master").unwrap();
+        assert_eq!(resolve_head(git_dir).unwrap(), None);
+        assert_eq!(
+            head_ref(git_dir).unwrap().as_deref(),
+            Some("refs/heads/master")
+        );
+
+        write_ref(git_dir, "refs/heads/master", &id(ID1)).unwrap();
+        assert_eq!(resolve_head(git_dir).unwrap(), Some(id(ID1)));
+
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted\n{} refs/heads/master\n{} refs/tags/v1\n^{}\n",
+                ID2, ID2, ID1
+            ),
+        )
+        .unwrap();
+
+        // the loose one wins
+        let refs = list_refs(git_dir).unwrap();
+        assert_eq!(
+            refs,
+            vec![
+                ("refs/heads/master".to_string(), id(ID1)),
+                ("refs/tags/v1".to_string(), id(ID2)),
+            ]
+        );
+        assert_eq!(read_ref(git_dir, "refs/tags/v1").unwrap(), Some(id(ID2)));
+    }
+}
@@ -0,0 +1,70 @@
+//! trees of the object database seen as flat lists of files, the way the index
+//! sees them
+
+use std::collections::BTreeMap;
+
+use crate::{
+    object::{tree::ObjectType, Object, ObjectDatabase, ObjectId, ObjectMode, Tree},
+    GitError,
+};
+
+/// the files under the tree `id`(everything but trees, gitlinks included), by their
+/// paths joined with `/`. byte order of the paths is the order of the index
+pub fn flatten(
+    database: &ObjectDatabase,
+    id: &ObjectId,
+) -> Result<BTreeMap<Vec<u8>, (ObjectMode, ObjectId)>, GitError> {
+    let mut files = BTreeMap::new();
+    flatten_into(database, id, &mut Vec::new(), &mut files)?;
+    Ok(files)
+}
+
+pub fn read_tree(database: &ObjectDatabase, id: &ObjectId) -> Result<Tree, GitError> {
+    match database.read(id)? {
+        Object::Tree(tree) => Ok(tree),
+        object => Err(GitError::UnexpectedType {
+            id: *id,
+            expected: ObjectType::Tree,
+            actual: object.kind(),
+        }),
+    }
+}
+
+/// the tree of the commit `id`, tags are followed to the commit
+pub fn commit_tree(database: &ObjectDatabase, id: &ObjectId) -> Result<ObjectId, GitError> {
+    let mut id = *id;
+    loop {
+        match database.read(&id)? {
+            Object::Commit(commit) => return Ok(commit.root_sha1),
+            Object::Tag(tag) => id = tag.object,
+            object => {
+                return Err(GitError::UnexpectedType {
+                    id,
+                    expected: ObjectType::Commit,
+                    actual: object.kind(),
+                })
+            }
+        }
+    }
+}
+
+fn flatten_into(
+    database: &ObjectDatabase,
+    id: &ObjectId,
+    prefix: &mut Vec<u8>,
+    files: &mut BTreeMap<Vec<u8>, (ObjectMode, ObjectId)>,
+) -> Result<(), GitError> {
+    let tree = read_tree(database, id)?;
+    for entry in tree.entrys {
+        let len = prefix.len();
+        prefix.extend_from_slice(&entry.filename);
+        if entry.mode == ObjectMode::Dir {
+            prefix.push(b'/');
+            flatten_into(database, &entry.sha1, prefix, files)?;
+        } else {
+            files.insert(prefix.clone(), (entry.mode, entry.sha1));
+        }
+        prefix.truncate(len);
+    }
+    Ok(())
+}
@@ -30,8 +30,25 @@ pub fn double_hex_to_bytes(hex: &[u8]) -> Vec<u8> {
         .collect()
 }
 
+/// compare `raw` with a hex `prefix` nibble by nibble, without encoding it first
+pub fn hex_prefix_matches(raw: &[u8], prefix: &str) -> bool {
+    let prefix = prefix.as_bytes();
+    if prefix.len() > raw.len() * 2 {
+        return false;
+    }
+    prefix.iter().enumerate().all(|(i, &c)| {
+        let nibble = if i % 2 == 0 {
+            raw[i / 2] >> 4
+        } else {
+            raw[i / 2] & 0xf
+        };
+        (c as char).to_digit(16) == Some(nibble as u32)
+    })
+}
+
+/// invalid utf-8 is replaced with `U+FFFD`, so it's only for showing bytes to people
 pub fn bytes_to_string(raw: &[u8]) -> String {
-    raw.iter().map(|&v| v as char).collect::<String>()
+    String::from_utf8_lossy(raw).into_owned()
 }
 
 // transfer 1234(0x31 0x32 0x33 0x34) into 1234
@@ -45,7 +62,7 @@ pub fn bytes_to_usize(raw: &[u8]) -> usize {
 }
 
 pub fn hex_to_i32(raw: &[u8]) -> i32 {
-    if raw[0] == b'-' {
+    if raw.first() == Some(&b'-') {
         return -1
     }
     let mut res = 0;
@@ -93,11 +110,11 @@ mod tests {
 
     #[test]
     fn t3() {
-        let a = &[b'1', b'2', b'3'];
+        let a = b"123";
         let r = hex_to_i32(a.as_bytes());
         assert_eq!(r, 123);
 
-        let a = &[b'-', b'1', b'2', b'3'];
+        let a = b"-123";
         let r = hex_to_i32(a.as_bytes());
         assert_eq!(r, -1);
     }
@@ -0,0 +1,300 @@
+//! the compressed bitmaps(EWAH) of git, used by the index extensions
+//!
+//! bitmap := bit size(u32) | number of words(u32) | words(u64 each) | position of the
+//!     last marker word(u32)
+//!
+//! the words are runs of marker words, each followed by its literal words. a marker
+//! word holds the bit of the run(bit 0), the number of words in the run(bits 1-32) and
+//! the number of literal words after it(bits 33-63)
+
+use bytes::{BufMut, BytesMut};
+
+const WORD_BITS: usize = 64;
+const RUNNING_LEN_MASK: u64 = 0xFFFF_FFFF;
+const LARGEST_RUNNING_LEN: u64 = RUNNING_LEN_MASK;
+const LARGEST_LITERAL_COUNT: u64 = (1 << 31) - 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EwahBitmap {
+    bit_size: u32,
+    words: Vec<u64>,
+    /// position of the marker word new words are counted in
+    rlw: u32,
+}
+
+impl Default for EwahBitmap {
+    fn default() -> Self {
+        Self {
+            bit_size: 0,
+            words: vec![0],
+            rlw: 0,
+        }
+    }
+}
+
+impl EwahBitmap {
+    pub fn new(bit_size: u32, words: Vec<u64>, rlw: u32) -> Self {
+        Self {
+            bit_size,
+            words,
+            rlw,
+        }
+    }
+
+    /// the bitmap with the bits at `positions` set, which must be increasing. it's
+    /// built the way git builds them, so they encode into the same bytes
+    pub fn from_positions(positions: impl IntoIterator<Item = usize>) -> Self {
+        let mut bitmap = Self::default();
+        for position in positions {
+            bitmap.set(position);
+        }
+        bitmap
+    }
+
+    /// the number of bits, set or not
+    pub fn bit_size(&self) -> usize {
+        self.bit_size as usize
+    }
+
+    pub fn contains(&self, position: usize) -> bool {
+        self.iter().any(|p| p == position)
+    }
+
+    /// positions of the set bits, in increasing order, found as they are asked for. a
+    /// corrupt bitmap ends early
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let bit_size = self.bit_size as usize;
+        // the first bit of a word, words past the end are cut to the size
+        let first_bit = move |word: usize| word.saturating_mul(WORD_BITS).min(bit_size);
+        self.runs()
+            .flat_map(move |(word_index, bit, running_len, literals)| {
+                let ones = if bit {
+                    first_bit(word_index)..first_bit(word_index + running_len)
+                } else {
+                    0..0
+                };
+                let literals = literals.iter().enumerate().flat_map(move |(i, &literal)| {
+                    let first = first_bit(word_index + running_len + i);
+                    (0..WORD_BITS)
+                        .filter(move |bit| literal & (1 << bit) != 0)
+                        .map(move |bit| first.saturating_add(bit))
+                });
+                ones.chain(literals)
+            })
+            .take_while(move |&p| p < bit_size)
+    }
+
+    /// whether the markers stay within the words, and cover no more words than the
+    /// bits need
+    pub fn is_consistent(&self) -> bool {
+        let mut pos = 0;
+        let mut covered = 0u64;
+        while let Some(&marker) = self.words.get(pos) {
+            let literal_count = marker >> 33;
+            covered += ((marker >> 1) & RUNNING_LEN_MASK) + literal_count;
+            pos += 1 + literal_count as usize;
+            if pos > self.words.len() {
+                return false;
+            }
+        }
+        covered <= (self.bit_size as u64).div_ceil(WORD_BITS as u64)
+    }
+
+    /// (index of the first word, bit of the run, length of the run, literal words) of
+    /// every marker. a literal count past the end is cut, and stops it
+    fn runs(&self) -> impl Iterator<Item = (usize, bool, usize, &[u64])> + '_ {
+        let mut pos = 0;
+        let mut word_index = Some(0usize);
+        std::iter::from_fn(move || {
+            let marker = *self.words.get(pos)?;
+            let start = word_index?;
+            let running_len = ((marker >> 1) & RUNNING_LEN_MASK) as usize;
+            let literal_count = (marker >> 33) as usize;
+            let literals = &self.words[pos + 1..];
+            let literals = &literals[..literal_count.min(literals.len())];
+            pos = pos.saturating_add(1 + literal_count);
+            word_index = start
+                .checked_add(running_len)
+                .and_then(|i| i.checked_add(literals.len()));
+            Some((start, marker & 1 != 0, running_len, literals))
+        })
+    }
+
+    fn marker(&self) -> u64 {
+        self.words[self.rlw as usize]
+    }
+
+    fn set_marker(&mut self, marker: u64) {
+        self.words[self.rlw as usize] = marker;
+    }
+
+    fn running_bit(&self) -> bool {
+        self.marker() & 1 != 0
+    }
+
+    fn running_len(&self) -> u64 {
+        (self.marker() >> 1) & RUNNING_LEN_MASK
+    }
+
+    fn literal_count(&self) -> u64 {
+        self.marker() >> 33
+    }
+
+    fn set_running_bit(&mut self, bit: bool) {
+        self.set_marker(self.marker() & !1 | bit as u64);
+    }
+
+    fn set_running_len(&mut self, len: u64) {
+        self.set_marker(self.marker() & !(RUNNING_LEN_MASK << 1) | len << 1);
+    }
+
+    fn set_literal_count(&mut self, count: u64) {
+        self.set_marker(self.marker() & ((1 << 33) - 1) | count << 33);
+    }
+
+    fn push_marker(&mut self) {
+        self.words.push(0);
+        self.rlw = (self.words.len() - 1) as u32;
+    }
+
+    fn set(&mut self, position: usize) {
+        assert!(
+            position >= self.bit_size as usize,
+            "positions must increase"
+        );
+        let words_before = (self.bit_size as usize).div_ceil(WORD_BITS);
+        let dist = (position + 1).div_ceil(WORD_BITS) - words_before;
+        self.bit_size = position as u32 + 1;
+        let bit = 1u64 << (position % WORD_BITS);
+
+        if dist > 0 {
+            if dist > 1 {
+                self.add_empty_words(false, dist as u64 - 1);
+            }
+            self.add_literal(bit);
+            return;
+        }
+        if self.literal_count() == 0 {
+            // the word is the last of a run of ones
+            self.set_running_len(self.running_len() - 1);
+            self.add_literal(bit);
+            return;
+        }
+        let last = self.words.len() - 1;
+        self.words[last] |= bit;
+        if self.words[last] == u64::MAX {
+            // a full literal word joins the run
+            self.words.pop();
+            self.set_literal_count(self.literal_count() - 1);
+            self.add_empty_word(true);
+        }
+    }
+
+    fn add_literal(&mut self, word: u64) {
+        let count = self.literal_count();
+        if count >= LARGEST_LITERAL_COUNT {
+            self.push_marker();
+            self.set_literal_count(1);
+        } else {
+            self.set_literal_count(count + 1);
+        }
+        self.words.push(word);
+    }
+
+    fn add_empty_word(&mut self, bit: bool) {
+        let no_literal = self.literal_count() == 0;
+        let running_len = self.running_len();
+        if no_literal && running_len == 0 {
+            self.set_running_bit(bit);
+        }
+        if no_literal && self.running_bit() == bit && running_len < LARGEST_RUNNING_LEN {
+            self.set_running_len(running_len + 1);
+        } else {
+            self.push_marker();
+            self.set_running_bit(bit);
+            self.set_running_len(1);
+        }
+    }
+
+    fn add_empty_words(&mut self, bit: bool, mut number: u64) {
+        if self.running_bit() != bit && self.running_len() == 0 && self.literal_count() == 0 {
+            self.set_running_bit(bit);
+        } else if self.literal_count() != 0 || self.running_bit() != bit {
+            self.push_marker();
+            self.set_running_bit(bit);
+        }
+        let running_len = self.running_len();
+        let can_add = number.min(LARGEST_RUNNING_LEN - running_len);
+        self.set_running_len(running_len + can_add);
+        number -= can_add;
+        while number > 0 {
+            let len = number.min(LARGEST_RUNNING_LEN);
+            self.push_marker();
+            self.set_running_bit(bit);
+            self.set_running_len(len);
+            number -= len;
+        }
+    }
+}
+
+impl From<&EwahBitmap> for Vec<u8> {
+    fn from(bitmap: &EwahBitmap) -> Self {
+        let mut buf = BytesMut::with_capacity(12 + bitmap.words.len() * 8);
+        buf.put_u32(bitmap.bit_size);
+        buf.put_u32(bitmap.words.len() as u32);
+        for word in bitmap.words.iter() {
+            buf.put_u64(*word);
+        }
+        buf.put_u32(bitmap.rlw);
+        buf.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_positions() {
+        // as git writes them
+        let bitmap = EwahBitmap::from_positions([0]);
+        assert_eq!(bitmap, EwahBitmap::new(1, vec![1 << 33, 1], 0));
+        let bitmap = EwahBitmap::from_positions([0, 1]);
+        assert_eq!(bitmap, EwahBitmap::new(2, vec![1 << 33, 3], 0));
+        assert_eq!(EwahBitmap::from_positions([]), EwahBitmap::default());
+
+        for positions in [
+            vec![],
+            vec![5],
+            vec![0, 63, 64, 200],
+            (0..64).collect(),
+            (0..200).collect(),
+            (100..300).chain([1000, 5000]).collect(),
+        ] {
+            let bitmap = EwahBitmap::from_positions(positions.iter().copied());
+            assert_eq!(bitmap.iter().collect::<Vec<_>>(), positions);
+        }
+
+        // a full word turns into a run of ones
+        let bitmap = EwahBitmap::from_positions(0..64);
+        assert_eq!(bitmap.words, [2 | 1]);
+        assert!(bitmap.contains(63));
+        assert!(!bitmap.contains(64));
+        assert!(bitmap.is_consistent());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        // a run of 2^32 - 1 words of ones, for 10 bits
+        let bitmap = EwahBitmap::new(10, vec![(0xFFFF_FFFF << 1) | 1], 0);
+        assert_eq!(
+            bitmap.iter().collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>()
+        );
+        assert!(!bitmap.is_consistent());
+        // more literal words than there are
+        let bitmap = EwahBitmap::new(128, vec![2 << 33, 1], 0);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), [0]);
+        assert!(!bitmap.is_consistent());
+    }
+}
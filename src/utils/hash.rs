@@ -0,0 +1,162 @@
+//! the hash function of a repository, chosen by `extensions.objectFormat`
+//!
+//! every object name, tree entry, index entry and checksum of the repository uses
+//! the same hash, so its length must always come from here and never be hard coded
+
+use std::{io::Write, str::FromStr};
+
+use ::sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ObjectFormat {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl ObjectFormat {
+    /// length of the raw hash in bytes
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(self) -> usize {
+        match self {
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+        }
+    }
+
+    /// length of the hex encoded hash
+    pub fn hex_len(self) -> usize {
+        self.len() * 2
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+        }
+    }
+
+    pub fn hash(self, content: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.update(content);
+        hasher.finish()
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha1 => Hasher::Sha1(Sha1::new()),
+            Self::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    /// the name of an object of this format, in hex
+    pub fn is_object_name(self, id: &str) -> bool {
+        id.len() == self.hex_len() && id.bytes().all(|c| c.is_ascii_hexdigit())
+    }
+}
+
+impl std::fmt::Display for ObjectFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ObjectFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            _ => Err(format!("unknown object format `{}`", s)),
+        }
+    }
+}
+
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn update(&mut self, content: &[u8]) {
+        match self {
+            Self::Sha1(hasher) => hasher.update(content),
+            Self::Sha256(hasher) => hasher.update(content),
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        match self {
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// hash everything written through it, e.g. for the trailing checksum of packs
+pub struct HashWriter<W> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> HashWriter<W> {
+    pub fn new(inner: W, format: ObjectFormat) -> Self {
+        Self {
+            inner,
+            hasher: format.hasher(),
+        }
+    }
+
+    /// the inner writer, and the digest of everything written so far
+    pub fn finish(self) -> (W, Vec<u8>) {
+        (self.inner, self.hasher.finish())
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::bytes::bytes_to_hex;
+
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        let content = b"blob 12\0hello world\n";
+        assert_eq!(
+            bytes_to_hex(&ObjectFormat::Sha1.hash(content)),
+            "3b18e512dba79e4c8300dd08aeb37f8e728b8dad"
+        );
+        assert_eq!(
+            bytes_to_hex(&ObjectFormat::Sha256.hash(content)),
+            "0bd69098bd9b9cc5934a610ab65da429b525361147faa7b5b922919e9a23143d"
+        );
+
+        let mut writer = HashWriter::new(Vec::new(), ObjectFormat::Sha256);
+        writer.write_all(&content[..5]).unwrap();
+        writer.write_all(&content[5..]).unwrap();
+        let (written, digest) = writer.finish();
+        assert_eq!(written, content);
+        assert_eq!(digest, ObjectFormat::Sha256.hash(content));
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("SHA256".parse::<ObjectFormat>(), Ok(ObjectFormat::Sha256));
+        assert_eq!("sha1".parse::<ObjectFormat>(), Ok(ObjectFormat::Sha1));
+        assert!("md5".parse::<ObjectFormat>().is_err());
+    }
+}
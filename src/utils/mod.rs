@@ -1,3 +1,7 @@
 pub mod bytes;
+pub mod ewah;
+pub mod hash;
+pub mod pack;
+pub mod path;
 pub mod sha1;
-pub mod path;
\ No newline at end of file
+pub mod wildmatch;
\ No newline at end of file
@@ -0,0 +1,954 @@
+//! packfile(`objects/pack/pack-*.pack`) reader and writer, the reader locates
+//! objects through the `.idx` file next to the pack
+//!
+//! the `.idx` version 2 file layout:
+//!     magic(`\377tOc`) | version(2) | fan-out table(256 * u32)
+//!     | object names(n * hash length) | crc32(n * u32) | offsets(n * u32)
+//!     | large offsets(m * u64) | pack checksum | idx checksum
+//!
+//! the hash(names and checksums) is the one of the repository, see `ObjectFormat`
+//!
+//! the `.pack` file layout:
+//!     `PACK` | version(2) | n(u32) | n entries | pack checksum
+//!
+//! every entry starts with a variable length header of type and inflated size,
+//! deltified entries are followed by their base(a negative offset for OFS_DELTA,
+//! and an object name for REF_DELTA), and then the zlib compressed data.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{write::ZlibEncoder, Compression, Crc};
+
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::eof,
+    multi::count,
+    number::complete::{be_u32, be_u64},
+    IResult,
+};
+
+use crate::{
+    object::tree::ObjectType,
+    utils::{
+        bytes::{bytes_to_hex, hex_prefix_matches},
+        hash::{HashWriter, ObjectFormat},
+        path::tmp_file,
+    },
+    GitError,
+};
+
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+pub const OBJ_OFS_DELTA: u8 = 6;
+pub const OBJ_REF_DELTA: u8 = 7;
+
+const IDX_MAGIC: &[u8] = b"\xfftOc";
+/// way longer than any sane delta chain, only protects us from cycles in a corrupt pack
+const MAX_DELTA_CHAIN: usize = 10000;
+
+#[derive(Clone, Debug)]
+pub struct PackIndex {
+    format: ObjectFormat,
+    fanout: Vec<u32>,
+    names: Vec<u8>,
+    crc32: Vec<u32>,
+    offsets: Vec<u64>,
+    pub pack_checksum: Vec<u8>,
+}
+
+impl PackIndex {
+    pub fn open<P: AsRef<Path>>(path: P, format: ObjectFormat) -> Result<Self, GitError> {
+        let content = std::fs::read(path.as_ref())?;
+        Self::parse(&content, format)
+            .map_err(|_e| GitError::CorruptRepo(path.as_ref().display().to_string()))
+    }
+
+    pub fn parse(content: &[u8], format: ObjectFormat) -> Result<Self, String> {
+        let r: IResult<_, _> = decode_pack_index(content, format);
+        let (_, index) = r.map_err(|e| format!("bad pack index: {:?}", e))?;
+        Ok(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// object names in this pack, sorted
+    pub fn ids(&self) -> impl Iterator<Item = &[u8]> {
+        self.names.chunks(self.format.len())
+    }
+
+    pub fn offset(&self, n: usize) -> u64 {
+        self.offsets[n]
+    }
+
+    pub fn crc32(&self, n: usize) -> u32 {
+        self.crc32[n]
+    }
+
+    /// position of the object in the sorted name table
+    pub fn position(&self, id: &[u8]) -> Option<usize> {
+        if id.len() != self.format.len() {
+            return None;
+        }
+        let first = id[0] as usize;
+        let lo = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1] as usize
+        };
+        let hi = self.fanout[first] as usize;
+
+        let (mut lo, mut hi) = (lo, hi);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.name(mid).cmp(id) {
+                std::cmp::Ordering::Equal => return Some(mid),
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        None
+    }
+
+    /// names starting with the lowercase hex `prefix`, which has at least 2 digits
+    pub fn ids_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a [u8]> {
+        let first = u8::from_str_radix(prefix.get(..2).unwrap_or("xx"), 16).ok();
+        let (lo, hi) = match first {
+            Some(0) => (0, self.fanout[0] as usize),
+            Some(first) => (
+                self.fanout[first as usize - 1] as usize,
+                self.fanout[first as usize] as usize,
+            ),
+            None => (0, 0),
+        };
+        (lo..hi)
+            .map(move |n| self.name(n))
+            .filter(move |name| hex_prefix_matches(name, prefix))
+    }
+
+    /// offset of the object in the `.pack` file
+    pub fn find(&self, id: &[u8]) -> Option<u64> {
+        self.position(id).map(|n| self.offsets[n])
+    }
+
+    fn name(&self, n: usize) -> &[u8] {
+        let len = self.format.len();
+        &self.names[n * len..(n + 1) * len]
+    }
+}
+
+pub fn decode_pack_index(content: &[u8], format: ObjectFormat) -> IResult<&[u8], PackIndex> {
+    let (content, _) = tag(IDX_MAGIC)(content)?;
+    let (content, _version) = tag(&2u32.to_be_bytes()[..])(content)?;
+    let (content, fanout) = count(be_u32, 256)(content)?;
+
+    let n = fanout[255] as usize;
+    if fanout.windows(2).any(|w| w[0] > w[1]) {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            content,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+
+    let (content, names) = take(n * format.len())(content)?;
+    let (content, crc32) = count(be_u32, n)(content)?;
+    let (content, small_offsets) = count(be_u32, n)(content)?;
+
+    let large_num = small_offsets
+        .iter()
+        .filter(|&&v| v & 0x8000_0000 != 0)
+        .count();
+    let (content, large_offsets) = count(be_u64, large_num)(content)?;
+
+    let mut offsets = Vec::with_capacity(n);
+    for offset in small_offsets {
+        if offset & 0x8000_0000 == 0 {
+            offsets.push(offset as u64);
+        } else {
+            let large = large_offsets
+                .get((offset & 0x7fff_ffff) as usize)
+                .ok_or_else(|| {
+                    nom::Err::Failure(nom::error::Error::new(
+                        content,
+                        nom::error::ErrorKind::Verify,
+                    ))
+                })?;
+            offsets.push(*large);
+        }
+    }
+
+    let (content, pack_checksum) = take(format.len())(content)?;
+    let (content, _idx_checksum) = take(format.len())(content)?;
+    let (content, _) = eof(content)?;
+
+    Ok((
+        content,
+        PackIndex {
+            format,
+            fanout,
+            names: names.to_vec(),
+            crc32,
+            offsets,
+            pack_checksum: pack_checksum.to_vec(),
+        },
+    ))
+}
+
+#[derive(Clone, Debug)]
+pub struct Pack {
+    path: PathBuf,
+    index: PackIndex,
+}
+
+impl Pack {
+    /// open the pack through its `.idx` file, the `.pack` file must be next to it
+    pub fn open<P: AsRef<Path>>(idx_path: P, format: ObjectFormat) -> Result<Self, GitError> {
+        let index = PackIndex::open(idx_path.as_ref(), format)?;
+        let path = idx_path.as_ref().with_extension("pack");
+        if !path.exists() {
+            return Err(GitError::CorruptRepo(path.display().to_string()));
+        }
+        Ok(Self { path, index })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    pub fn contains(&self, id: &[u8]) -> bool {
+        self.index.position(id).is_some()
+    }
+
+    /// read the object and resolve its deltas. bases of REF_DELTA which are not in
+    /// this pack are looked up through `resolve`
+    pub fn read<F>(&self, id: &[u8], resolve: F) -> Result<Option<(ObjectType, Vec<u8>)>, GitError>
+    where
+        F: Fn(&[u8]) -> Result<(ObjectType, Vec<u8>), GitError>,
+    {
+        match self.index.find(id) {
+            Some(offset) => self.read_at(offset, resolve).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_at<F>(&self, offset: u64, resolve: F) -> Result<(ObjectType, Vec<u8>), GitError>
+    where
+        F: Fn(&[u8]) -> Result<(ObjectType, Vec<u8>), GitError>,
+    {
+        let mut file = BufReader::new(File::open(&self.path)?);
+
+        // walk down the chain to the base object, and then apply the deltas backward
+        let mut deltas = Vec::new();
+        let mut offset = offset;
+        let (kind, mut content) = loop {
+            if deltas.len() > MAX_DELTA_CHAIN {
+                return Err(self.corrupt(offset, "delta chain too long"));
+            }
+
+            file.seek(SeekFrom::Start(offset))?;
+            let (code, size) = read_entry_header(&mut file)?;
+            match code {
+                OBJ_OFS_DELTA => {
+                    let distance = read_ofs_distance(&mut file)?;
+                    deltas.push(inflate(&mut file, size)?);
+                    offset = offset
+                        .checked_sub(distance)
+                        .filter(|_| distance != 0)
+                        .ok_or_else(|| self.corrupt(offset, "bad delta base offset"))?;
+                }
+                OBJ_REF_DELTA => {
+                    let mut base = vec![0u8; self.index.format.len()];
+                    file.read_exact(&mut base)?;
+                    deltas.push(inflate(&mut file, size)?);
+                    match self.index.find(&base) {
+                        Some(base_offset) => offset = base_offset,
+                        None => break resolve(&base)?,
+                    }
+                }
+                code => {
+                    let kind =
+                        object_type(code).ok_or_else(|| self.corrupt(offset, "bad object type"))?;
+                    break (kind, inflate(&mut file, size)?);
+                }
+            }
+        };
+
+        while let Some(delta) = deltas.pop() {
+            content = apply_delta(&content, &delta).map_err(|e| self.corrupt(offset, e))?;
+        }
+        Ok((kind, content))
+    }
+
+    /// the content of an undeltified entry, inflated while being read, so that big
+    /// blobs never have to be in memory as a whole. deltas are `None`, and have to be
+    /// resolved by [`Pack::read_at`]
+    pub fn stream_at(&self, offset: u64) -> Result<Option<(ObjectType, u64, impl Read)>, GitError> {
+        use flate2::bufread::ZlibDecoder;
+
+        let mut file = BufReader::new(File::open(&self.path)?);
+        file.seek(SeekFrom::Start(offset))?;
+        let (code, size) = read_entry_header(&mut file)?;
+        match code {
+            OBJ_OFS_DELTA | OBJ_REF_DELTA => Ok(None),
+            code => {
+                let kind =
+                    object_type(code).ok_or_else(|| self.corrupt(offset, "bad object type"))?;
+                let size = size as u64;
+                Ok(Some((kind, size, ZlibDecoder::new(file).take(size))))
+            }
+        }
+    }
+
+    fn corrupt(&self, offset: u64, reason: &str) -> GitError {
+        GitError::CorruptRepo(format!("{}: {} at {}", self.path.display(), reason, offset))
+    }
+}
+
+pub fn object_type(code: u8) -> Option<ObjectType> {
+    match code {
+        OBJ_COMMIT => Some(ObjectType::Commit),
+        OBJ_TREE => Some(ObjectType::Tree),
+        OBJ_BLOB => Some(ObjectType::Blob),
+        OBJ_TAG => Some(ObjectType::Tag),
+        _ => None,
+    }
+}
+
+pub fn type_code(kind: ObjectType) -> u8 {
+    match kind {
+        ObjectType::Commit => OBJ_COMMIT,
+        ObjectType::Tree => OBJ_TREE,
+        ObjectType::Blob => OBJ_BLOB,
+        ObjectType::Tag => OBJ_TAG,
+    }
+}
+
+/// type in bit 4-6 of the first byte, and size in the lower 4 bits, continued by
+/// 7 bits of every following byte(little endian) as long as the MSB is set
+fn read_entry_header<R: Read>(reader: &mut R) -> std::io::Result<(u8, usize)> {
+    let mut c = read_byte(reader)?;
+    let code = (c >> 4) & 0x7;
+    let mut size = (c & 0x0f) as usize;
+    let mut shift = 4;
+    while c & 0x80 != 0 {
+        if shift > usize::BITS - 7 {
+            return Err(invalid_data("entry size overflow"));
+        }
+        c = read_byte(reader)?;
+        size += ((c & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+    Ok((code, size))
+}
+
+/// big endian, and every continuation adds one so that there is no redundant encoding.
+/// index version 4 stores the lengths to strip from paths the same way
+pub fn read_ofs_distance<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut c = read_byte(reader)?;
+    let mut distance = (c & 0x7f) as u64;
+    while c & 0x80 != 0 {
+        if distance >= 1 << (u64::BITS - 8) {
+            return Err(invalid_data("delta base offset overflow"));
+        }
+        c = read_byte(reader)?;
+        distance = ((distance + 1) << 7) | (c & 0x7f) as u64;
+    }
+    Ok(distance)
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> std::io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn inflate<R: std::io::BufRead>(reader: &mut R, size: usize) -> std::io::Result<Vec<u8>> {
+    use flate2::bufread::ZlibDecoder;
+
+    // the size may be corrupt, don't trust it for allocation
+    let mut content = Vec::with_capacity(size.min(1 << 24));
+    ZlibDecoder::new(reader)
+        .take(size as u64 + 1)
+        .read_to_end(&mut content)?;
+    if content.len() != size {
+        return Err(invalid_data("inflated size mismatch"));
+    }
+    Ok(content)
+}
+
+fn invalid_data(reason: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, reason)
+}
+
+/// delta := source size | target size | instructions
+///     copy:   1oooossss + offset bytes(by bit `o`) + size bytes(by bit `s`)
+///     insert: 0nnnnnnn + n bytes of literal data
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut delta = delta;
+    let source_size = read_delta_size(&mut delta)?;
+    let target_size = read_delta_size(&mut delta)?;
+    if source_size != base.len() {
+        return Err("delta source size mismatch");
+    }
+
+    // the size may be corrupt, don't trust it for allocation
+    let mut target = Vec::with_capacity(target_size.min(base.len() + delta.len()));
+    while let Some((&op, rest)) = delta.split_first() {
+        delta = rest;
+        if op & 0x80 != 0 {
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    let (&b, rest) = delta.split_first().ok_or("truncated delta")?;
+                    delta = rest;
+                    offset |= (b as usize) << (i * 8);
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    let (&b, rest) = delta.split_first().ok_or("truncated delta")?;
+                    delta = rest;
+                    size |= (b as usize) << (i * 8);
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let chunk = offset
+                .checked_add(size)
+                .and_then(|end| base.get(offset..end))
+                .ok_or("delta copy out of range")?;
+            target.extend_from_slice(chunk);
+        } else if op != 0 {
+            let n = op as usize;
+            if delta.len() < n {
+                return Err("truncated delta");
+            }
+            target.extend_from_slice(&delta[..n]);
+            delta = &delta[n..];
+        } else {
+            return Err("unexpected delta opcode 0");
+        }
+    }
+
+    if target.len() != target_size {
+        return Err("delta target size mismatch");
+    }
+    Ok(target)
+}
+
+fn read_delta_size(delta: &mut &[u8]) -> Result<usize, &'static str> {
+    let mut size = 0usize;
+    let mut shift = 0;
+    loop {
+        let (&c, rest) = delta.split_first().ok_or("truncated delta")?;
+        *delta = rest;
+        if shift >= usize::BITS {
+            return Err("delta size overflow");
+        }
+        size |= ((c & 0x7f) as usize) << shift;
+        shift += 7;
+        if c & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// an object to be written into a pack
+#[derive(Clone, Debug)]
+pub struct PackEntry {
+    pub id: Vec<u8>,
+    pub kind: ObjectType,
+    pub content: Vec<u8>,
+}
+
+/// write `entries` into `pack_dir/pack-<checksum>.pack`, along with its `.idx`,
+/// and return the path of the `.idx` file.
+///
+/// objects are sorted by type and size, and every object is tried as a delta
+/// against the `window` objects of the same type before it, chains are no longer
+/// than `depth`. a delta is only kept if it's smaller than half of the object.
+pub fn write_pack(
+    pack_dir: &Path,
+    entries: &[PackEntry],
+    window: usize,
+    depth: usize,
+    format: ObjectFormat,
+) -> Result<PathBuf, GitError> {
+    fs::create_dir_all(pack_dir)?;
+
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&entries[a], &entries[b]);
+        type_code(a.kind)
+            .cmp(&type_code(b.kind))
+            .then(b.content.len().cmp(&a.content.len()))
+    });
+
+    let deltas = find_deltas(entries, &order, window, depth);
+
+    let tmp_pack = tmp_file(pack_dir, "tmp_pack")?;
+    let tmp_idx = tmp_file(pack_dir, "tmp_idx").inspect_err(|_| {
+        let _ = fs::remove_file(&tmp_pack);
+    })?;
+    let result = (|| {
+        let mut writer = HashWriter::new(BufWriter::new(File::create(&tmp_pack)?), format);
+        writer.write_all(b"PACK")?;
+        writer.write_all(&2u32.to_be_bytes())?;
+        writer.write_all(&(entries.len() as u32).to_be_bytes())?;
+
+        let mut written = 12u64;
+        let mut offsets = vec![0u64; entries.len()];
+        let mut crcs = vec![0u32; entries.len()];
+        for (position, &n) in order.iter().enumerate() {
+            let entry = &entries[n];
+            let mut raw = Vec::new();
+            let data = match deltas[position] {
+                Some((base_position, ref delta)) => {
+                    let base = order[base_position];
+                    raw.extend(entry_header(OBJ_OFS_DELTA, delta.len()));
+                    raw.extend(encode_ofs_distance(written - offsets[base]));
+                    &delta[..]
+                }
+                None => {
+                    raw.extend(entry_header(type_code(entry.kind), entry.content.len()));
+                    &entry.content[..]
+                }
+            };
+            let mut z = ZlibEncoder::new(raw, Compression::default());
+            z.write_all(data)?;
+            let raw = z.finish()?;
+
+            let mut crc = Crc::new();
+            crc.update(&raw);
+            offsets[n] = written;
+            crcs[n] = crc.sum();
+
+            writer.write_all(&raw)?;
+            written += raw.len() as u64;
+        }
+
+        let (mut file, checksum) = writer.finish();
+        file.write_all(&checksum)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        write_pack_index(&tmp_idx, entries, &offsets, &crcs, &checksum, format)?;
+        Ok(checksum)
+    })();
+
+    let checksum = match result {
+        Ok(checksum) => checksum,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_pack);
+            let _ = fs::remove_file(&tmp_idx);
+            return Err(GitError::Io(e));
+        }
+    };
+
+    // the `.idx` goes last, since readers find packs through it
+    let name = format!("pack-{}", bytes_to_hex(&checksum));
+    let pack_path = pack_dir.join(format!("{}.pack", name));
+    let idx_path = pack_dir.join(format!("{}.idx", name));
+    fs::rename(&tmp_pack, pack_path)?;
+    fs::rename(&tmp_idx, &idx_path)?;
+    Ok(idx_path)
+}
+
+/// the best delta(if any) for every object in `order`, as (position of base in `order`, delta)
+fn find_deltas(
+    entries: &[PackEntry],
+    order: &[usize],
+    window: usize,
+    depth: usize,
+) -> Vec<Option<(usize, Vec<u8>)>> {
+    let mut deltas: Vec<Option<(usize, Vec<u8>)>> = Vec::with_capacity(order.len());
+    let mut depths = vec![0usize; order.len()];
+    let mut candidates: VecDeque<(usize, DeltaIndex)> = VecDeque::with_capacity(window);
+
+    for (position, &n) in order.iter().enumerate() {
+        let entry = &entries[n];
+
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        for (base_position, index) in candidates.iter() {
+            let base = &entries[order[*base_position]];
+            if base.kind != entry.kind || depths[*base_position] >= depth {
+                continue;
+            }
+            let max_size = match best {
+                Some((_, ref delta)) => delta.len(),
+                None => entry.content.len() / 2,
+            };
+            if let Some(delta) = index.delta(&entry.content, max_size) {
+                best = Some((*base_position, delta));
+            }
+        }
+
+        if let Some((base_position, _)) = best {
+            depths[position] = depths[base_position] + 1;
+        }
+        deltas.push(best);
+
+        if window > 0 {
+            if candidates.len() == window {
+                candidates.pop_front();
+            }
+            candidates.push_back((position, DeltaIndex::new(&entry.content)));
+        }
+    }
+    deltas
+}
+
+fn write_pack_index(
+    path: &Path,
+    entries: &[PackEntry],
+    offsets: &[u64],
+    crcs: &[u32],
+    pack_checksum: &[u8],
+    format: ObjectFormat,
+) -> std::io::Result<()> {
+    let mut sorted: Vec<usize> = (0..entries.len()).collect();
+    sorted.sort_by(|&a, &b| entries[a].id.cmp(&entries[b].id));
+
+    let mut writer = HashWriter::new(BufWriter::new(File::create(path)?), format);
+    writer.write_all(IDX_MAGIC)?;
+    writer.write_all(&2u32.to_be_bytes())?;
+
+    let mut fanout = [0u32; 256];
+    for entry in entries.iter() {
+        fanout[entry.id[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout.iter_mut() {
+        total += *count;
+        *count = total;
+    }
+    for count in fanout.iter() {
+        writer.write_all(&count.to_be_bytes())?;
+    }
+
+    for &n in sorted.iter() {
+        writer.write_all(&entries[n].id)?;
+    }
+    for &n in sorted.iter() {
+        writer.write_all(&crcs[n].to_be_bytes())?;
+    }
+
+    let mut large_offsets = Vec::new();
+    for &n in sorted.iter() {
+        let offset = offsets[n];
+        if offset < 0x8000_0000 {
+            writer.write_all(&(offset as u32).to_be_bytes())?;
+        } else {
+            writer.write_all(&(0x8000_0000 | large_offsets.len() as u32).to_be_bytes())?;
+            large_offsets.push(offset);
+        }
+    }
+    for offset in large_offsets {
+        writer.write_all(&offset.to_be_bytes())?;
+    }
+
+    writer.write_all(pack_checksum)?;
+    let (mut file, checksum) = writer.finish();
+    file.write_all(&checksum)?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+fn entry_header(code: u8, size: usize) -> Vec<u8> {
+    let mut header = Vec::new();
+    let mut c = (code << 4) | (size & 0x0f) as u8;
+    let mut size = size >> 4;
+    while size != 0 {
+        header.push(c | 0x80);
+        c = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    header.push(c);
+    header
+}
+
+pub fn encode_ofs_distance(mut distance: u64) -> Vec<u8> {
+    let mut encoded = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        encoded.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    encoded.reverse();
+    encoded
+}
+
+/// size of the blocks of the base we index, shorter matches are not worth a copy
+const DELTA_BLOCK: usize = 16;
+/// bound the work on very repetitive content
+const DELTA_BUCKET: usize = 16;
+/// the largest size a single copy instruction expresses without a size byte
+const DELTA_MAX_COPY: usize = 0x10000;
+
+/// index every block of the base, so that we can find where a block of the target
+/// appears in the base
+pub struct DeltaIndex<'a> {
+    base: &'a [u8],
+    blocks: HashMap<&'a [u8], Vec<usize>>,
+}
+
+impl<'a> DeltaIndex<'a> {
+    pub fn new(base: &'a [u8]) -> Self {
+        let mut blocks: HashMap<&[u8], Vec<usize>> = HashMap::new();
+        for offset in (0..base.len().saturating_sub(DELTA_BLOCK - 1)).step_by(DELTA_BLOCK) {
+            let bucket = blocks
+                .entry(&base[offset..offset + DELTA_BLOCK])
+                .or_default();
+            if bucket.len() < DELTA_BUCKET {
+                bucket.push(offset);
+            }
+        }
+        Self { base, blocks }
+    }
+
+    /// the delta turning the base into `target`, `None` if it's larger than `max_size`
+    pub fn delta(&self, target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+        let mut delta = Vec::new();
+        encode_delta_size(&mut delta, self.base.len());
+        encode_delta_size(&mut delta, target.len());
+
+        let mut insert: Vec<u8> = Vec::new();
+        let mut i = 0;
+        while i < target.len() {
+            let mut best = (0, 0);
+            if let Some(bucket) = target
+                .get(i..i + DELTA_BLOCK)
+                .and_then(|b| self.blocks.get(b))
+            {
+                for &offset in bucket {
+                    let len = self.base[offset..]
+                        .iter()
+                        .zip(target[i..].iter())
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    if len > best.1 {
+                        best = (offset, len);
+                    }
+                }
+            }
+
+            if best.1 < DELTA_BLOCK {
+                insert.push(target[i]);
+                if insert.len() == 0x7f {
+                    flush_insert(&mut delta, &mut insert);
+                }
+                i += 1;
+            } else {
+                i += best.1;
+                // the match may go on backward, eating the pending insert
+                let (mut offset, mut len) = best;
+                while offset > 0 && insert.last() == Some(&self.base[offset - 1]) {
+                    insert.pop();
+                    offset -= 1;
+                    len += 1;
+                }
+                flush_insert(&mut delta, &mut insert);
+                while len > 0 {
+                    let size = len.min(DELTA_MAX_COPY);
+                    encode_copy(&mut delta, offset, size);
+                    offset += size;
+                    len -= size;
+                }
+            }
+
+            if delta.len() > max_size {
+                return None;
+            }
+        }
+        flush_insert(&mut delta, &mut insert);
+
+        if delta.len() > max_size {
+            return None;
+        }
+        Some(delta)
+    }
+}
+
+fn encode_delta_size(delta: &mut Vec<u8>, mut size: usize) {
+    while size >= 0x80 {
+        delta.push(0x80 | (size & 0x7f) as u8);
+        size >>= 7;
+    }
+    delta.push(size as u8);
+}
+
+fn flush_insert(delta: &mut Vec<u8>, insert: &mut Vec<u8>) {
+    if !insert.is_empty() {
+        delta.push(insert.len() as u8);
+        delta.append(insert);
+    }
+}
+
+fn encode_copy(delta: &mut Vec<u8>, offset: usize, size: usize) {
+    let mut op = 0x80u8;
+    let mut args = Vec::with_capacity(7);
+    for i in 0..4 {
+        let b = ((offset >> (i * 8)) & 0xff) as u8;
+        if b != 0 {
+            op |= 1 << i;
+            args.push(b);
+        }
+    }
+    // a copy of exactly 0x10000 has no size byte at all
+    for i in 0..3 {
+        let b = ((size >> (i * 8)) & 0xff) as u8;
+        if b != 0 {
+            op |= 0x10 << i;
+            args.push(b);
+        }
+    }
+    delta.push(op);
+    delta.extend(args);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::bytes::{bytes_to_hex, double_hex_to_bytes};
+
+    use super::*;
+
+    const OFS_PACK: &str = "data/objects/pack/pack-810157a6c431572d771323d2bb3904da79f5c1e1.idx";
+    const REF_PACK: &str = "data/packs/ref/pack-e22f65ba881ea223ec3aec530b93ab3618aeb941.idx";
+    const SHA256_PACK: &str = "data/sha256/packs/\
+        pack-e436a62c599cf4b453c27472b6dd3514af6619c95fe667b5bd59eb92ee4cada0.idx";
+
+    fn no_resolve(id: &[u8]) -> Result<(ObjectType, Vec<u8>), GitError> {
+        Err(GitError::ObjectNotFound(bytes_to_hex(id)))
+    }
+
+    /// every object in the pack should hash to its own name
+    fn check_pack(path: &str, format: ObjectFormat, len: usize) {
+        let pack = Pack::open(path, format).unwrap();
+        assert_eq!(pack.index().len(), len);
+
+        for id in pack.index().ids() {
+            let (kind, content) = pack.read(id, no_resolve).unwrap().unwrap();
+            let mut raw = format!("{} {}\0", kind, content.len()).into_bytes();
+            raw.extend_from_slice(&content);
+            assert_eq!(format.hash(&raw), id);
+        }
+    }
+
+    #[test]
+    fn test_ofs_delta_pack() {
+        check_pack(OFS_PACK, ObjectFormat::Sha1, 10);
+    }
+
+    #[test]
+    fn test_ref_delta_pack() {
+        check_pack(REF_PACK, ObjectFormat::Sha1, 10);
+    }
+
+    #[test]
+    fn test_sha256_pack() {
+        check_pack(SHA256_PACK, ObjectFormat::Sha256, 5);
+        // the same pack can't be read as sha1
+        assert!(Pack::open(SHA256_PACK, ObjectFormat::Sha1).is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let pack = Pack::open(OFS_PACK, ObjectFormat::Sha1).unwrap();
+        let id = double_hex_to_bytes(b"b9c7b8fc425fdda39bd2eba81d905449ba2bebd6");
+        assert_eq!(pack.index().find(&id), Some(1019));
+
+        let (kind, content) = pack.read(&id, no_resolve).unwrap().unwrap();
+        assert!(matches!(kind, ObjectType::Blob));
+        assert_eq!(content.len(), 7455);
+
+        let missing = double_hex_to_bytes(b"b9c7b8fc425fdda39bd2eba81d905449ba2bebd7");
+        assert!(pack.read(&missing, no_resolve).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_create_delta() {
+        let base: Vec<u8> = (0..2000).map(|i| (i * 7 % 251) as u8).collect();
+        let mut target = base.clone();
+        target[1000] = 0;
+        target.splice(10..10, b"inserted".iter().cloned());
+        target.extend_from_slice(b"appended");
+
+        let delta = DeltaIndex::new(&base).delta(&target, target.len()).unwrap();
+        assert!(delta.len() < 100);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+
+        // nothing in common, no delta smaller than the object itself
+        assert!(DeltaIndex::new(b"0123456789abcdef0123456789abcdef")
+            .delta(b"totally different content", 10)
+            .is_none());
+    }
+
+    #[test]
+    fn test_write_pack() {
+        let source = Pack::open(OFS_PACK, ObjectFormat::Sha1).unwrap();
+        let entries: Vec<_> = source
+            .index()
+            .ids()
+            .map(|id| {
+                let (kind, content) = source.read(id, no_resolve).unwrap().unwrap();
+                PackEntry {
+                    id: id.to_vec(),
+                    kind,
+                    content,
+                }
+            })
+            .collect();
+
+        let dir = tempfile::tempdir().unwrap();
+        let idx_path = write_pack(dir.path(), &entries, 10, 50, ObjectFormat::Sha1).unwrap();
+        assert!(idx_path.with_extension("pack").exists());
+
+        let pack = Pack::open(&idx_path, ObjectFormat::Sha1).unwrap();
+        assert_eq!(pack.index().len(), entries.len());
+        for entry in entries.iter() {
+            let (kind, content) = pack.read(&entry.id, no_resolve).unwrap().unwrap();
+            assert_eq!(kind, entry.kind);
+            assert_eq!(content, entry.content);
+        }
+
+        // the three versions of `lines.txt` are way smaller as deltas
+        let size = fs::metadata(idx_path.with_extension("pack")).unwrap().len();
+        assert!(size < 1500, "pack too large: {}", size);
+    }
+
+    #[test]
+    fn test_ofs_distance() {
+        for distance in [1u64, 127, 128, 16511, 16512, 1 << 40] {
+            let encoded = encode_ofs_distance(distance);
+            assert_eq!(read_ofs_distance(&mut &encoded[..]).unwrap(), distance);
+        }
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let base = b"hello world";
+        // source 11, target 13, copy(6, 5), insert "!!", copy(0, 6)
+        let delta = [11, 13, 0x91, 6, 5, 2, b'!', b'!', 0x90, 6];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"world!!hello ");
+
+        assert!(apply_delta(b"short", &delta).is_err());
+        assert!(apply_delta(base, &[11, 13, 0x91, 6, 50]).is_err());
+    }
+}
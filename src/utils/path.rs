@@ -3,30 +3,125 @@
 //! and when we exec `git-rs add xxx`, we will walk the whole path,
 //! it's also needy to provide some utils for this usage
 
-use std::path::PathBuf;
+use std::{
+    borrow::Cow,
+    fs::{self, OpenOptions},
+    io,
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use crate::REPO_NAME;
+use crate::{GitError, REPO_NAME};
 
-pub fn root_dir() -> Result<PathBuf, ()> {
-    let mut dir = std::env::current_dir().unwrap();
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub fn root_dir() -> Option<PathBuf> {
+    find_root(std::env::current_dir().ok()?)
+}
+
+/// the `.git-rs` directory of the repository we are in
+pub fn repo_dir() -> Result<PathBuf, GitError> {
+    root_dir()
+        .map(|root| root.join(REPO_NAME))
+        .ok_or(GitError::NotRepository)
+}
+
+/// walk up from `dir` until we meet a directory containing the repository
+pub fn find_root(mut dir: PathBuf) -> Option<PathBuf> {
     if dir.join(REPO_NAME).exists() {
-        return Ok(dir);
+        return Some(dir);
     }
     while dir.pop() {
         if dir.join(REPO_NAME).exists() {
-            return Ok(dir);
+            return Some(dir);
+        }
+    }
+
+    None
+}
+
+/// whether a directory above `path`(relative to `root`, separated by `/`) is a symlink,
+/// which may lead anywhere, even outside the working tree
+pub fn has_symlink_leading_path(root: &Path, path: &[u8]) -> bool {
+    let mut components = path.split(|&c| c == b'/');
+    components.next_back();
+    let mut dir = root.to_path_buf();
+    for component in components {
+        dir.push(bytes_to_path(component));
+        if fs::symlink_metadata(&dir).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            return true;
         }
     }
+    false
+}
 
-    Err(())
+/// a new empty file in `dir`, to be written and then renamed into place. the name is
+/// unique in the process, and a file left behind by another one(say a crashed process
+/// whose pid is reused) is never taken over
+pub fn tmp_file(dir: &Path, prefix: &str) -> io::Result<PathBuf> {
+    loop {
+        let n = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("{}_{}_{}", prefix, std::process::id(), n));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
 }
 
-pub fn object_path(root_path:&PathBuf,name: &str) -> PathBuf
-{
-    let dir_name = root_path.join("objects").join(&name[0..2]);
-    let filename = &name[2..];
-    let full_path = dir_name.join(filename);
-    full_path
+pub fn object_path(root_path: &Path, name: &str) -> PathBuf {
+    loose_object_path(&root_path.join("objects"), name)
+}
+
+/// paths inside trees and the index are raw bytes separated by `/`, they only become
+/// a `Path` when we touch the filesystem. on unix any bytes are fine, elsewhere the
+/// bytes must be utf-8(which is what git writes there)
+#[cfg(unix)]
+pub fn bytes_to_path(raw: &[u8]) -> Cow<'_, Path> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    Cow::Borrowed(Path::new(OsStr::from_bytes(raw)))
+}
+
+#[cfg(not(unix))]
+pub fn bytes_to_path(raw: &[u8]) -> Cow<'_, Path> {
+    Cow::Owned(PathBuf::from(
+        String::from_utf8_lossy(raw).replace('/', "\\"),
+    ))
+}
+
+/// the reverse of [`bytes_to_path`]
+#[cfg(unix)]
+pub fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    Cow::Owned(path.to_string_lossy().replace('\\', "/").into_bytes())
+}
+
+/// `path` relative to `root`, without `.` and `..`. `None` if it's outside
+pub fn relative_to_root(root: &Path, path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized.strip_prefix(root).ok().map(Path::to_path_buf)
+}
+
+/// `objects/xx/yyyy...`, where `xx` is the first byte of the object name
+pub fn loose_object_path(objects_dir: &Path, name: &str) -> PathBuf {
+    objects_dir.join(&name[0..2]).join(&name[2..])
 }
 
 #[cfg(test)]
@@ -35,11 +130,42 @@ mod tests {
 
     #[test]
     fn test1() {
-        use std::path::Path;
+        // don't `set_current_dir` here, other tests rely on relative paths
+        let cwd = std::env::current_dir().unwrap();
+        let parent = cwd.parent().unwrap().to_path_buf();
+        let root = find_root(parent);
+        assert_eq!(root, None);
+    }
+
+    #[test]
+    fn test_relative_to_root() {
+        let root = Path::new("/repo");
+        assert_eq!(
+            relative_to_root(root, Path::new("/repo/src/../a.txt")),
+            Some(PathBuf::from("a.txt"))
+        );
+        assert_eq!(relative_to_root(root, Path::new("/repo/..")), None);
+        assert_eq!(relative_to_root(root, Path::new("/other/a.txt")), None);
+    }
 
-        let path = Path::new("../");
-        std::env::set_current_dir(&path).unwrap();
-        let root = root_dir();
-        assert_eq!(root, Err(()));
+    #[test]
+    fn test_tmp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = tmp_file(dir.path(), "tmp_pack").unwrap();
+        fs::write(&first, "written").unwrap();
+        let second = tmp_file(dir.path(), "tmp_pack").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::read(&first).unwrap(), b"written");
+        assert!(second.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_path_bytes() {
+        // latin-1 `café`, which is not utf-8
+        let raw = b"dir/caf\xe9.txt";
+        let path = bytes_to_path(raw);
+        assert_eq!(path.to_string_lossy(), "dir/caf\u{fffd}.txt");
+        assert_eq!(&path_to_bytes(&path)[..], raw);
     }
 }
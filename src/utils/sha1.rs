@@ -1,45 +1,74 @@
 use flate2::Compression;
 use std::io::prelude::*;
 
-use std::{io::BufReader, path::Path};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
 
-pub fn encode_file<P: AsRef<Path>>(path: P) -> Vec<u8> {
+use crate::GitError;
+
+/// compress the file into `out` as it's read, returns the number of bytes written
+pub fn encode_file<P: AsRef<Path>, W: Write>(path: P, mut out: W) -> Result<u64, GitError> {
     use flate2::bufread::ZlibEncoder;
 
-    let file = std::fs::File::open(path).unwrap();
-    let b = BufReader::new(file);
-    let mut z = ZlibEncoder::new(b, Compression::fast());
-    let mut buffer = Vec::new();
-    z.read_to_end(&mut buffer).unwrap();
-    buffer
+    let file = File::open(path)?;
+    let mut z = ZlibEncoder::new(BufReader::new(file), Compression::fast());
+    Ok(io::copy(&mut z, &mut out)?)
 }
 
 pub fn encode(content: &[u8]) -> Vec<u8> {
     use flate2::write::ZlibEncoder;
 
+    // writing into memory never fails
     let mut z = ZlibEncoder::new(Vec::new(), Compression::fast());
-    z.write_all(&content).unwrap();
-    let compressed = z.finish().unwrap();
-    compressed
+    z.write_all(content).expect("compress into memory");
+    z.finish().expect("compress into memory")
 }
 
-pub fn decode_file<P: AsRef<Path>>(path: P) -> Vec<u8> {
+/// inflate the file into `out` as it's read, returns the number of bytes written. the
+/// error tells where the compressed data stops making sense
+pub fn decode_file<P: AsRef<Path>, W: Write>(path: P, mut out: W) -> Result<u64, GitError> {
     use flate2::bufread::ZlibDecoder;
 
-    let bytes = std::fs::read(path).unwrap();
-    let mut deflater = ZlibDecoder::new(&bytes[..]);
-    let mut reader = Vec::new();
-    deflater.read_to_end(&mut reader).unwrap();
-    reader
+    let path = path.as_ref();
+    let mut deflater = ZlibDecoder::new(BufReader::new(File::open(path)?));
+    let mut buffer = [0u8; 8192];
+    let mut written = 0;
+    loop {
+        let n = match deflater.read(&mut buffer) {
+            Ok(0) => return Ok(written),
+            Ok(n) => n,
+            Err(e) => {
+                let offset = deflater.total_in() as usize;
+                return Err(GitError::corrupt_file(path, offset, e.to_string()));
+            }
+        };
+        out.write_all(&buffer[..n])?;
+        written += n as u64;
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<u8>, GitError> {
+    inflate(bytes).map_err(|(offset, reason)| GitError::Decode {
+        id: None,
+        path: None,
+        offset: offset as u64,
+        reason,
+    })
 }
 
-pub fn decode(bytes: &[u8]) -> Vec<u8> {
+/// the content, or the offset in `bytes` and the reason of the failure
+fn inflate(bytes: &[u8]) -> Result<Vec<u8>, (usize, String)> {
     use flate2::bufread::ZlibDecoder;
 
-    let mut deflater = ZlibDecoder::new(&bytes[..]);
+    let mut deflater = ZlibDecoder::new(bytes);
     let mut reader = Vec::new();
-    deflater.read_to_end(&mut reader).unwrap();
-    reader
+    match deflater.read_to_end(&mut reader) {
+        Ok(_) => Ok(reader),
+        Err(e) => Err((deflater.total_in() as usize, e.to_string())),
+    }
 }
 
 #[cfg(test)]
@@ -56,7 +85,7 @@ mod tests {
     #[test]
     fn test_decode_blob() {
         let r = include_bytes!("../../data/objects/3b/18e512dba79e4c8300dd08aeb37f8e728b8dad");
-        let res  = decode(&r[..]);
+        let res  = decode(&r[..]).unwrap();
         assert_eq!(&res[..], "blob 12\0hello world\n".as_bytes());
     }
 
@@ -66,4 +95,39 @@ mod tests {
         let r=encode(&r[..]);
         assert_eq!(r,vec![0x78, 0x1, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0xe1, 0x2, 0x0, 0x1e, 0x72, 0x4, 0x67]);
     }
+
+    #[test]
+    fn test_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hello.txt");
+        std::fs::copy("data/hello.txt", &path).unwrap();
+        let mut compressed = Vec::new();
+        let written = encode_file(&path, &mut compressed).unwrap();
+        assert_eq!(written, compressed.len() as u64);
+        assert_eq!(compressed, encode(include_bytes!("../../data/hello.txt")));
+
+        std::fs::write(&path, &compressed).unwrap();
+        let mut content = Vec::new();
+        decode_file(&path, &mut content).unwrap();
+        assert_eq!(content, include_bytes!("../../data/hello.txt"));
+    }
+
+    #[test]
+    fn test_decode_corrupt() {
+        let r = include_bytes!("../../data/objects/3b/18e512dba79e4c8300dd08aeb37f8e728b8dad");
+        let mut corrupt = r.to_vec();
+        corrupt[10] ^= 0xff;
+        match decode(&corrupt) {
+            Err(GitError::Decode { offset, .. }) => assert!(offset <= corrupt.len() as u64),
+            r => panic!("expect a decode error, got {:?}", r),
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("garbage");
+        std::fs::write(&path, b"not zlib at all").unwrap();
+        match decode_file(&path, io::sink()) {
+            Err(GitError::Decode { path: Some(p), .. }) => assert_eq!(p, path),
+            r => panic!("expect a decode error, got {:?}", r),
+        }
+    }
 }
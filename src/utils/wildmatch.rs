@@ -0,0 +1,313 @@
+//! git's glob matching(`wildmatch`), used by the ignore and attributes patterns
+//!
+//! `*` and `?` match anything but `/` when matching a path, `**` between slashes
+//! matches any number of directories, `[...]` is a class which may be negated by `!`
+//! or `^` and may hold ranges and `[:alpha:]` like names, `\` escapes the next byte
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Wild {
+    Match,
+    NoMatch,
+    /// the text ran out, no shorter text can match either
+    AbortAll,
+    /// a `*` met a slash, only a `**` before it may still match
+    AbortToStarStar,
+}
+
+/// whether `text` matches `pattern`. with `pathname`, wildcards don't match `/` except
+/// for `**`
+pub fn wildmatch(pattern: &[u8], text: &[u8], pathname: bool) -> bool {
+    dowild(pattern, text, pathname) == Wild::Match
+}
+
+fn is_glob_special(c: u8) -> bool {
+    matches!(c, b'*' | b'?' | b'[' | b'\\')
+}
+
+/// a port of `dowild` in git's wildmatch.c, NUL is the end of the pattern and text
+fn dowild(pattern: &[u8], text: &[u8], pathname: bool) -> Wild {
+    let at = |s: &[u8], i: usize| s.get(i).copied().unwrap_or(0);
+    let (mut p, mut t) = (0, 0);
+
+    while at(pattern, p) != 0 {
+        let mut p_ch = at(pattern, p);
+        let mut t_ch = at(text, t);
+        if t_ch == 0 && p_ch != b'*' {
+            return Wild::AbortAll;
+        }
+        match p_ch {
+            b'?' => {
+                if pathname && t_ch == b'/' {
+                    return Wild::NoMatch;
+                }
+            }
+            b'*' => {
+                p += 1;
+                let match_slash;
+                if at(pattern, p) == b'*' {
+                    let star_start = p - 1;
+                    while at(pattern, p) == b'*' {
+                        p += 1;
+                    }
+                    let after = at(pattern, p);
+                    if !pathname {
+                        // without a path, `*` is the same as `**`
+                        match_slash = true;
+                    } else if (star_start == 0 || pattern[star_start - 1] == b'/')
+                        && (after == 0
+                            || after == b'/'
+                            || (after == b'\\' && at(pattern, p + 1) == b'/'))
+                    {
+                        // `foo/**/bar` matches `foo/bar` too
+                        if after == b'/'
+                            && dowild(&pattern[p + 1..], &text[t..], pathname) == Wild::Match
+                        {
+                            return Wild::Match;
+                        }
+                        match_slash = true;
+                    } else {
+                        match_slash = false;
+                    }
+                } else {
+                    match_slash = !pathname;
+                }
+
+                if at(pattern, p) == 0 {
+                    // a trailing `*` matches only the rest of a name
+                    if !match_slash && text[t..].contains(&b'/') {
+                        return Wild::NoMatch;
+                    }
+                    return Wild::Match;
+                } else if !match_slash && at(pattern, p) == b'/' {
+                    // `*/` matches the next directory
+                    match text[t..].iter().position(|&c| c == b'/') {
+                        Some(slash) => t += slash,
+                        None => return Wild::NoMatch,
+                    }
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+
+                loop {
+                    if t_ch == 0 {
+                        break;
+                    }
+                    // skip to the next occurrence of a literal after the star
+                    if !is_glob_special(at(pattern, p)) {
+                        p_ch = at(pattern, p);
+                        loop {
+                            t_ch = at(text, t);
+                            if t_ch == 0 || (!match_slash && t_ch == b'/') || t_ch == p_ch {
+                                break;
+                            }
+                            t += 1;
+                        }
+                        if t_ch != p_ch {
+                            return Wild::NoMatch;
+                        }
+                    }
+                    match dowild(&pattern[p..], &text[t..], pathname) {
+                        Wild::NoMatch => {
+                            if !match_slash && t_ch == b'/' {
+                                return Wild::AbortToStarStar;
+                            }
+                        }
+                        Wild::AbortToStarStar if match_slash => {}
+                        matched => return matched,
+                    }
+                    t += 1;
+                    t_ch = at(text, t);
+                }
+                return Wild::AbortAll;
+            }
+            b'[' => {
+                p += 1;
+                p_ch = at(pattern, p);
+                let negated = p_ch == b'!' || p_ch == b'^';
+                if negated {
+                    p += 1;
+                    p_ch = at(pattern, p);
+                }
+                let mut prev_ch = 0;
+                let mut matched = false;
+                loop {
+                    if p_ch == 0 {
+                        return Wild::AbortAll;
+                    }
+                    if p_ch == b'\\' {
+                        p += 1;
+                        p_ch = at(pattern, p);
+                        if p_ch == 0 {
+                            return Wild::AbortAll;
+                        }
+                        matched |= t_ch == p_ch;
+                    } else if p_ch == b'-'
+                        && prev_ch != 0
+                        && at(pattern, p + 1) != 0
+                        && at(pattern, p + 1) != b']'
+                    {
+                        p += 1;
+                        p_ch = at(pattern, p);
+                        if p_ch == b'\\' {
+                            p += 1;
+                            p_ch = at(pattern, p);
+                            if p_ch == 0 {
+                                return Wild::AbortAll;
+                            }
+                        }
+                        matched |= prev_ch <= t_ch && t_ch <= p_ch;
+                        p_ch = 0;
+                    } else if p_ch == b'[' && at(pattern, p + 1) == b':' {
+                        let start = p + 2;
+                        let end = match pattern[start..].iter().position(|&c| c == b']') {
+                            Some(i) => start + i,
+                            None => return Wild::AbortAll,
+                        };
+                        if end == start || pattern[end - 1] != b':' {
+                            // no `:]`, so the `[` is a plain character
+                            p = start - 2;
+                            p_ch = b'[';
+                            matched |= t_ch == p_ch;
+                        } else {
+                            p = end;
+                            matched |= match char_class(&pattern[start..end - 1], t_ch) {
+                                Some(matched) => matched,
+                                None => return Wild::AbortAll,
+                            };
+                            p_ch = 0;
+                        }
+                    } else {
+                        matched |= t_ch == p_ch;
+                    }
+                    prev_ch = p_ch;
+                    p += 1;
+                    p_ch = at(pattern, p);
+                    if p_ch == b']' {
+                        break;
+                    }
+                }
+                if matched == negated || (pathname && t_ch == b'/') {
+                    return Wild::NoMatch;
+                }
+            }
+            _ => {
+                if p_ch == b'\\' {
+                    // a literal match of the next byte
+                    p += 1;
+                    p_ch = at(pattern, p);
+                }
+                if t_ch != p_ch {
+                    return Wild::NoMatch;
+                }
+            }
+        }
+        p += 1;
+        t += 1;
+    }
+
+    if at(text, t) == 0 {
+        Wild::Match
+    } else {
+        Wild::NoMatch
+    }
+}
+
+/// whether `c` is in the class `[:name:]`, `None` for an unknown name
+fn char_class(name: &[u8], c: u8) -> Option<bool> {
+    Some(match name {
+        b"alnum" => c.is_ascii_alphanumeric(),
+        b"alpha" => c.is_ascii_alphabetic(),
+        b"blank" => c == b' ' || c == b'\t',
+        b"cntrl" => c.is_ascii_control(),
+        b"digit" => c.is_ascii_digit(),
+        b"graph" => c.is_ascii_graphic(),
+        b"lower" => c.is_ascii_lowercase(),
+        b"print" => c.is_ascii_graphic() || c == b' ',
+        b"punct" => c.is_ascii_punctuation(),
+        b"space" => c.is_ascii_whitespace() || c == b'\x0b',
+        b"upper" => c.is_ascii_uppercase(),
+        b"xdigit" => c.is_ascii_hexdigit(),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildmatch() {
+        // (pattern, text, match with pathname, match without), from git's t3070
+        let cases: &[(&str, &str, bool, bool)] = &[
+            ("foo", "foo", true, true),
+            ("bar", "foo", false, false),
+            ("???", "foo", true, true),
+            ("*f", "foo", false, false),
+            ("*", "foo", true, true),
+            ("*foo*", "foo", true, true),
+            ("*ob*a*r*", "foobar", true, true),
+            ("\\*", "*", true, true),
+            ("\\*", "foo", false, false),
+            ("[!a-z]", "a", false, false),
+            ("[a-z]", "a", true, true),
+            ("[]]", "]", true, true),
+            ("[a-]", "-", true, true),
+            ("[[:digit:][:upper:]]", "A", true, true),
+            ("[[:digit:]]", "a", false, false),
+            ("[[:nope:]]", "a", false, false),
+            ("[:digit:]", "1", false, false),
+            ("[\\]]", "]", true, true),
+            ("foo*bar", "foo/baz/bar", false, true),
+            ("foo**bar", "foo/baz/bar", false, true),
+            ("foo/**/bar", "foo/bar", true, false),
+            ("foo/**/bar", "foo/baz/qux/bar", true, true),
+            ("**/foo", "foo", true, false),
+            ("**/foo", "a/b/foo", true, true),
+            ("foo/**", "foo/a/b", true, true),
+            ("foo/**", "foo", false, false),
+            ("*/foo", "bar/foo", true, true),
+            ("*/foo", "a/bar/foo", false, true),
+            ("foo?bar", "foo/bar", false, true),
+            ("foo[/]bar", "foo/bar", false, true),
+            ("**/*.o", "a/b/c.o", true, true),
+            ("*.o", "a/b/c.o", false, true),
+            (
+                "-*-*-*-*-*-*-12-*-*-*-m-*-*-*",
+                "-adobe-courier-bold-o-normal--12-120-75-75-m-70-iso8859-1",
+                true,
+                true,
+            ),
+            (
+                "XXX/*/*/*/*/*/*/12/*/*/*/m/*/*/*",
+                "XXX/adobe/courier/bold/o/normal//12/120/75/75/X/70/iso8859/1",
+                false,
+                false,
+            ),
+            (
+                "**/*a*b*g*n*t",
+                "abcd/abcdefg/abcdefghijk/abcdefghijklmnop.txt",
+                true,
+                true,
+            ),
+            ("a[", "a[", false, false),
+        ];
+        for &(pattern, text, pathname, plain) in cases {
+            let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+            assert_eq!(
+                wildmatch(pattern, text, true),
+                pathname,
+                "{:?} {:?}",
+                pattern,
+                text
+            );
+            assert_eq!(
+                wildmatch(pattern, text, false),
+                plain,
+                "{:?} {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+}